@@ -0,0 +1,6 @@
+-- Create Issue Delivery Queue Table
+CREATE TABLE issue_delivery_queue (
+    newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues(id),
+    subscriber_email TEXT NOT NULL,
+    PRIMARY KEY (newsletter_issue_id, subscriber_email)
+);
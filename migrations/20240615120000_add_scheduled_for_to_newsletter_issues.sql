@@ -0,0 +1,4 @@
+ALTER TABLE newsletter_issues ADD COLUMN scheduled_for timestamptz NULL;
+CREATE INDEX newsletter_issues_scheduled_for_idx
+    ON newsletter_issues (scheduled_for)
+    WHERE status = 'scheduled';
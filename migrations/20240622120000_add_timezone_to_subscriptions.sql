@@ -0,0 +1 @@
+ALTER TABLE subscriptions ADD COLUMN timezone TEXT NULL;
@@ -0,0 +1,4 @@
+-- Wall-clock time at which each subscriber should receive the issue
+ALTER TABLE newsletter_issues ADD COLUMN deliver_at_local timestamp NULL;
+ALTER TABLE issue_delivery_queue ADD COLUMN execute_after timestamptz NOT NULL DEFAULT NOW();
+CREATE INDEX issue_delivery_queue_execute_after_idx ON issue_delivery_queue (execute_after);
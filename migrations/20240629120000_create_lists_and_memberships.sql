@@ -0,0 +1,42 @@
+-- Mailing lists, with every existing subscriber moved onto the default one
+CREATE TABLE lists (
+    id uuid NOT NULL PRIMARY KEY,
+    slug TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT NOW()
+);
+INSERT INTO lists (id, slug, name) VALUES (gen_random_uuid(), 'newsletter', 'Newsletter');
+
+CREATE TABLE list_memberships (
+    subscriber_id uuid NOT NULL REFERENCES subscriptions(id),
+    list_id uuid NOT NULL REFERENCES lists(id),
+    status TEXT NOT NULL,
+    subscribed_at timestamptz NOT NULL DEFAULT NOW(),
+    confirmed_at timestamptz NULL,
+    PRIMARY KEY (subscriber_id, list_id)
+);
+CREATE INDEX list_memberships_list_id_idx ON list_memberships (list_id);
+INSERT INTO list_memberships (subscriber_id, list_id, status, subscribed_at, confirmed_at)
+SELECT
+    s.id,
+    l.id,
+    s.status,
+    s.subscribed_at,
+    CASE WHEN s.status = 'confirmed' THEN s.subscribed_at END
+FROM subscriptions s, lists l
+WHERE l.slug = 'newsletter';
+
+ALTER TABLE subscription_tokens ADD COLUMN list_id uuid NULL REFERENCES lists(id);
+UPDATE subscription_tokens SET list_id = (SELECT id FROM lists WHERE slug = 'newsletter');
+ALTER TABLE subscription_tokens ALTER COLUMN list_id SET NOT NULL;
+
+-- Lists each newsletter issue is delivered to
+CREATE TABLE newsletter_issue_lists (
+    newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues(id),
+    list_id uuid NOT NULL REFERENCES lists(id),
+    PRIMARY KEY (newsletter_issue_id, list_id)
+);
+INSERT INTO newsletter_issue_lists (newsletter_issue_id, list_id)
+SELECT i.id, l.id
+FROM newsletter_issues i, lists l
+WHERE l.slug = 'newsletter';
@@ -0,0 +1,13 @@
+-- Free-form labels attached to subscribers
+CREATE TABLE subscriber_tags (
+    subscriber_id uuid NOT NULL REFERENCES subscriptions(id),
+    tag TEXT NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (subscriber_id, tag)
+);
+CREATE INDEX subscriber_tags_tag_idx ON subscriber_tags (tag);
+
+ALTER TABLE subscriptions ADD COLUMN custom_fields jsonb NOT NULL DEFAULT '{}';
+
+-- Segment filter restricting which list members receive an issue
+ALTER TABLE newsletter_issues ADD COLUMN segment jsonb NULL;
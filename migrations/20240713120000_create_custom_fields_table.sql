@@ -0,0 +1,9 @@
+-- Typed profile fields admins can define for subscribers
+CREATE TABLE custom_fields (
+    key TEXT NOT NULL PRIMARY KEY,
+    label TEXT NOT NULL,
+    field_type TEXT NOT NULL CHECK (field_type IN ('string', 'number', 'date', 'enum')),
+    options TEXT[] NOT NULL DEFAULT '{}',
+    required BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at timestamptz NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,8 @@
+CREATE TABLE email_change_requests (
+    token TEXT PRIMARY KEY,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions(id),
+    new_email TEXT NOT NULL,
+    requested_at timestamptz NOT NULL DEFAULT now(),
+    expires_at timestamptz NOT NULL
+);
+CREATE INDEX email_change_requests_subscriber_id ON email_change_requests (subscriber_id);
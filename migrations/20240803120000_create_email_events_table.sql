@@ -0,0 +1,11 @@
+-- One row per delivery attempt, kept after the queue entry is gone so that
+-- a subscriber's delivery history can be reported.
+CREATE TABLE email_events (
+    id uuid PRIMARY KEY DEFAULT gen_random_uuid(),
+    subscriber_id uuid NOT NULL REFERENCES subscriptions(id),
+    newsletter_issue_id uuid REFERENCES newsletter_issues(id),
+    event_type TEXT NOT NULL CHECK (event_type IN ('delivered', 'failed')),
+    detail TEXT,
+    occurred_at timestamptz NOT NULL DEFAULT now()
+);
+CREATE INDEX email_events_subscriber_id ON email_events (subscriber_id);
@@ -0,0 +1,29 @@
+-- Deleting a subscriber removes everything that refers to them. Delivery
+-- events are kept without the subscriber so that issue statistics survive.
+ALTER TABLE subscription_tokens
+    DROP CONSTRAINT subscription_tokens_subscription_id_fkey,
+    ADD CONSTRAINT subscription_tokens_subscription_id_fkey
+        FOREIGN KEY (subscription_id) REFERENCES subscriptions(id) ON DELETE CASCADE;
+ALTER TABLE list_memberships
+    DROP CONSTRAINT list_memberships_subscriber_id_fkey,
+    ADD CONSTRAINT list_memberships_subscriber_id_fkey
+        FOREIGN KEY (subscriber_id) REFERENCES subscriptions(id) ON DELETE CASCADE;
+ALTER TABLE subscriber_tags
+    DROP CONSTRAINT subscriber_tags_subscriber_id_fkey,
+    ADD CONSTRAINT subscriber_tags_subscriber_id_fkey
+        FOREIGN KEY (subscriber_id) REFERENCES subscriptions(id) ON DELETE CASCADE;
+ALTER TABLE email_change_requests
+    DROP CONSTRAINT email_change_requests_subscriber_id_fkey,
+    ADD CONSTRAINT email_change_requests_subscriber_id_fkey
+        FOREIGN KEY (subscriber_id) REFERENCES subscriptions(id) ON DELETE CASCADE;
+ALTER TABLE email_events ALTER COLUMN subscriber_id DROP NOT NULL;
+ALTER TABLE email_events
+    DROP CONSTRAINT email_events_subscriber_id_fkey,
+    ADD CONSTRAINT email_events_subscriber_id_fkey
+        FOREIGN KEY (subscriber_id) REFERENCES subscriptions(id) ON DELETE SET NULL;
+
+-- SHA-256 of the lowercased address of every erased subscriber.
+CREATE TABLE suppressed_emails (
+    email_hash TEXT PRIMARY KEY,
+    suppressed_at timestamptz NOT NULL DEFAULT now()
+);
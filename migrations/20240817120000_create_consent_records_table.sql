@@ -0,0 +1,14 @@
+-- Proof of consent: how and when each list signup was made and confirmed.
+CREATE TABLE consent_records (
+    id uuid PRIMARY KEY DEFAULT gen_random_uuid(),
+    subscriber_id uuid NOT NULL REFERENCES subscriptions(id) ON DELETE CASCADE,
+    list_id uuid NOT NULL REFERENCES lists(id),
+    source_ip TEXT,
+    user_agent TEXT,
+    form_id TEXT,
+    consent_text_version TEXT,
+    consented_at timestamptz NOT NULL DEFAULT now(),
+    confirmation_ip TEXT,
+    confirmed_at timestamptz
+);
+CREATE INDEX consent_records_subscriber_id ON consent_records (subscriber_id);
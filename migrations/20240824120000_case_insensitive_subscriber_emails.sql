@@ -0,0 +1,39 @@
+-- Email addresses are unique regardless of case.
+ALTER TABLE subscriptions DROP CONSTRAINT subscriptions_email_key;
+
+-- Bring existing addresses in line with SubscriberEmail, which lowercases
+-- the domain. Internationalized domains are left for the application.
+UPDATE subscriptions
+SET email = substring(email FROM '^(.*)@[^@]*$') || '@' || lower(substring(email FROM '@([^@]*)$'))
+WHERE substring(email FROM '@([^@]*)$') <> lower(substring(email FROM '@([^@]*)$'));
+
+-- Subscribers whose address already collides with an older subscriber's
+-- point at it, and stay out of the uniqueness check until an admin merges
+-- or erases them.
+ALTER TABLE subscriptions ADD COLUMN duplicate_of uuid NULL;
+
+UPDATE subscriptions AS s
+SET duplicate_of = ranked.kept_id
+FROM (
+    SELECT
+        id,
+        first_value(id) OVER w AS kept_id,
+        row_number() OVER w AS rank
+    FROM subscriptions
+    WINDOW w AS (PARTITION BY lower(email) ORDER BY subscribed_at, id)
+) AS ranked
+WHERE s.id = ranked.id AND ranked.rank > 1;
+
+CREATE UNIQUE INDEX subscriptions_email_lower_key
+    ON subscriptions (lower(email))
+    WHERE duplicate_of IS NULL;
+
+DO $$
+DECLARE
+    duplicates bigint;
+BEGIN
+    SELECT count(*) INTO duplicates FROM subscriptions WHERE duplicate_of IS NOT NULL;
+    IF duplicates > 0 THEN
+        RAISE WARNING '% subscriptions share their email address with an older one, see subscriptions.duplicate_of.', duplicates;
+    END IF;
+END $$;
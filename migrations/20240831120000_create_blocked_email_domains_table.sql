@@ -0,0 +1,5 @@
+-- Disposable domains added by admins to the ones bundled with the app.
+CREATE TABLE blocked_email_domains (
+    domain TEXT NOT NULL PRIMARY KEY,
+    added_at timestamptz NOT NULL DEFAULT now()
+);
@@ -0,0 +1,8 @@
+-- Requests counted by the Postgres rate limit store, shared by all replicas.
+-- Unlogged: losing the counts in a crash only resets the limits.
+CREATE UNLOGGED TABLE rate_limit_hits (
+    key TEXT NOT NULL,
+    expires_at timestamptz NOT NULL
+);
+CREATE INDEX rate_limit_hits_key_idx ON rate_limit_hits (key, expires_at);
+CREATE INDEX rate_limit_hits_expires_at_idx ON rate_limit_hits (expires_at);
@@ -0,0 +1,4 @@
+-- Suppressed addresses are hashed with the application secret from now on.
+-- Existing unkeyed hashes are rekeyed by the application when it starts, as
+-- the secret is not available here.
+ALTER TABLE suppressed_emails ADD COLUMN keyed BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,2 @@
+-- Why a scheduled issue could not be published, for issues in the 'failed' status
+ALTER TABLE newsletter_issues ADD COLUMN failure_reason TEXT NULL;
@@ -0,0 +1,98 @@
+use std::{
+    future::{ready, Future, Ready},
+    pin::Pin,
+    rc::Rc,
+    sync::Arc,
+};
+
+use actix_web::{
+    body::EitherBody,
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header,
+    ResponseError,
+};
+use secrecy::{ExposeSecret, Secret};
+use subtle::ConstantTimeEq;
+
+use crate::error::AppError;
+
+/// Admits requests to the `/admin` endpoints only if they carry the admin
+/// token as a bearer token, and answers the others with `401 Unauthorized`.
+#[derive(Clone)]
+pub struct AdminAuth {
+    token: Arc<Secret<String>>,
+}
+
+impl AdminAuth {
+    pub fn new(token: Secret<String>) -> Self {
+        Self {
+            token: Arc::new(token),
+        }
+    }
+
+    /// Compares in constant time, so that the token cannot be guessed one
+    /// byte at a time from response times.
+    fn admits(&self, request: &ServiceRequest) -> bool {
+        let Some(credentials) = request
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+        else {
+            return false;
+        };
+        let expected = self.token.expose_secret();
+        !expected.is_empty() && bool::from(credentials.as_bytes().ct_eq(expected.as_bytes()))
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for AdminAuth
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Transform = AdminAuthMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(AdminAuthMiddleware {
+            service: Rc::new(service),
+            auth: self.clone(),
+        }))
+    }
+}
+
+pub struct AdminAuthMiddleware<S> {
+    service: Rc<S>,
+    auth: AdminAuth,
+}
+
+impl<S, B> Service<ServiceRequest> for AdminAuthMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, request: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let admitted = self.auth.admits(&request);
+        Box::pin(async move {
+            if !admitted {
+                let response = AppError::Unauthorized.error_response();
+                return Ok(request.into_response(response).map_into_right_body());
+            }
+            service
+                .call(request)
+                .await
+                .map(ServiceResponse::map_into_left_body)
+        })
+    }
+}
@@ -0,0 +1,396 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, Utc};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::rate_limit::{Decision, RateLimit, RateLimitStore};
+use crate::signed_links::{LinkPurpose, SignedLinks};
+
+/// A form field hidden from people, which only bots fill in.
+pub const HONEYPOT_FIELD: &str = "website";
+const FORM_TOKEN_TTL_HOURS: i64 = 24;
+
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub struct VerificationError(pub String);
+
+/// Verifies that a signup was made by a person, with a CAPTCHA service or a
+/// proof of work. `challenge` is the form token the signup was made with.
+#[async_trait::async_trait]
+pub trait HumanVerifier: Send + Sync {
+    async fn verify(
+        &self,
+        challenge: &str,
+        response: &str,
+        remote_ip: Option<&str>,
+    ) -> Result<bool, VerificationError>;
+}
+
+/// Requires a nonce such that the SHA-256 of `<challenge>:<nonce>` starts
+/// with `difficulty` zero bits, which takes a browser a moment to find.
+pub struct ProofOfWork {
+    difficulty: u32,
+}
+
+impl ProofOfWork {
+    pub fn new(difficulty: u32) -> Self {
+        Self { difficulty }
+    }
+
+    pub fn solve(&self, challenge: &str) -> String {
+        (0u64..)
+            .map(|nonce| nonce.to_string())
+            .find(|nonce| self.is_solution(challenge, nonce))
+            .expect("A nonce is found before running out of u64s.")
+    }
+
+    fn is_solution(&self, challenge: &str, nonce: &str) -> bool {
+        let hash = Sha256::digest(format!("{challenge}:{nonce}").as_bytes());
+        let mut zero_bits = 0;
+        for byte in hash {
+            zero_bits += byte.leading_zeros();
+            if byte != 0 {
+                break;
+            }
+        }
+        zero_bits >= self.difficulty
+    }
+}
+
+#[async_trait::async_trait]
+impl HumanVerifier for ProofOfWork {
+    async fn verify(
+        &self,
+        challenge: &str,
+        response: &str,
+        _remote_ip: Option<&str>,
+    ) -> Result<bool, VerificationError> {
+        Ok(self.is_solution(challenge, response))
+    }
+}
+
+/// Why a signup was taken for a bot's.
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum BotDetected {
+    #[error("The submission was rejected.")]
+    Honeypot,
+    #[error("The form token is missing, please reload the form.")]
+    MissingFormToken,
+    #[error("The form token is invalid or has expired, please reload the form.")]
+    InvalidFormToken,
+    #[error("The form token has already been used, please reload the form.")]
+    ReusedFormToken,
+    #[error("The form was submitted too quickly, please try again.")]
+    TooFast,
+    #[error("The verification failed, please try again.")]
+    VerificationFailed,
+}
+
+impl BotDetected {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Honeypot => "honeypot",
+            Self::MissingFormToken => "missing_form_token",
+            Self::InvalidFormToken => "invalid_form_token",
+            Self::ReusedFormToken => "reused_form_token",
+            Self::TooFast => "too_fast",
+            Self::VerificationFailed => "verification_failed",
+        }
+    }
+}
+
+/// What a signup carries to prove it was not made by a bot.
+#[derive(Default)]
+pub struct Submission<'a> {
+    pub honeypot: Option<&'a str>,
+    pub form_token: Option<&'a str>,
+    pub verification: Option<&'a str>,
+    pub remote_ip: Option<&'a str>,
+}
+
+/// Screens signups for bots: a filled-in honeypot, a form submitted sooner
+/// after it was served than a person could fill it in, a form token used
+/// before and, when configured, a failed human verification.
+#[derive(Clone)]
+pub struct BotProtection {
+    signed_links: SignedLinks,
+    require_form_token: bool,
+    min_fill_time: Duration,
+    verifier: Option<Arc<dyn HumanVerifier>>,
+    /// Remembers the nonces of accepted form tokens until they expire.
+    used_tokens: Arc<dyn RateLimitStore>,
+}
+
+impl BotProtection {
+    pub fn new(
+        signed_links: SignedLinks,
+        require_form_token: bool,
+        min_fill_time: Duration,
+        verifier: Option<Arc<dyn HumanVerifier>>,
+        used_tokens: Arc<dyn RateLimitStore>,
+    ) -> Self {
+        Self {
+            signed_links,
+            require_form_token,
+            min_fill_time,
+            verifier,
+            used_tokens,
+        }
+    }
+
+    pub fn with_verifier(self, verifier: Arc<dyn HumanVerifier>) -> Self {
+        Self {
+            verifier: Some(verifier),
+            ..self
+        }
+    }
+
+    /// A token signing when the form was served, to be submitted with it.
+    pub fn form_token(&self) -> String {
+        let expires_at = Utc::now() + Duration::hours(FORM_TOKEN_TTL_HOURS);
+        self.signed_links
+            .sign(LinkPurpose::SignupForm, Uuid::new_v4(), Some(expires_at))
+    }
+
+    /// Checks everything but whether the form token was used before, which
+    /// `consume` does once the rest of the signup is valid too, so that a
+    /// person can correct a mistake and submit the same form again.
+    #[tracing::instrument(name = "Screening a signup for bots.", skip_all)]
+    pub async fn verify(&self, submission: &Submission<'_>) -> Result<Verified, BotDetected> {
+        if submission.honeypot.is_some_and(|value| !value.is_empty()) {
+            return Err(BotDetected::Honeypot);
+        }
+
+        // A verifier needs the token as its challenge.
+        let form_token = match submission.form_token.filter(|token| !token.is_empty()) {
+            Some(token) => Some(token),
+            None if self.require_form_token || self.verifier.is_some() => {
+                return Err(BotDetected::MissingFormToken)
+            }
+            None => None,
+        };
+        let mut verified = Verified { nonce: None };
+        if let Some(form_token) = form_token {
+            let (nonce, expires_at) = self
+                .signed_links
+                .verify_with_expiry(LinkPurpose::SignupForm, form_token)
+                .map_err(|_| BotDetected::InvalidFormToken)?;
+            let expires_at = expires_at.ok_or(BotDetected::InvalidFormToken)?;
+            let served_at = expires_at - Duration::hours(FORM_TOKEN_TTL_HOURS);
+            if Utc::now() - served_at < self.min_fill_time {
+                return Err(BotDetected::TooFast);
+            }
+            verified.nonce = Some((nonce, expires_at));
+        }
+
+        if let (Some(verifier), Some(form_token)) = (&self.verifier, form_token) {
+            let response = submission.verification.unwrap_or_default();
+            let is_human = verifier
+                .verify(form_token, response, submission.remote_ip)
+                .await
+                .unwrap_or_else(|e| {
+                    tracing::error!("Failed to verify a signup: {}", e);
+                    false
+                });
+            if !is_human {
+                return Err(BotDetected::VerificationFailed);
+            }
+        }
+        Ok(verified)
+    }
+
+    /// Uses up the form token of a verified submission.
+    #[tracing::instrument(name = "Using up a form token.", skip_all)]
+    pub async fn consume(&self, verified: Verified) -> Result<(), BotDetected> {
+        let Some((nonce, expires_at)) = verified.nonce else {
+            return Ok(());
+        };
+        let once = RateLimit {
+            max_requests: 1,
+            window: (expires_at - Utc::now()).to_std().unwrap_or_default(),
+        };
+        match self
+            .used_tokens
+            .hit(&format!("form_token:{nonce}"), &once)
+            .await
+        {
+            Ok(Decision::Allowed) => Ok(()),
+            Ok(Decision::Limited { .. }) => Err(BotDetected::ReusedFormToken),
+            // Like the rate limiter, an outage of the store must not take
+            // signups down with it.
+            Err(e) => {
+                tracing::error!("Failed to record a used form token: {}", e);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A submission that passed `BotProtection::verify`, whose form token is yet
+/// to be used up.
+#[must_use]
+pub struct Verified {
+    nonce: Option<(Uuid, DateTime<Utc>)>,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::{Duration, Utc};
+    use claims::{assert_err_eq, assert_ok};
+    use uuid::Uuid;
+
+    use super::{BotDetected, BotProtection, ProofOfWork, Submission, FORM_TOKEN_TTL_HOURS};
+    use crate::rate_limit::InMemoryStore;
+    use crate::signed_links::{LinkPurpose, SignedLinks};
+
+    fn signed_links() -> SignedLinks {
+        SignedLinks::new("http://localhost".to_string(), "key".to_string().into())
+    }
+
+    fn protection_with(require_form_token: bool, min_fill_time: Duration) -> BotProtection {
+        BotProtection::new(
+            signed_links(),
+            require_form_token,
+            min_fill_time,
+            None,
+            Arc::new(InMemoryStore::default()),
+        )
+    }
+
+    fn protection(require_form_token: bool) -> BotProtection {
+        protection_with(require_form_token, Duration::seconds(3))
+    }
+
+    /// A token for a form served `age` ago.
+    fn token_served(age: Duration) -> String {
+        let expires_at = Utc::now() - age + Duration::hours(FORM_TOKEN_TTL_HOURS);
+        signed_links().sign(LinkPurpose::SignupForm, Uuid::new_v4(), Some(expires_at))
+    }
+
+    async fn check(
+        protection: &BotProtection,
+        submission: &Submission<'_>,
+    ) -> Result<(), BotDetected> {
+        protection
+            .consume(protection.verify(submission).await?)
+            .await
+    }
+
+    fn with_token(token: &str) -> Submission<'_> {
+        Submission {
+            form_token: Some(token),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn a_filled_in_honeypot_is_rejected() {
+        let submission = Submission {
+            honeypot: Some("https://spam.example"),
+            ..Default::default()
+        };
+        assert_err_eq!(
+            check(&protection(false), &submission).await,
+            BotDetected::Honeypot
+        );
+        assert_ok!(check(&protection(false), &Submission::default()).await);
+    }
+
+    #[tokio::test]
+    async fn the_form_token_is_only_required_when_configured() {
+        assert_ok!(check(&protection(false), &Submission::default()).await);
+        assert_err_eq!(
+            check(&protection(true), &Submission::default()).await,
+            BotDetected::MissingFormToken
+        );
+    }
+
+    #[tokio::test]
+    async fn forms_submitted_too_quickly_are_rejected() {
+        let token = token_served(Duration::seconds(1));
+        assert_err_eq!(
+            check(&protection(true), &with_token(&token)).await,
+            BotDetected::TooFast
+        );
+
+        let token = token_served(Duration::seconds(5));
+        assert_ok!(check(&protection(true), &with_token(&token)).await);
+    }
+
+    #[tokio::test]
+    async fn invalid_and_expired_form_tokens_are_rejected() {
+        let expired = token_served(Duration::hours(FORM_TOKEN_TTL_HOURS + 1));
+        let unsubscribe = signed_links().sign(LinkPurpose::Unsubscribe, Uuid::new_v4(), None);
+        for token in [expired.as_str(), unsubscribe.as_str(), "not-a-token"] {
+            assert_err_eq!(
+                check(&protection(false), &with_token(token)).await,
+                BotDetected::InvalidFormToken
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn served_form_tokens_are_accepted_after_the_minimum_fill_time() {
+        let protection = protection_with(true, Duration::zero());
+        let token = protection.form_token();
+        assert_ok!(check(&protection, &with_token(&token)).await);
+    }
+
+    #[tokio::test]
+    async fn form_tokens_can_only_be_used_once() {
+        let protection = protection_with(true, Duration::zero());
+        let token = protection.form_token();
+        assert_ok!(check(&protection, &with_token(&token)).await);
+        assert_err_eq!(
+            check(&protection, &with_token(&token)).await,
+            BotDetected::ReusedFormToken
+        );
+        assert_ok!(check(&protection, &with_token(&protection.form_token())).await);
+    }
+
+    #[tokio::test]
+    async fn verifying_does_not_use_up_the_form_token() {
+        let protection = protection_with(true, Duration::zero());
+        let token = protection.form_token();
+        let _ = assert_ok!(protection.verify(&with_token(&token)).await);
+        assert_ok!(check(&protection, &with_token(&token)).await);
+        assert_err_eq!(
+            check(&protection, &with_token(&token)).await,
+            BotDetected::ReusedFormToken
+        );
+    }
+
+    #[tokio::test]
+    async fn the_verifier_must_accept_the_submission() {
+        let proof_of_work = Arc::new(ProofOfWork::new(8));
+        let protection =
+            protection_with(false, Duration::zero()).with_verifier(proof_of_work.clone());
+        let token = protection.form_token();
+
+        let nonce = proof_of_work.solve(&token);
+        let solved = Submission {
+            verification: Some(&nonce),
+            ..with_token(&token)
+        };
+        assert_ok!(check(&protection, &solved).await);
+        assert_err_eq!(
+            check(&protection, &with_token(&token)).await,
+            BotDetected::VerificationFailed
+        );
+        assert_err_eq!(
+            check(&protection, &Submission::default()).await,
+            BotDetected::MissingFormToken
+        );
+    }
+
+    #[test]
+    fn proof_of_work_solutions_are_bound_to_their_challenge() {
+        let proof_of_work = ProofOfWork::new(12);
+        let nonce = proof_of_work.solve("challenge");
+        assert!(proof_of_work.is_solution("challenge", &nonce));
+        assert!(!proof_of_work.is_solution("other challenge", &nonce));
+    }
+}
@@ -1,3 +1,5 @@
+use std::net::IpAddr;
+
 use secrecy::Secret;
 use serde_aux::field_attributes::deserialize_number_from_string;
 #[derive(serde::Deserialize)]
@@ -5,6 +7,12 @@ pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
+    #[serde(default)]
+    pub email_policy: EmailPolicySettings,
+    #[serde(default)]
+    pub bot_protection: BotProtectionSettings,
+    #[serde(default)]
+    pub rate_limit: RateLimitSettings,
 }
 
 #[derive(serde::Deserialize)]
@@ -13,6 +21,9 @@ pub struct ApplicationSettings {
     pub port: u16,
     pub host: String,
     pub base_url: String,
+    pub hmac_secret: Secret<String>,
+    /// The bearer token of requests to the `/admin` endpoints.
+    pub admin_token: Secret<String>,
 }
 
 #[derive(serde::Deserialize)]
@@ -26,6 +37,102 @@ pub struct DatabaseSettings {
     pub require_ssl: bool,
 }
 
+/// Which addresses may subscribe, beyond being well-formed. Disposable
+/// domains are always rejected.
+#[derive(serde::Deserialize)]
+#[serde(default)]
+pub struct EmailPolicySettings {
+    pub reject_role_accounts: bool,
+    /// Whether the domain must have MX (or A) records.
+    pub check_mx: bool,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub mx_timeout_millis: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub mx_cache_ttl_secs: u64,
+}
+
+impl Default for EmailPolicySettings {
+    fn default() -> Self {
+        Self {
+            reject_role_accounts: false,
+            check_mx: false,
+            mx_timeout_millis: 2000,
+            mx_cache_ttl_secs: 3600,
+        }
+    }
+}
+
+/// How signups are screened for bots. A honeypot field is always checked.
+#[derive(serde::Deserialize)]
+#[serde(default)]
+pub struct BotProtectionSettings {
+    /// Whether signups must carry a token from `/subscriptions/form-token`.
+    pub require_form_token: bool,
+    /// How long after the token was issued a form can be submitted.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub min_fill_secs: u64,
+    /// Requires a proof of work with this many leading zero bits.
+    pub proof_of_work_bits: Option<u32>,
+}
+
+impl Default for BotProtectionSettings {
+    fn default() -> Self {
+        Self {
+            require_form_token: false,
+            min_fill_secs: 3,
+            proof_of_work_bits: None,
+        }
+    }
+}
+
+/// Limits on requests to the public `/subscriptions` endpoints.
+#[derive(serde::Deserialize)]
+#[serde(default)]
+pub struct RateLimitSettings {
+    pub enabled: bool,
+    pub store: RateLimitStoreKind,
+    /// Proxies whose `X-Forwarded-For` entries are trusted.
+    pub trusted_proxies: Vec<IpAddr>,
+    pub per_ip: LimitSettings,
+    /// Applies to the endpoints that send mail to the address they are given.
+    pub per_email: LimitSettings,
+}
+
+impl Default for RateLimitSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            store: RateLimitStoreKind::Memory,
+            trusted_proxies: Vec::new(),
+            per_ip: LimitSettings {
+                max_requests: 30,
+                window_secs: 60,
+            },
+            per_email: LimitSettings {
+                max_requests: 5,
+                window_secs: 3600,
+            },
+        }
+    }
+}
+
+/// Where request counts are kept: in each replica's memory, or in Postgres
+/// to share them between replicas.
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RateLimitStoreKind {
+    Memory,
+    Postgres,
+}
+
+#[derive(serde::Deserialize, Clone, Copy)]
+pub struct LimitSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_requests: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub window_secs: u64,
+}
+
 pub fn get_configuration() -> Settings {
     let configuration_dir = std::env::current_dir()
         .expect("Failed to determine the current directory.")
@@ -87,4 +194,7 @@ pub struct EmailClientSettings {
     pub authorization_token: Secret<String>,
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub timeout_millis: u64,
+    /// Whether the provider can deliver to internationalized addresses.
+    #[serde(default)]
+    pub smtputf8: bool,
 }
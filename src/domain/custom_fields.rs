@@ -0,0 +1,314 @@
+use chrono::NaiveDate;
+use serde_json::{Map, Number, Value};
+
+const RESERVED_KEYS: [&str; 3] = ["id", "name", "email"];
+const MAX_STRING_LENGTH: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CustomFieldType {
+    String,
+    Number,
+    Date,
+    Enum,
+}
+
+impl CustomFieldType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::String => "string",
+            Self::Number => "number",
+            Self::Date => "date",
+            Self::Enum => "enum",
+        }
+    }
+
+    pub fn parse(field_type: &str) -> Result<Self, String> {
+        match field_type {
+            "string" => Ok(Self::String),
+            "number" => Ok(Self::Number),
+            "date" => Ok(Self::Date),
+            "enum" => Ok(Self::Enum),
+            other => Err(format!("{} is not a custom field type.", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct CustomFieldDefinition {
+    pub key: String,
+    pub label: String,
+    #[serde(rename = "type")]
+    pub field_type: CustomFieldType,
+    pub options: Vec<String>,
+    pub required: bool,
+}
+
+impl CustomFieldDefinition {
+    pub fn parse(
+        key: String,
+        label: String,
+        field_type: CustomFieldType,
+        options: Vec<String>,
+        required: bool,
+    ) -> Result<Self, String> {
+        let is_valid_key = key.len() <= 64
+            && key.starts_with(|c: char| c.is_ascii_lowercase())
+            && key
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+        if !is_valid_key {
+            return Err(format!(
+                "{} is not a valid field key. Use up to 64 lowercase letters, digits and underscores, starting with a letter.",
+                key
+            ));
+        }
+        if RESERVED_KEYS.contains(&key.as_str()) {
+            return Err(format!("{} is a reserved field key.", key));
+        }
+        if label.trim().is_empty() {
+            return Err("Field label cannot be empty.".to_string());
+        }
+
+        let options = options
+            .into_iter()
+            .map(|option| option.trim().to_string())
+            .collect::<Vec<_>>();
+        match field_type {
+            CustomFieldType::Enum if options.is_empty() => {
+                return Err("Enum fields need at least one option.".to_string());
+            }
+            CustomFieldType::Enum
+                if options.iter().any(String::is_empty)
+                    || (1..options.len()).any(|i| options[..i].contains(&options[i])) =>
+            {
+                return Err("Enum options must be unique and non-empty.".to_string());
+            }
+            CustomFieldType::Enum => {}
+            _ if !options.is_empty() => {
+                return Err("Only enum fields can have options.".to_string());
+            }
+            _ => {}
+        }
+
+        Ok(Self {
+            key,
+            label: label.trim().to_string(),
+            field_type,
+            options,
+            required,
+        })
+    }
+
+    /// Validates a submitted value and returns it in its stored JSON form.
+    /// Values coming from HTML forms are always strings, so numbers are also
+    /// accepted in their textual form.
+    fn parse_value(&self, value: Value) -> Result<Value, String> {
+        let invalid = || format!("Invalid value for {}.", self.label);
+        match (self.field_type, value) {
+            (CustomFieldType::String, Value::String(value)) => {
+                if value.chars().count() > MAX_STRING_LENGTH {
+                    return Err(format!(
+                        "{} cannot be longer than {} characters.",
+                        self.label, MAX_STRING_LENGTH
+                    ));
+                }
+                Ok(Value::String(value))
+            }
+            (CustomFieldType::Number, Value::Number(number)) => Ok(Value::Number(number)),
+            (CustomFieldType::Number, Value::String(value)) => {
+                let value = value.trim();
+                if let Ok(integer) = value.parse::<i64>() {
+                    return Ok(Value::Number(integer.into()));
+                }
+                value
+                    .parse::<f64>()
+                    .ok()
+                    .and_then(Number::from_f64)
+                    .map(Value::Number)
+                    .ok_or_else(invalid)
+            }
+            (CustomFieldType::Date, Value::String(value)) => {
+                NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d")
+                    .map(|date| Value::String(date.format("%Y-%m-%d").to_string()))
+                    .map_err(|_| format!("{} must be a date formatted as YYYY-MM-DD.", self.label))
+            }
+            (CustomFieldType::Enum, Value::String(value)) => {
+                if self.options.contains(&value) {
+                    Ok(Value::String(value))
+                } else {
+                    Err(format!(
+                        "{} must be one of: {}.",
+                        self.label,
+                        self.options.join(", ")
+                    ))
+                }
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// Custom profile values of a subscriber, validated against the field
+/// definitions and stored as a JSON object keyed by field key.
+#[derive(Debug, Clone, Default)]
+pub struct CustomFields(Map<String, Value>);
+
+impl CustomFields {
+    pub fn parse(
+        definitions: &[CustomFieldDefinition],
+        values: Map<String, Value>,
+    ) -> Result<Self, String> {
+        let mut fields = Map::new();
+        for (key, value) in values {
+            let definition = definitions
+                .iter()
+                .find(|definition| definition.key == key)
+                .ok_or_else(|| format!("{} is not a known field.", key))?;
+            let is_blank = match &value {
+                Value::Null => true,
+                Value::String(value) => value.trim().is_empty(),
+                _ => false,
+            };
+            if !is_blank {
+                fields.insert(key, definition.parse_value(value)?);
+            }
+        }
+
+        if let Some(missing) = definitions
+            .iter()
+            .find(|definition| definition.required && !fields.contains_key(&definition.key))
+        {
+            return Err(format!("{} is required.", missing.label));
+        }
+
+        Ok(Self(fields))
+    }
+
+    pub fn as_json(&self) -> Value {
+        Value::Object(self.0.clone())
+    }
+}
+
+/// Renders a stored custom field value for use in an email template.
+pub fn custom_field_to_template_value(value: &Value) -> String {
+    match value {
+        Value::String(value) => value.clone(),
+        Value::Null => String::new(),
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CustomFieldDefinition, CustomFieldType, CustomFields};
+    use claims::{assert_err, assert_ok};
+    use serde_json::{json, Map, Value};
+
+    fn definitions() -> Vec<CustomFieldDefinition> {
+        vec![
+            CustomFieldDefinition::parse(
+                "company".into(),
+                "Company".into(),
+                CustomFieldType::String,
+                vec![],
+                false,
+            )
+            .unwrap(),
+            CustomFieldDefinition::parse(
+                "seats".into(),
+                "Seats".into(),
+                CustomFieldType::Number,
+                vec![],
+                false,
+            )
+            .unwrap(),
+            CustomFieldDefinition::parse(
+                "birthday".into(),
+                "Birthday".into(),
+                CustomFieldType::Date,
+                vec![],
+                false,
+            )
+            .unwrap(),
+            CustomFieldDefinition::parse(
+                "plan".into(),
+                "Plan".into(),
+                CustomFieldType::Enum,
+                vec!["free".into(), "pro".into()],
+                true,
+            )
+            .unwrap(),
+        ]
+    }
+
+    fn values(values: Value) -> Map<String, Value> {
+        values.as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn valid_values_are_stored_in_their_typed_form() {
+        let fields = assert_ok!(CustomFields::parse(
+            &definitions(),
+            values(json!({
+                "company": "Acme",
+                "seats": "12",
+                "birthday": "1990-01-31",
+                "plan": "pro"
+            }))
+        ));
+        assert_eq!(
+            fields.as_json(),
+            json!({ "company": "Acme", "seats": 12, "birthday": "1990-01-31", "plan": "pro" })
+        );
+    }
+
+    #[test]
+    fn blank_optional_values_are_dropped() {
+        let fields = assert_ok!(CustomFields::parse(
+            &definitions(),
+            values(json!({ "company": " ", "seats": null, "plan": "free" }))
+        ));
+        assert_eq!(fields.as_json(), json!({ "plan": "free" }));
+    }
+
+    #[test]
+    fn invalid_values_are_rejected() {
+        let cases = [
+            json!({ "plan": "enterprise" }),
+            json!({ "plan": "pro", "seats": "a dozen" }),
+            json!({ "plan": "pro", "birthday": "31/01/1990" }),
+            json!({ "plan": "pro", "company": 42 }),
+            json!({ "plan": "pro", "unknown": "value" }),
+            json!({ "company": "Acme" }),
+        ];
+        for case in cases {
+            assert_err!(
+                CustomFields::parse(&definitions(), values(case.clone())),
+                "{} was accepted",
+                case
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_definitions_are_rejected() {
+        let cases = [
+            ("Company", CustomFieldType::String, vec![]),
+            ("name", CustomFieldType::String, vec![]),
+            ("1st", CustomFieldType::String, vec![]),
+            ("plan", CustomFieldType::Enum, vec![]),
+            ("plan", CustomFieldType::Enum, vec!["a".into(), "a".into()]),
+            ("seats", CustomFieldType::Number, vec!["1".into()]),
+        ];
+        for (key, field_type, options) in cases {
+            assert_err!(CustomFieldDefinition::parse(
+                key.into(),
+                "Label".into(),
+                field_type,
+                options,
+                false
+            ));
+        }
+    }
+}
@@ -0,0 +1,47 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DigestFrequency {
+    Immediate,
+    Daily,
+    Weekly,
+}
+
+impl DigestFrequency {
+    pub const ALL: [DigestFrequency; 3] = [Self::Immediate, Self::Daily, Self::Weekly];
+
+    pub fn parse(frequency: &str) -> Result<Self, String> {
+        Self::ALL
+            .into_iter()
+            .find(|candidate| candidate.as_ref() == frequency)
+            .ok_or_else(|| format!("{} is not a valid digest frequency.", frequency))
+    }
+}
+
+impl AsRef<str> for DigestFrequency {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::Immediate => "immediate",
+            Self::Daily => "daily",
+            Self::Weekly => "weekly",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DigestFrequency;
+    use claims::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn parse_given_known_frequency_returns_it() {
+        for frequency in DigestFrequency::ALL {
+            assert_ok_eq!(DigestFrequency::parse(frequency.as_ref()), frequency);
+        }
+    }
+
+    #[test]
+    fn parse_given_unknown_frequency_returns_error() {
+        for frequency in ["", "hourly", "Weekly"] {
+            assert_err!(DigestFrequency::parse(frequency));
+        }
+    }
+}
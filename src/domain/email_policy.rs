@@ -0,0 +1,160 @@
+use std::collections::HashSet;
+
+use once_cell::sync::Lazy;
+
+use super::{SubscriberEmail, ValidationError};
+
+static DISPOSABLE_DOMAINS: Lazy<HashSet<&'static str>> = Lazy::new(|| {
+    include_str!("disposable_domains.txt")
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect()
+});
+
+/// Local parts that reach a team or a system rather than a person.
+const ROLE_ACCOUNTS: &[&str] = &[
+    "abuse",
+    "admin",
+    "administrator",
+    "billing",
+    "contact",
+    "do-not-reply",
+    "donotreply",
+    "help",
+    "hostmaster",
+    "info",
+    "marketing",
+    "no-reply",
+    "noc",
+    "noreply",
+    "office",
+    "postmaster",
+    "root",
+    "sales",
+    "security",
+    "support",
+    "team",
+    "webmaster",
+];
+
+/// Which well-formed addresses may subscribe.
+#[derive(Debug, Clone, Default)]
+pub struct EmailPolicy {
+    reject_role_accounts: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum EmailPolicyViolation {
+    #[error("Addresses at {0} are not accepted, please use a permanent address.")]
+    DisposableDomain(String),
+    #[error("Role addresses such as {0}@ are not accepted, please use a personal address.")]
+    RoleAccount(String),
+}
+
+impl EmailPolicyViolation {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::DisposableDomain(_) => "disposable_domain",
+            Self::RoleAccount(_) => "role_account",
+        }
+    }
+}
+
+impl From<EmailPolicyViolation> for ValidationError {
+    fn from(violation: EmailPolicyViolation) -> Self {
+        Self::new(violation.code(), violation.to_string())
+    }
+}
+
+impl EmailPolicy {
+    pub fn new(reject_role_accounts: bool) -> Self {
+        Self {
+            reject_role_accounts,
+        }
+    }
+
+    /// `blocked_domains` are the domains admins added to the bundled
+    /// disposable ones.
+    pub fn check(
+        &self,
+        email: &SubscriberEmail,
+        blocked_domains: &[String],
+    ) -> Result<(), EmailPolicyViolation> {
+        if let Some(domain) = domain_and_parents(email.domain()).find(|domain| {
+            DISPOSABLE_DOMAINS.contains(domain) || blocked_domains.iter().any(|d| d == domain)
+        }) {
+            return Err(EmailPolicyViolation::DisposableDomain(domain.to_string()));
+        }
+        if self.reject_role_accounts {
+            // `info+news@` is as much a role account as `info@`.
+            let local_part = email.local_part().to_lowercase();
+            let mailbox = local_part.split('+').next().unwrap_or_default();
+            if ROLE_ACCOUNTS.contains(&mailbox) {
+                return Err(EmailPolicyViolation::RoleAccount(mailbox.to_string()));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The domain followed by each of its parents: `a.example.com`, then
+/// `example.com`, then `com`.
+pub fn domain_and_parents(domain: &str) -> impl Iterator<Item = &str> {
+    std::iter::successors(Some(domain), |domain| {
+        domain.split_once('.').map(|(_, parent)| parent)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{domain_and_parents, EmailPolicy, EmailPolicyViolation};
+    use crate::domain::SubscriberEmail;
+    use claims::{assert_err_eq, assert_ok};
+
+    fn email(email: &str) -> SubscriberEmail {
+        SubscriberEmail::parse(email.to_string()).unwrap()
+    }
+
+    #[test]
+    fn disposable_domains_and_their_subdomains_are_rejected() {
+        let policy = EmailPolicy::default();
+        for address in ["ursula@mailinator.com", "ursula@eu.Mailinator.com"] {
+            assert_err_eq!(
+                policy.check(&email(address), &[]),
+                EmailPolicyViolation::DisposableDomain("mailinator.com".to_string())
+            );
+        }
+        assert_ok!(policy.check(&email("ursula@notmailinator.com"), &[]));
+    }
+
+    #[test]
+    fn domains_blocked_by_admins_are_rejected() {
+        let policy = EmailPolicy::default();
+        let blocked = ["throwaway.example".to_string()];
+        assert_err_eq!(
+            policy.check(&email("ursula@throwaway.example"), &blocked),
+            EmailPolicyViolation::DisposableDomain("throwaway.example".to_string())
+        );
+        assert_ok!(policy.check(&email("ursula@example.com"), &blocked));
+    }
+
+    #[test]
+    fn role_accounts_are_only_rejected_when_configured() {
+        let address = email("NoReply+news@example.com");
+        assert_ok!(EmailPolicy::new(false).check(&address, &[]));
+        assert_err_eq!(
+            EmailPolicy::new(true).check(&address, &[]),
+            EmailPolicyViolation::RoleAccount("noreply".to_string())
+        );
+        assert_ok!(EmailPolicy::new(true).check(&email("ursula@example.com"), &[]));
+    }
+
+    #[test]
+    fn domain_and_parents_lists_every_suffix() {
+        assert_eq!(
+            domain_and_parents("a.example.com").collect::<Vec<_>>(),
+            ["a.example.com", "example.com", "com"]
+        );
+    }
+}
@@ -0,0 +1,109 @@
+use std::collections::BTreeSet;
+
+use scraper::{ElementRef, Html};
+
+const URL_ATTRIBUTES: [&str; 6] = ["href", "src", "cite", "action", "background", "longdesc"];
+
+#[derive(Debug, Default, PartialEq, serde::Serialize)]
+pub struct SanitizationReport {
+    pub removed_elements: BTreeSet<String>,
+    pub removed_attributes: BTreeSet<String>,
+    pub removed_urls: BTreeSet<String>,
+}
+
+impl SanitizationReport {
+    pub fn is_empty(&self) -> bool {
+        self.removed_elements.is_empty()
+            && self.removed_attributes.is_empty()
+            && self.removed_urls.is_empty()
+    }
+}
+
+pub fn sanitize_html(html: &str) -> (String, SanitizationReport) {
+    let builder = ammonia::Builder::default();
+    let report = report(&builder, html);
+    (builder.clean(html).to_string(), report)
+}
+
+fn report(builder: &ammonia::Builder, html: &str) -> SanitizationReport {
+    let tags = builder.clone_tags();
+    let generic_attributes = builder.clone_generic_attributes();
+    let tag_attributes = builder.clone_tag_attributes();
+    let url_schemes = builder.clone_url_schemes();
+
+    let mut report = SanitizationReport::default();
+    let fragment = Html::parse_fragment(html);
+    for element in fragment
+        .root_element()
+        .descendants()
+        .skip(1)
+        .filter_map(ElementRef::wrap)
+    {
+        let name = element.value().name();
+        if !tags.contains(name) {
+            report.removed_elements.insert(name.to_string());
+            continue;
+        }
+
+        for (attribute, value) in element.value().attrs() {
+            let is_allowed = generic_attributes.contains(attribute)
+                || tag_attributes
+                    .get(name)
+                    .is_some_and(|allowed| allowed.contains(attribute));
+            if !is_allowed {
+                report
+                    .removed_attributes
+                    .insert(format!("{}[{}]", name, attribute));
+            } else if URL_ATTRIBUTES.contains(&attribute) {
+                let is_safe_url = match url::Url::parse(value) {
+                    Ok(url) => url_schemes.contains(url.scheme()),
+                    Err(e) => e == url::ParseError::RelativeUrlWithoutBase,
+                };
+                if !is_safe_url {
+                    report.removed_urls.insert(value.to_string());
+                }
+            }
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::sanitize_html;
+
+    #[test]
+    fn sanitize_html_given_safe_html_reports_nothing() {
+        let (html, report) =
+            sanitize_html(r#"<p>Hello <a href="https://example.com">there</a></p>"#);
+
+        assert!(report.is_empty());
+        assert!(html.contains(r#"href="https://example.com""#));
+    }
+
+    #[test]
+    fn sanitize_html_removes_scripts_and_disallowed_tags() {
+        let (html, report) =
+            sanitize_html("<p>Hi</p><script>alert(1)</script><iframe src=\"https://x\"></iframe>");
+
+        assert_eq!(html, "<p>Hi</p>");
+        assert!(report.removed_elements.contains("script"));
+        assert!(report.removed_elements.contains("iframe"));
+    }
+
+    #[test]
+    fn sanitize_html_removes_event_handlers() {
+        let (html, report) = sanitize_html(r#"<img src="a.png" onerror="alert(1)">"#);
+
+        assert!(!html.contains("onerror"));
+        assert!(report.removed_attributes.contains("img[onerror]"));
+    }
+
+    #[test]
+    fn sanitize_html_removes_javascript_urls() {
+        let (html, report) = sanitize_html(r#"<a href="javascript:alert(1)">click</a>"#);
+
+        assert!(!html.contains("javascript:"));
+        assert!(report.removed_urls.contains("javascript:alert(1)"));
+    }
+}
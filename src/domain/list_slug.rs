@@ -0,0 +1,73 @@
+pub const DEFAULT_LIST_SLUG: &str = "newsletter";
+
+#[derive(Debug, Clone)]
+pub struct ListSlug(String);
+
+impl ListSlug {
+    pub fn parse(slug: String) -> Result<Self, String> {
+        let is_valid = !slug.is_empty()
+            && slug.len() <= 64
+            && !slug.starts_with('-')
+            && !slug.ends_with('-')
+            && slug
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+        if !is_valid {
+            return Err(format!(
+                "{} is not a valid list slug. Use up to 64 lowercase letters, digits and hyphens.",
+                slug
+            ));
+        }
+        Ok(Self(slug))
+    }
+}
+
+impl Default for ListSlug {
+    fn default() -> Self {
+        Self(DEFAULT_LIST_SLUG.to_string())
+    }
+}
+
+impl AsRef<str> for ListSlug {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for ListSlug {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ListSlug;
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn parse_given_lowercase_slug_returns_ok() {
+        for slug in ["newsletter", "product-updates", "engineering-digest-2024"] {
+            assert_ok!(ListSlug::parse(slug.to_string()));
+        }
+    }
+
+    #[test]
+    fn parse_given_invalid_slug_returns_error() {
+        for slug in [
+            "",
+            "Product",
+            "product updates",
+            "-digest",
+            "digest-",
+            "ünïcode",
+        ] {
+            assert_err!(ListSlug::parse(slug.to_string()));
+        }
+    }
+
+    #[test]
+    fn parse_given_slug_longer_than_64_characters_returns_error() {
+        assert_err!(ListSlug::parse("a".repeat(65)));
+    }
+}
@@ -0,0 +1,126 @@
+use std::collections::{BTreeSet, HashMap};
+
+use super::newsletter_content::escape_html;
+
+/// Merge tags available in every issue, on top of `subscriber.<key>` for
+/// each custom field.
+pub const BUILT_IN_MERGE_TAGS: [&str; 3] =
+    ["subscriber.name", "subscriber.email", "unsubscribe_url"];
+
+/// Replaces `{{ variable }}` placeholders with their values, HTML-escaping
+/// them if `escape` is set. Unknown placeholders are left untouched.
+pub fn render_merge_tags(
+    template: &str,
+    variables: &HashMap<String, String>,
+    escape: bool,
+) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut position = 0;
+    for (start, end, tag) in placeholders(template) {
+        rendered.push_str(&template[position..start]);
+        match variables.get(tag) {
+            Some(value) if escape => rendered.push_str(&escape_html(value)),
+            Some(value) => rendered.push_str(value),
+            None => rendered.push_str(&template[start..end]),
+        }
+        position = end;
+    }
+    rendered.push_str(&template[position..]);
+    rendered
+}
+
+/// Checks that every merge tag used in `templates` can be rendered, i.e. is
+/// either built in or refers to one of the given custom fields.
+pub fn validate_merge_tags<'a>(
+    templates: &[&str],
+    custom_field_keys: impl IntoIterator<Item = &'a str>,
+) -> Result<(), String> {
+    let mut unknown = templates
+        .iter()
+        .flat_map(|template| placeholders(template).map(|(_, _, tag)| tag))
+        .collect::<BTreeSet<_>>();
+    for tag in BUILT_IN_MERGE_TAGS {
+        unknown.remove(tag);
+    }
+    for key in custom_field_keys {
+        unknown.remove(format!("subscriber.{}", key).as_str());
+    }
+
+    if unknown.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "Unknown merge tags: {}.",
+            unknown.into_iter().collect::<Vec<_>>().join(", ")
+        ))
+    }
+}
+
+/// Yields the byte range and trimmed name of each `{{ ... }}` placeholder.
+fn placeholders(template: &str) -> impl Iterator<Item = (usize, usize, &str)> {
+    let mut position = 0;
+    std::iter::from_fn(move || {
+        let start = position + template[position..].find("{{")?;
+        let end = start + template[start..].find("}}")? + 2;
+        position = end;
+        Some((start, end, template[start + 2..end - 2].trim()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render_merge_tags, validate_merge_tags};
+    use claims::{assert_err, assert_ok};
+    use std::collections::HashMap;
+
+    fn variables() -> HashMap<String, String> {
+        HashMap::from([("subscriber.company".to_string(), "<Acme & Co>".to_string())])
+    }
+
+    #[test]
+    fn known_variables_are_replaced() {
+        let rendered = render_merge_tags("Hi {{subscriber.company}}!", &variables(), false);
+        assert_eq!(rendered, "Hi <Acme & Co>!");
+    }
+
+    #[test]
+    fn values_are_escaped_in_html() {
+        let rendered = render_merge_tags("<p>{{ subscriber.company }}</p>", &variables(), true);
+        assert_eq!(rendered, "<p>&lt;Acme &amp; Co&gt;</p>");
+    }
+
+    #[test]
+    fn values_are_escaped_inside_attributes() {
+        let variables = HashMap::from([(
+            "unsubscribe_url".to_string(),
+            "https://example.com/?a=1&b=\"2\"".to_string(),
+        )]);
+        let rendered = render_merge_tags(r#"<a href="{{ unsubscribe_url }}">"#, &variables, true);
+        assert_eq!(
+            rendered,
+            r#"<a href="https://example.com/?a=1&amp;b=&quot;2&quot;">"#
+        );
+    }
+
+    #[test]
+    fn unknown_and_unterminated_placeholders_are_left_alone() {
+        let template = "{{ subscriber.plan }} and {{ subscriber.company";
+        assert_eq!(render_merge_tags(template, &variables(), true), template);
+    }
+
+    #[test]
+    fn built_in_and_custom_field_tags_are_valid() {
+        let html = "<p>Hi {{ subscriber.name }} from {{subscriber.company}}</p>";
+        let text = "Unsubscribe: {{ unsubscribe_url }} ({{ subscriber.email }})";
+        assert_ok!(validate_merge_tags(&[html, text], ["company"]));
+    }
+
+    #[test]
+    fn unknown_tags_are_reported() {
+        let result = validate_merge_tags(&["{{ subscriber.plan }} {{ first_name }}"], ["company"]);
+        assert_eq!(
+            assert_err!(result),
+            "Unknown merge tags: first_name, subscriber.plan."
+        );
+    }
+}
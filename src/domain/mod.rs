@@ -1,7 +1,30 @@
+pub use custom_fields::{
+    custom_field_to_template_value, CustomFieldDefinition, CustomFieldType, CustomFields,
+};
+pub use digest_frequency::DigestFrequency;
+pub use email_policy::{domain_and_parents, EmailPolicy, EmailPolicyViolation};
+pub use html_sanitizer::SanitizationReport;
+pub use list_slug::{ListSlug, DEFAULT_LIST_SLUG};
+pub use merge_tags::{render_merge_tags, validate_merge_tags, BUILT_IN_MERGE_TAGS};
 pub use new_subscriber::NewSubscriber;
-pub use subscriber_email::SubscriberEmail;
+pub(crate) use newsletter_content::escape_html;
+pub use newsletter_content::NewsletterContent;
+pub use subscriber_email::{parse_domain, SubscriberEmail, SubscriberEmailError};
 pub use subscriber_name::SubscriberName;
+pub use subscriber_tag::SubscriberTag;
+pub use subscriber_timezone::SubscriberTimezone;
+pub use validation_error::ValidationError;
 
+mod custom_fields;
+mod digest_frequency;
+mod email_policy;
+mod html_sanitizer;
+mod list_slug;
+mod merge_tags;
 mod new_subscriber;
+mod newsletter_content;
 mod subscriber_email;
 mod subscriber_name;
+mod subscriber_tag;
+mod subscriber_timezone;
+mod validation_error;
@@ -1,8 +1,12 @@
+use crate::domain::custom_fields::CustomFields;
 use crate::domain::subscriber_email::SubscriberEmail;
 use crate::domain::subscriber_name::SubscriberName;
+use crate::domain::subscriber_timezone::SubscriberTimezone;
 
 #[derive(Debug)]
 pub struct NewSubscriber {
     pub name: SubscriberName,
     pub email: SubscriberEmail,
+    pub timezone: Option<SubscriberTimezone>,
+    pub custom_fields: CustomFields,
 }
@@ -0,0 +1,320 @@
+use pulldown_cmark::{Event, Options, Parser, Tag, TagEnd};
+
+use crate::domain::html_sanitizer::{sanitize_html, SanitizationReport};
+
+const LAYOUT: &str = include_str!("../../templates/newsletter.html");
+
+#[derive(Debug)]
+pub struct NewsletterContent {
+    html: String,
+    text: String,
+    markdown: Option<String>,
+    sanitization_report: SanitizationReport,
+}
+
+impl NewsletterContent {
+    pub fn parse(
+        title: &str,
+        html: Option<String>,
+        text: Option<String>,
+        markdown: Option<String>,
+    ) -> Result<Self, String> {
+        match (html, text, markdown) {
+            (None, None, Some(markdown)) => Self::from_markdown(title, markdown),
+            (Some(html), Some(text), None) => Self::from_html_and_text(title, html, text),
+            (_, _, Some(_)) => {
+                Err("Newsletter content cannot contain both markdown and html/text.".to_string())
+            }
+            _ => {
+                Err("Newsletter content must contain either markdown or html and text.".to_string())
+            }
+        }
+    }
+
+    pub fn from_markdown(title: &str, markdown: String) -> Result<Self, String> {
+        if markdown.trim().is_empty() {
+            return Err("Newsletter markdown cannot be empty.".to_string());
+        }
+
+        let (html, sanitization_report) = sanitize_html(&markdown_to_html(&markdown));
+        let text = markdown_to_text(&markdown);
+        Ok(Self {
+            html: render_layout(title, &html),
+            text,
+            markdown: Some(markdown),
+            sanitization_report,
+        })
+    }
+
+    pub fn from_html_and_text(title: &str, html: String, text: String) -> Result<Self, String> {
+        if html.trim().is_empty() || text.trim().is_empty() {
+            return Err("Newsletter html and text cannot be empty.".to_string());
+        }
+
+        let (html, sanitization_report) = sanitize_html(&html);
+        Ok(Self {
+            html: render_layout(title, &html),
+            text,
+            markdown: None,
+            sanitization_report,
+        })
+    }
+
+    pub fn html(&self) -> &str {
+        &self.html
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn markdown(&self) -> Option<&str> {
+        self.markdown.as_deref()
+    }
+
+    pub fn sanitization_report(&self) -> &SanitizationReport {
+        &self.sanitization_report
+    }
+}
+
+fn parser(markdown: &str) -> Parser<'_> {
+    Parser::new_ext(
+        markdown,
+        Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TABLES,
+    )
+}
+
+fn markdown_to_html(markdown: &str) -> String {
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, parser(markdown));
+    restore_merge_tags_in_links(&html)
+}
+
+/// Link destinations get percent-encoded, which would turn e.g.
+/// `[Unsubscribe](<{{ unsubscribe_url }}>)` into `%7B%7B%20unsubscribe_url%20%7D%7D`.
+fn restore_merge_tags_in_links(html: &str) -> String {
+    let mut restored = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find("%7B%7B") {
+        let Some(length) = rest[start..].find("%7D%7D") else {
+            break;
+        };
+        let inner = &rest[start + 6..start + length];
+        restored.push_str(&rest[..start]);
+        restored.push_str("{{");
+        restored.push_str(&inner.replace("%20", " "));
+        restored.push_str("}}");
+        rest = &rest[start + length + 6..];
+    }
+    restored.push_str(rest);
+    restored
+}
+
+fn render_layout(title: &str, content: &str) -> String {
+    LAYOUT
+        .replace("{{ title }}", &escape_html(title))
+        .replace("{{ content }}", content)
+}
+
+pub(crate) fn escape_html(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for character in value.chars() {
+        match character {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#x27;"),
+            _ => escaped.push(character),
+        }
+    }
+    escaped
+}
+
+fn markdown_to_text(markdown: &str) -> String {
+    let mut text = String::new();
+    let mut links: Vec<String> = Vec::new();
+    let mut lists: Vec<Option<u64>> = Vec::new();
+
+    for event in parser(markdown) {
+        match event {
+            Event::Text(value) | Event::Code(value) => text.push_str(&value),
+            Event::SoftBreak | Event::HardBreak => text.push('\n'),
+            Event::Rule => text.push_str("----------\n\n"),
+            Event::Start(Tag::List(start)) => {
+                if lists.is_empty() && !text.is_empty() && !text.ends_with("\n\n") {
+                    text.push('\n');
+                }
+                lists.push(start);
+            }
+            Event::End(TagEnd::List(_)) => {
+                lists.pop();
+                if lists.is_empty() {
+                    text.push('\n');
+                }
+            }
+            Event::Start(Tag::Item) => {
+                if !text.is_empty() && !text.ends_with('\n') {
+                    text.push('\n');
+                }
+                text.push_str(&"  ".repeat(lists.len().saturating_sub(1)));
+                match lists.last_mut() {
+                    Some(Some(number)) => {
+                        text.push_str(&format!("{}. ", number));
+                        *number += 1;
+                    }
+                    _ => text.push_str("- "),
+                }
+            }
+            Event::End(TagEnd::Item) if !text.ends_with('\n') => text.push('\n'),
+            Event::Start(Tag::Link { dest_url, .. })
+            | Event::Start(Tag::Image { dest_url, .. }) => {
+                links.push(dest_url.to_string());
+            }
+            Event::End(TagEnd::Link) | Event::End(TagEnd::Image) => {
+                if let Some(url) = links.pop() {
+                    text.push_str(&format!(" ({})", url));
+                }
+            }
+            Event::End(TagEnd::Paragraph)
+            | Event::End(TagEnd::Heading(_))
+            | Event::End(TagEnd::CodeBlock)
+            | Event::End(TagEnd::Table) => {
+                if lists.is_empty() {
+                    text.push_str("\n\n");
+                } else {
+                    text.push('\n');
+                }
+            }
+            Event::End(TagEnd::TableCell) => text.push('\t'),
+            Event::End(TagEnd::TableHead) | Event::End(TagEnd::TableRow) => text.push('\n'),
+            _ => {}
+        }
+    }
+
+    let mut text = text.trim_end().to_string();
+    text.push('\n');
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NewsletterContent;
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn parse_given_markdown_renders_html_and_text() {
+        let markdown =
+            "# Hello\n\nSome **bold** text and a [link](https://example.com).".to_string();
+        let content =
+            NewsletterContent::parse("Title", None, None, Some(markdown.clone())).unwrap();
+
+        assert!(content.html().contains("<h1>Hello</h1>"));
+        assert!(content.html().contains("<strong>bold</strong>"));
+        assert!(content.html().contains("<title>Title</title>"));
+        assert_eq!(
+            content.text(),
+            "Hello\n\nSome bold text and a link (https://example.com).\n"
+        );
+        assert_eq!(content.markdown(), Some(markdown.as_str()));
+    }
+
+    #[test]
+    fn parse_given_markdown_with_script_strips_it_from_html() {
+        let markdown = "Hi <script>alert('x')</script> there".to_string();
+        let content = NewsletterContent::parse("Title", None, None, Some(markdown)).unwrap();
+
+        assert!(!content.html().contains("<script>"));
+        assert!(!content.text().contains("<script>"));
+    }
+
+    #[test]
+    fn parse_given_markdown_escapes_title_in_layout() {
+        let content =
+            NewsletterContent::parse("<b>Title</b>", None, None, Some("Body".to_string())).unwrap();
+
+        assert!(content
+            .html()
+            .contains("<title>&lt;b&gt;Title&lt;/b&gt;</title>"));
+    }
+
+    #[test]
+    fn parse_given_markdown_lists_renders_readable_text() {
+        let markdown = "Items:\n\n- one\n- two\n\n1. first\n2. second".to_string();
+        let content = NewsletterContent::parse("Title", None, None, Some(markdown)).unwrap();
+
+        assert_eq!(
+            content.text(),
+            "Items:\n\n- one\n- two\n\n1. first\n2. second\n"
+        );
+    }
+
+    #[test]
+    fn parse_given_html_and_text_wraps_html_in_layout() {
+        let result = NewsletterContent::parse(
+            "Title",
+            Some("<p>Hi</p>".to_string()),
+            Some("Hi".to_string()),
+            None,
+        );
+
+        let content = assert_ok!(result);
+        assert!(content.html().contains("<title>Title</title>"));
+        assert!(content.html().contains("<p>Hi</p>"));
+        assert_eq!(content.text(), "Hi");
+        assert_eq!(content.markdown(), None);
+        assert!(content.sanitization_report().is_empty());
+    }
+
+    #[test]
+    fn parse_given_html_with_script_sanitizes_and_reports_it() {
+        let content = NewsletterContent::parse(
+            "Title",
+            Some("<p onclick=\"steal()\">Hi</p><script>alert(1)</script>".to_string()),
+            Some("Hi".to_string()),
+            None,
+        )
+        .unwrap();
+
+        assert!(!content.html().contains("<script>"));
+        assert!(!content.html().contains("onclick"));
+        let report = content.sanitization_report();
+        assert!(report.removed_elements.contains("script"));
+        assert!(report.removed_attributes.contains("p[onclick]"));
+    }
+
+    #[test]
+    fn parse_given_markdown_and_html_returns_error() {
+        let result = NewsletterContent::parse(
+            "Title",
+            Some("<p>Hi</p>".to_string()),
+            None,
+            Some("Hi".to_string()),
+        );
+        assert_err!(result);
+    }
+
+    #[test]
+    fn parse_given_only_html_returns_error() {
+        let result = NewsletterContent::parse("Title", Some("<p>Hi</p>".to_string()), None, None);
+        assert_err!(result);
+    }
+
+    #[test]
+    fn parse_given_empty_markdown_returns_error() {
+        let result = NewsletterContent::parse("Title", None, None, Some("  ".to_string()));
+        assert_err!(result);
+    }
+
+    #[test]
+    fn merge_tags_survive_markdown_link_destinations() {
+        let content = assert_ok!(NewsletterContent::from_markdown(
+            "Title",
+            "[Unsubscribe](<{{ unsubscribe_url }}>) or [here]({{unsubscribe_url}})".to_string()
+        ));
+        assert!(content
+            .html()
+            .contains(r#"<a href="{{ unsubscribe_url }}""#));
+        assert!(content.html().contains(r#"<a href="{{unsubscribe_url}}""#));
+    }
+}
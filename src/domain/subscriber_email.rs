@@ -1,16 +1,191 @@
-use validator::ValidateEmail;
+use std::net::IpAddr;
 
+use unicode_normalization::UnicodeNormalization;
+
+use super::ValidationError;
+
+/// RFC 5321 limits, in octets.
+const MAX_EMAIL_LENGTH: usize = 254;
+const MAX_LOCAL_PART_LENGTH: usize = 64;
+const MAX_DOMAIN_LENGTH: usize = 253;
+const MAX_DOMAIN_LABEL_LENGTH: usize = 63;
+
+/// An address in normal form: trimmed, NFC-normalized, with its domain
+/// lowercased and converted to ASCII (punycode). The local part keeps its
+/// case, which only the receiving server may interpret, and may contain
+/// non-ASCII characters (RFC 6531).
 #[derive(Debug, Clone)]
 pub struct SubscriberEmail(String);
 
+/// Why an address was rejected.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum SubscriberEmailError {
+    #[error("Subscriber email cannot be empty.")]
+    Empty,
+    #[error("Subscriber email must contain an @ symbol.")]
+    MissingAtSymbol,
+    #[error("Subscriber email must contain a name.")]
+    MissingLocalPart,
+    #[error("Subscriber email must contain a host.")]
+    MissingHost,
+    #[error("Subscriber email cannot be longer than {MAX_EMAIL_LENGTH} characters.")]
+    TooLong,
+    #[error(
+        "The name of a subscriber email cannot be longer than {MAX_LOCAL_PART_LENGTH} characters."
+    )]
+    LocalPartTooLong,
+    #[error(
+        "The host of a subscriber email cannot be longer than {MAX_DOMAIN_LENGTH} characters."
+    )]
+    DomainTooLong,
+    #[error("The host of a subscriber email has an invalid label: '{0}'.")]
+    InvalidDomainLabel(String),
+    #[error("Invalid email.")]
+    Invalid,
+}
+
+impl SubscriberEmailError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Empty => "empty",
+            Self::MissingAtSymbol => "missing_at_symbol",
+            Self::MissingLocalPart => "missing_local_part",
+            Self::MissingHost => "missing_host",
+            Self::TooLong => "too_long",
+            Self::LocalPartTooLong => "local_part_too_long",
+            Self::DomainTooLong => "domain_too_long",
+            Self::InvalidDomainLabel(_) => "invalid_domain_label",
+            Self::Invalid => "invalid",
+        }
+    }
+}
+
+impl From<SubscriberEmailError> for ValidationError {
+    fn from(error: SubscriberEmailError) -> Self {
+        Self::new(error.code(), error.to_string())
+    }
+}
+
 impl SubscriberEmail {
-    pub fn parse(email: String) -> Result<Self, String> {
-        if email.validate_email() {
-            Ok(Self(email))
+    pub fn parse(email: String) -> Result<Self, SubscriberEmailError> {
+        let email = email.trim().nfc().collect::<String>();
+        if email.is_empty() {
+            return Err(SubscriberEmailError::Empty);
+        }
+        // The local part may itself contain a quoted `@`, the host never does.
+        let Some((local_part, domain)) = email.rsplit_once('@') else {
+            return Err(SubscriberEmailError::MissingAtSymbol);
+        };
+        if local_part.is_empty() {
+            return Err(SubscriberEmailError::MissingLocalPart);
+        }
+        if domain.is_empty() {
+            return Err(SubscriberEmailError::MissingHost);
+        }
+        let domain = if domain.starts_with('[') {
+            if !is_valid_address_literal(domain) {
+                return Err(SubscriberEmailError::Invalid);
+            }
+            domain.to_string()
         } else {
-            Err("Invalid email.".to_string())
+            parse_domain(domain)?
+        };
+        if local_part.len() > MAX_LOCAL_PART_LENGTH {
+            return Err(SubscriberEmailError::LocalPartTooLong);
         }
+        if !is_valid_local_part(local_part) {
+            return Err(SubscriberEmailError::Invalid);
+        }
+        let email = format!("{local_part}@{domain}");
+        if email.len() > MAX_EMAIL_LENGTH {
+            return Err(SubscriberEmailError::TooLong);
+        }
+        Ok(Self(email))
+    }
+
+    pub fn local_part(&self) -> &str {
+        self.split().0
     }
+
+    /// The domain, in lowercase ASCII.
+    pub fn domain(&self) -> &str {
+        self.split().1
+    }
+
+    fn split(&self) -> (&str, &str) {
+        self.0
+            .rsplit_once('@')
+            .expect("A parsed email always contains an @ symbol.")
+    }
+
+    /// Whether the address can only be delivered by a server supporting
+    /// SMTPUTF8. Domains are always stored as ASCII, so only a non-ASCII
+    /// local part requires it.
+    pub fn requires_smtputf8(&self) -> bool {
+        !self.0.is_ascii()
+    }
+
+    /// The address with its domain in Unicode, for transports that support
+    /// SMTPUTF8.
+    pub fn to_unicode(&self) -> String {
+        let (local_part, domain) = self.split();
+        let (domain, _) = idna::domain_to_unicode(domain);
+        format!("{local_part}@{domain}")
+    }
+}
+
+/// Converts a domain name to the form addresses are stored with: lowercase
+/// ASCII, with internationalized labels in punycode.
+pub fn parse_domain(domain: &str) -> Result<String, SubscriberEmailError> {
+    let domain = domain
+        .trim()
+        .split('.')
+        .map(|label| {
+            idna::domain_to_ascii(label)
+                .map_err(|_| SubscriberEmailError::InvalidDomainLabel(label.to_string()))
+        })
+        .collect::<Result<Vec<_>, _>>()?
+        .join(".");
+    if domain.len() > MAX_DOMAIN_LENGTH {
+        return Err(SubscriberEmailError::DomainTooLong);
+    }
+    if let Some(label) = domain.split('.').find(|label| !is_valid_label(label)) {
+        return Err(SubscriberEmailError::InvalidDomainLabel(label.to_string()));
+    }
+    Ok(domain)
+}
+
+/// A dot-atom whose atoms may also contain any non-ASCII character but
+/// controls and spaces (RFC 6531). Quoted local parts are not accepted.
+fn is_valid_local_part(local_part: &str) -> bool {
+    local_part.split('.').all(|atom| {
+        !atom.is_empty()
+            && atom.chars().all(|c| {
+                if c.is_ascii() {
+                    c.is_ascii_alphanumeric() || "!#$%&'*+/=?^_`{|}~-".contains(c)
+                } else {
+                    !c.is_control() && !c.is_whitespace()
+                }
+            })
+    })
+}
+
+/// An IP address in brackets, such as `[127.0.0.1]` or `[IPv6:::1]`.
+fn is_valid_address_literal(domain: &str) -> bool {
+    domain
+        .strip_prefix('[')
+        .and_then(|literal| literal.strip_suffix(']'))
+        .map(|literal| literal.strip_prefix("IPv6:").unwrap_or(literal))
+        .is_some_and(|address| address.parse::<IpAddr>().is_ok())
+}
+
+/// An LDH label: letters, digits and inner hyphens.
+fn is_valid_label(label: &str) -> bool {
+    !label.is_empty()
+        && label.len() <= MAX_DOMAIN_LABEL_LENGTH
+        && !label.starts_with('-')
+        && !label.ends_with('-')
+        && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
 }
 
 impl AsRef<str> for SubscriberEmail {
@@ -21,37 +196,172 @@ impl AsRef<str> for SubscriberEmail {
 
 #[cfg(test)]
 mod tests {
-    use super::SubscriberEmail;
-    use claims::{assert_err, assert_ok};
+    use super::{SubscriberEmail, SubscriberEmailError};
+    use claims::{assert_err_eq, assert_ok};
     use fake::faker::internet::en::SafeEmail;
     use fake::Fake;
 
+    fn parse(email: &str) -> Result<SubscriberEmail, SubscriberEmailError> {
+        SubscriberEmail::parse(email.to_string())
+    }
+
     #[test]
     fn parse_given_empty_email_returns_error() {
-        let email = "";
-        let result = SubscriberEmail::parse(email.to_string());
-        assert_err!(result, "Subscriber email cannot be empty.");
+        assert_err_eq!(parse(""), SubscriberEmailError::Empty);
+        assert_err_eq!(parse("   "), SubscriberEmailError::Empty);
     }
 
     #[test]
     fn parse_given_email_without_at_symbol_then_error() {
-        let email = "ursula.com";
-        let result = SubscriberEmail::parse(email.to_string());
-        assert_err!(result, "Subscriber email must contain an @ symbol.");
+        assert_err_eq!(parse("ursula.com"), SubscriberEmailError::MissingAtSymbol);
     }
 
     #[test]
     fn parse_given_email_without_host_then_error() {
-        let email = "ewrwrw@";
-        let result = SubscriberEmail::parse(email.to_string());
-        assert_err!(result, "Subscriber email must contain a host.");
+        assert_err_eq!(parse("ewrwrw@"), SubscriberEmailError::MissingHost);
     }
 
     #[test]
     fn parse_given_email_without_name_then_error() {
-        let email = "@gmail.com";
-        let result = SubscriberEmail::parse(email.to_string());
-        assert_err!(result, "Subscriber email must contain a name.");
+        assert_err_eq!(parse("@gmail.com"), SubscriberEmailError::MissingLocalPart);
+    }
+
+    #[test]
+    fn parse_given_too_long_local_part_then_error() {
+        let email = format!("{}@example.com", "a".repeat(65));
+        assert_err_eq!(parse(&email), SubscriberEmailError::LocalPartTooLong);
+        assert_ok!(parse(&format!("{}@example.com", "a".repeat(64))));
+    }
+
+    #[test]
+    fn parse_given_too_long_email_then_error() {
+        let domain = vec!["a".repeat(63); 3].join(".");
+        let email = format!("{}@{domain}", "u".repeat(64));
+        assert_err_eq!(parse(&email), SubscriberEmailError::TooLong);
+    }
+
+    #[test]
+    fn parse_given_too_long_domain_then_error() {
+        let domain = vec!["a".repeat(63); 4].join(".");
+        let email = format!("ursula@{domain}");
+        assert_err_eq!(parse(&email), SubscriberEmailError::DomainTooLong);
+    }
+
+    #[test]
+    fn parse_given_invalid_domain_labels_then_error() {
+        let long_label = "a".repeat(64);
+        let test_cases = [
+            ("ursula@example..com", ""),
+            ("ursula@-example.com", "-example"),
+            ("ursula@example-.com", "example-"),
+            ("ursula@exa_mple.com", "exa_mple"),
+            ("ursula@example.com.", ""),
+        ];
+        for (email, label) in test_cases {
+            assert_err_eq!(
+                parse(email),
+                SubscriberEmailError::InvalidDomainLabel(label.to_string()),
+                "{} should have been rejected",
+                email
+            );
+        }
+        assert_err_eq!(
+            parse(&format!("ursula@{long_label}.com")),
+            SubscriberEmailError::InvalidDomainLabel(long_label)
+        );
+    }
+
+    #[test]
+    fn parse_given_invalid_local_part_then_error() {
+        for email in [
+            "urs ula@example.com",
+            ".ursula@example.com",
+            "ursula.@example.com",
+            "urs..ula@example.com",
+            "\"ursula\"@example.com",
+            "urs\u{0}ula@example.com",
+            "урсула\u{a0}ле@example.com",
+        ] {
+            assert_err_eq!(
+                parse(email),
+                SubscriberEmailError::Invalid,
+                "{} should have been rejected",
+                email
+            );
+        }
+    }
+
+    #[test]
+    fn parse_accepts_address_literals() {
+        assert_ok!(parse("ursula@[127.0.0.1]"));
+        assert_ok!(parse("ursula@[IPv6:::1]"));
+        assert_err_eq!(parse("ursula@[localhost]"), SubscriberEmailError::Invalid);
+    }
+
+    #[test]
+    fn parse_accepts_internationalized_addresses() {
+        let test_cases = [
+            // Cyrillic
+            (
+                "пользователь@пример.рф",
+                "пользователь@xn--e1afmkfd.xn--p1ai",
+                true,
+            ),
+            // CJK
+            ("用户@例子.广告", "用户@xn--fsqu00a.xn--4rr70v", true),
+            // Emoji domain, ASCII local part
+            ("ursula@☕.example", "ursula@xn--53h.example", false),
+        ];
+        for (email, stored, requires_smtputf8) in test_cases {
+            let parsed = assert_ok!(parse(email));
+            assert_eq!(parsed.as_ref(), stored);
+            assert_eq!(parsed.requires_smtputf8(), requires_smtputf8);
+            assert_eq!(parsed.to_unicode(), email);
+        }
+    }
+
+    #[test]
+    fn parse_normalizes_the_local_part_to_nfc() {
+        // `e` followed by a combining acute accent, rather than `é`.
+        let decomposed = assert_ok!(parse("jose\u{301}@example.com"));
+        let composed = assert_ok!(parse("jos\u{e9}@example.com"));
+        assert_eq!(decomposed.as_ref(), composed.as_ref());
+    }
+
+    #[test]
+    fn parse_normalizes_the_domain_but_not_the_local_part() {
+        let test_cases = [
+            ("  Ursula@Example.COM ", "Ursula@example.com"),
+            ("ursula@bücher.example", "ursula@xn--bcher-kva.example"),
+            (
+                "ursula@BU\u{308}CHER.example",
+                "ursula@xn--bcher-kva.example",
+            ),
+        ];
+        for (email, expected) in test_cases {
+            let parsed = assert_ok!(parse(email));
+            assert_eq!(parsed.as_ref(), expected);
+        }
+    }
+
+    #[test]
+    fn errors_have_distinct_codes() {
+        let errors = [
+            SubscriberEmailError::Empty,
+            SubscriberEmailError::MissingAtSymbol,
+            SubscriberEmailError::MissingLocalPart,
+            SubscriberEmailError::MissingHost,
+            SubscriberEmailError::TooLong,
+            SubscriberEmailError::LocalPartTooLong,
+            SubscriberEmailError::DomainTooLong,
+            SubscriberEmailError::InvalidDomainLabel(String::new()),
+            SubscriberEmailError::Invalid,
+        ];
+        let codes = errors
+            .iter()
+            .map(SubscriberEmailError::code)
+            .collect::<std::collections::HashSet<_>>();
+        assert_eq!(codes.len(), errors.len());
     }
 
     #[test]
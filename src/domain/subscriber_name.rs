@@ -1,19 +1,47 @@
+use unicode_normalization::UnicodeNormalization;
 use unicode_segmentation::UnicodeSegmentation;
 
+use super::ValidationError;
+
+const MAX_LENGTH: usize = 256;
 const FORBIDDEN_CHARACTERS: [char; 9] = ['/', '(', ')', '"', '<', '>', '\\', '{', '}'];
+/// Characters that reorder the text around them, which can make a name
+/// display as something else entirely.
+const BIDI_CONTROLS: [char; 12] = [
+    '\u{061C}', '\u{200E}', '\u{200F}', '\u{202A}', '\u{202B}', '\u{202C}', '\u{202D}', '\u{202E}',
+    '\u{2066}', '\u{2067}', '\u{2068}', '\u{2069}',
+];
+/// Characters that render as nothing at all.
+const INVISIBLE_CHARACTERS: [char; 4] = ['\u{180E}', '\u{200B}', '\u{2060}', '\u{FEFF}'];
+/// Joiners shape the characters on either side of them, in emoji sequences
+/// and some scripts, and are invisible anywhere else.
+const JOINERS: [char; 2] = ['\u{200C}', '\u{200D}'];
 
+/// A name in normal form: NFC-normalized, trimmed, with every run of
+/// whitespace collapsed to a single space.
 #[derive(Debug)]
 pub struct SubscriberName(String);
 impl SubscriberName {
-    pub fn parse(name: String) -> Result<Self, String> {
-        let is_empty = name.trim().is_empty();
-        if is_empty {
-            return Err("Subscriber name cannot be empty.".to_string());
+    pub fn parse(name: String) -> Result<Self, ValidationError> {
+        let name = name
+            .nfc()
+            .collect::<String>()
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        if name.is_empty() {
+            return Err(ValidationError::new(
+                "empty",
+                "Subscriber name cannot be empty.",
+            ));
         }
 
-        let is_too_long = name.graphemes(true).count() > 256;
+        let is_too_long = name.graphemes(true).count() > MAX_LENGTH;
         if is_too_long {
-            return Err("Subscriber name cannot be longer than 256 characters.".to_string());
+            return Err(ValidationError::new(
+                "too_long",
+                "Subscriber name cannot be longer than 256 characters.",
+            ));
         }
 
         let contains_forbidden_characters = name
@@ -21,16 +49,56 @@ impl SubscriberName {
             .any(|character| FORBIDDEN_CHARACTERS.contains(&character));
 
         if contains_forbidden_characters {
-            return Err(
-                "Subscriber name cannot contain any of the following characters: /()\"<>\\. \\{\\}"
-                    .to_string(),
-            );
+            return Err(ValidationError::new(
+                "forbidden_characters",
+                "Subscriber name cannot contain any of the following characters: /()\"<>\\. \\{\\}",
+            ));
+        }
+
+        if name.chars().any(char::is_control) {
+            return Err(ValidationError::new(
+                "control_characters",
+                "Subscriber name cannot contain control characters.",
+            ));
+        }
+
+        if name
+            .chars()
+            .any(|character| BIDI_CONTROLS.contains(&character))
+        {
+            return Err(ValidationError::new(
+                "bidi_controls",
+                "Subscriber name cannot contain bidirectional control characters.",
+            ));
+        }
+
+        if name
+            .chars()
+            .any(|character| INVISIBLE_CHARACTERS.contains(&character))
+            || has_stray_joiner(&name)
+        {
+            return Err(ValidationError::new(
+                "invisible_characters",
+                "Subscriber name cannot contain invisible characters.",
+            ));
         }
 
         Ok(Self(name))
     }
 }
 
+/// Whether a joiner is missing a visible character on either side.
+fn has_stray_joiner(name: &str) -> bool {
+    let is_joinable = |character: Option<&char>| {
+        character.is_some_and(|c| !c.is_whitespace() && !JOINERS.contains(c))
+    };
+    let characters = name.chars().collect::<Vec<_>>();
+    characters.iter().enumerate().any(|(i, character)| {
+        JOINERS.contains(character)
+            && !(i > 0 && is_joinable(characters.get(i - 1)) && is_joinable(characters.get(i + 1)))
+    })
+}
+
 impl AsRef<str> for SubscriberName {
     fn as_ref(&self) -> &str {
         &self.0
@@ -39,24 +107,27 @@ impl AsRef<str> for SubscriberName {
 
 #[cfg(test)]
 mod tests {
-    use super::{SubscriberName, FORBIDDEN_CHARACTERS};
+    use std::collections::HashMap;
+
+    use super::{SubscriberName, BIDI_CONTROLS, FORBIDDEN_CHARACTERS};
+    use crate::domain::render_merge_tags;
     use claims::{assert_err, assert_ok};
+    use proptest::prelude::*;
+    use unicode_normalization::is_nfc;
 
     #[test]
     fn parse_given_256_characters_name_returns_error() {
         let name = "ё".repeat(257);
         let result = SubscriberName::parse(name);
-        assert_err!(
-            result,
-            "Subscriber name cannot be longer than 256 characters."
-        );
+        assert_eq!(assert_err!(result).code, "too_long");
     }
 
     #[test]
     fn parse_given_empty_name_returns_error() {
-        let name = "";
-        let result = SubscriberName::parse(name.to_string());
-        assert_err!(result, "Subscriber name cannot be empty.");
+        for name in ["", " \t\n "] {
+            let result = SubscriberName::parse(name.to_string());
+            assert_eq!(assert_err!(result).code, "empty");
+        }
     }
 
     #[test]
@@ -65,10 +136,11 @@ mod tests {
         for char in FORBIDDEN_CHARACTERS {
             let name = format!("name{}", char);
             let result = SubscriberName::parse(name);
-            assert_err!(
+            let error = assert_err!(
                 result,
                 "Subscriber name cannot contain any of the following characters: {err_msg}"
             );
+            assert_eq!(error.code, "forbidden_characters");
         }
     }
 
@@ -78,4 +150,125 @@ mod tests {
         let result = SubscriberName::parse(name);
         assert_ok!(result, "Name should be valid.");
     }
+
+    #[test]
+    fn parse_trims_and_collapses_whitespace() {
+        let name = assert_ok!(SubscriberName::parse(
+            "  Ursula \t K.\n\u{a0}Le Guin ".to_string()
+        ));
+        assert_eq!(name.as_ref(), "Ursula K. Le Guin");
+    }
+
+    #[test]
+    fn parse_normalizes_to_nfc() {
+        let name = assert_ok!(SubscriberName::parse("Jose\u{301}".to_string()));
+        assert_eq!(name.as_ref(), "Jos\u{e9}");
+    }
+
+    #[test]
+    fn parse_given_control_characters_returns_error() {
+        for name in ["le\u{0}guin", "le\u{7f}guin", "le\u{85}guin\u{1b}"] {
+            let result = SubscriberName::parse(name.to_string());
+            assert_eq!(assert_err!(result).code, "control_characters", "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn parse_given_bidi_controls_returns_error() {
+        for control in BIDI_CONTROLS {
+            let result = SubscriberName::parse(format!("ursula{control}niug el"));
+            assert_eq!(assert_err!(result).code, "bidi_controls", "{:?}", control);
+        }
+    }
+
+    #[test]
+    fn parse_given_invisible_characters_returns_error() {
+        for name in [
+            "ur\u{200b}sula",
+            "\u{feff}ursula",
+            "\u{200d}ursula",
+            "ursula\u{200d}",
+            "ursula \u{200d}le guin",
+            "ur\u{200d}\u{200d}sula",
+            "ur\u{200c}\u{200d}sula",
+        ] {
+            let result = SubscriberName::parse(name.to_string());
+            assert_eq!(
+                assert_err!(result).code,
+                "invisible_characters",
+                "{:?}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn parse_accepts_joiners_between_characters() {
+        // A family emoji, and a Persian name with a zero-width non-joiner.
+        for name in [
+            "\u{1f468}\u{200d}\u{1f469}\u{200d}\u{1f467}",
+            "\u{645}\u{6cc}\u{200c}\u{62e}\u{648}\u{627}\u{647}\u{645}",
+        ] {
+            assert_ok!(SubscriberName::parse(name.to_string()));
+        }
+    }
+
+    /// Renders `name` into HTML the way newsletters do, and parses it back.
+    fn render(name: &str) -> scraper::Html {
+        let variables = HashMap::from([("subscriber.name".to_string(), name.to_string())]);
+        let html = render_merge_tags(
+            r#"<p title="{{ subscriber.name }}">Hello {{ subscriber.name }}!</p>"#,
+            &variables,
+            true,
+        );
+        scraper::Html::parse_fragment(&html)
+    }
+
+    proptest! {
+        #[test]
+        fn accepted_names_are_in_normal_form(name in any::<String>()) {
+            if let Ok(parsed) = SubscriberName::parse(name) {
+                let parsed = parsed.as_ref();
+                prop_assert_eq!(parsed, parsed.trim());
+                prop_assert!(is_nfc(parsed));
+                prop_assert!(!parsed.contains("  "));
+                prop_assert!(!parsed.chars().any(|c| c.is_whitespace() && c != ' '));
+                let reparsed = SubscriberName::parse(parsed.to_string()).unwrap();
+                prop_assert_eq!(reparsed.as_ref(), parsed);
+            }
+        }
+
+        #[test]
+        fn accepted_names_round_trip_into_html(name in "\\PC{0,40}|.{0,40}|[<>&\"' a-z]{0,20}") {
+            if let Ok(parsed) = SubscriberName::parse(name) {
+                let html = render(parsed.as_ref());
+                let selector = scraper::Selector::parse("*").unwrap();
+                let elements = html
+                    .root_element()
+                    .select(&selector)
+                    .map(|element| element.value().name().to_string())
+                    .collect::<Vec<_>>();
+                prop_assert_eq!(elements, ["p"]);
+                let paragraph = html
+                    .select(&scraper::Selector::parse("p").unwrap())
+                    .next()
+                    .unwrap();
+                prop_assert_eq!(paragraph.value().attr("title"), Some(parsed.as_ref()));
+                prop_assert_eq!(
+                    paragraph.text().collect::<String>(),
+                    format!("Hello {}!", parsed.as_ref())
+                );
+            }
+        }
+
+        #[test]
+        fn names_with_bidi_controls_are_rejected(
+            prefix in "[a-z ]{0,10}",
+            control in proptest::sample::select(BIDI_CONTROLS.to_vec()),
+            suffix in "[a-z ]{1,10}",
+        ) {
+            let name = format!("{prefix}{control}{suffix}");
+            prop_assert!(SubscriberName::parse(name).is_err());
+        }
+    }
 }
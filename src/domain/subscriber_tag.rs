@@ -0,0 +1,45 @@
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SubscriberTag(String);
+
+impl SubscriberTag {
+    pub fn parse(tag: String) -> Result<Self, String> {
+        let tag = tag.trim().to_lowercase();
+        let is_valid = !tag.is_empty()
+            && tag.len() <= 64
+            && tag
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !is_valid {
+            return Err(format!(
+                "{} is not a valid tag. Use up to 64 letters, digits, hyphens and underscores.",
+                tag
+            ));
+        }
+        Ok(Self(tag))
+    }
+}
+
+impl AsRef<str> for SubscriberTag {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SubscriberTag;
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn parse_normalizes_case_and_whitespace() {
+        let tag = assert_ok!(SubscriberTag::parse(" Beta ".to_string()));
+        assert_eq!(tag.as_ref(), "beta");
+    }
+
+    #[test]
+    fn parse_given_invalid_tag_returns_error() {
+        for tag in ["", "  ", "early adopter", "<script>", "ß"] {
+            assert_err!(SubscriberTag::parse(tag.to_string()));
+        }
+    }
+}
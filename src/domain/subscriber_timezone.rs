@@ -0,0 +1,45 @@
+use chrono_tz::Tz;
+
+use super::ValidationError;
+
+#[derive(Debug, Clone, Copy)]
+pub struct SubscriberTimezone(Tz);
+
+impl SubscriberTimezone {
+    pub fn parse(timezone: String) -> Result<Self, ValidationError> {
+        timezone.trim().parse::<Tz>().map(Self).map_err(|_| {
+            ValidationError::new(
+                "unknown_timezone",
+                format!("{} is not a valid IANA timezone.", timezone),
+            )
+        })
+    }
+}
+
+impl AsRef<str> for SubscriberTimezone {
+    fn as_ref(&self) -> &str {
+        self.0.name()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SubscriberTimezone;
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn parse_given_iana_timezone_returns_ok() {
+        for timezone in ["Europe/Athens", "America/New_York", "Asia/Kolkata", "UTC"] {
+            let result = SubscriberTimezone::parse(timezone.to_string());
+            assert_eq!(assert_ok!(result).as_ref(), timezone);
+        }
+    }
+
+    #[test]
+    fn parse_given_unknown_timezone_returns_error() {
+        for timezone in ["", "Mars/Olympus_Mons", "GMT+25", "europe athens"] {
+            let result = SubscriberTimezone::parse(timezone.to_string());
+            assert_err!(result);
+        }
+    }
+}
@@ -0,0 +1,28 @@
+/// Why an input value was rejected: a stable code for clients to match on
+/// and a message for humans.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("{message}")]
+pub struct ValidationError {
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl ValidationError {
+    pub fn new(code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    /// For parsers that only report a message.
+    pub fn invalid(message: String) -> Self {
+        Self::new("invalid", message)
+    }
+}
+
+impl From<ValidationError> for String {
+    fn from(error: ValidationError) -> Self {
+        error.message
+    }
+}
@@ -7,6 +7,8 @@ pub struct EmailClient {
     base_url: String,
     http_client: reqwest::Client,
     authorization_token: Secret<String>,
+    /// Whether the provider accepts SMTPUTF8 (internationalized) addresses.
+    smtputf8: bool,
 }
 
 impl EmailClient {
@@ -15,6 +17,7 @@ impl EmailClient {
         sender: SubscriberEmail,
         authorization_token: Secret<String>,
         timeout: std::time::Duration,
+        smtputf8: bool,
     ) -> Self {
         let http_client = reqwest::Client::builder().timeout(timeout).build().unwrap();
         Self {
@@ -22,6 +25,25 @@ impl EmailClient {
             base_url,
             http_client,
             authorization_token,
+            smtputf8,
+        }
+    }
+
+    /// Whether the provider can deliver to the address: those with a
+    /// non-ASCII local part need SMTPUTF8.
+    pub fn can_deliver_to(&self, email: &SubscriberEmail) -> bool {
+        self.smtputf8 || !email.requires_smtputf8()
+    }
+
+    /// Addresses go out with Unicode domains when the provider supports
+    /// SMTPUTF8, and with their punycode domains otherwise.
+    fn address(&self, email: &SubscriberEmail) -> Result<String, SendEmailError> {
+        if self.smtputf8 {
+            Ok(email.to_unicode())
+        } else if email.requires_smtputf8() {
+            Err(SendEmailError::Smtputf8Unsupported)
+        } else {
+            Ok(email.as_ref().to_string())
         }
     }
 
@@ -31,11 +53,13 @@ impl EmailClient {
         subject: &str,
         html_body: &str,
         text_body: &str,
-    ) -> Result<(), reqwest::Error> {
+    ) -> Result<(), SendEmailError> {
         let url = format!("{}/email", self.base_url);
+        let from = self.address(&self.sender)?;
+        let to = self.address(recipient)?;
         let send_email_request = SendEmailRequest {
-            from: self.sender.as_ref(),
-            to: recipient.as_ref(),
+            from: &from,
+            to: &to,
             subject,
             html_body,
             text_body,
@@ -54,6 +78,14 @@ impl EmailClient {
     }
 }
 
+#[derive(thiserror::Error, Debug)]
+pub enum SendEmailError {
+    #[error("The address requires SMTPUTF8, which the email provider does not support.")]
+    Smtputf8Unsupported,
+    #[error(transparent)]
+    Request(#[from] reqwest::Error),
+}
+
 #[derive(serde::Serialize)]
 #[serde(rename_all = "PascalCase")]
 struct SendEmailRequest<'a> {
@@ -66,7 +98,10 @@ struct SendEmailRequest<'a> {
 
 #[cfg(test)]
 mod tests {
-    use crate::{domain::SubscriberEmail, email_client::EmailClient};
+    use crate::{
+        domain::SubscriberEmail,
+        email_client::{EmailClient, SendEmailError},
+    };
     use claims::{assert_err, assert_ok};
     use fake::{
         faker::{
@@ -76,7 +111,7 @@ mod tests {
         Fake, Faker,
     };
     use wiremock::{
-        matchers::{any, header, header_exists, method, path},
+        matchers::{any, body_partial_json, header, header_exists, method, path},
         Mock, MockServer, ResponseTemplate,
     };
 
@@ -115,6 +150,7 @@ mod tests {
             email(),
             secrecy::Secret::new(Faker.fake()),
             std::time::Duration::from_millis(200),
+            false,
         )
     }
 
@@ -193,4 +229,71 @@ mod tests {
 
         assert_err!(send_email);
     }
+
+    #[tokio::test]
+    async fn send_email_falls_back_to_punycode_domains() {
+        let server = MockServer::start().await;
+        Mock::given(body_partial_json(
+            serde_json::json!({ "To": "ursula@xn--e1afmkfd.xn--p1ai" }),
+        ))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+        let recipient = SubscriberEmail::parse("ursula@пример.рф".into()).unwrap();
+        let send_email = email_client(&server.uri())
+            .send_email(&recipient, &subject(), &body(), &body())
+            .await;
+
+        assert_ok!(send_email);
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_for_addresses_requiring_unsupported_smtputf8() {
+        let server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&server)
+            .await;
+
+        let recipient = SubscriberEmail::parse("пользователь@пример.рф".into()).unwrap();
+        let email_client = email_client(&server.uri());
+        let send_email = email_client
+            .send_email(&recipient, &subject(), &body(), &body())
+            .await;
+
+        assert!(!email_client.can_deliver_to(&recipient));
+        assert!(matches!(
+            send_email,
+            Err(SendEmailError::Smtputf8Unsupported)
+        ));
+    }
+
+    #[tokio::test]
+    async fn send_email_keeps_unicode_domains_when_the_provider_supports_smtputf8() {
+        let server = MockServer::start().await;
+        Mock::given(body_partial_json(
+            serde_json::json!({ "To": "пользователь@пример.рф" }),
+        ))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+        let email_client = EmailClient::new(
+            server.uri(),
+            email(),
+            secrecy::Secret::new(Faker.fake()),
+            std::time::Duration::from_millis(200),
+            true,
+        );
+        let recipient = SubscriberEmail::parse("пользователь@пример.рф".into()).unwrap();
+        let send_email = email_client
+            .send_email(&recipient, &subject(), &body(), &body())
+            .await;
+
+        assert_ok!(send_email);
+    }
 }
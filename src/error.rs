@@ -0,0 +1,256 @@
+use std::{fmt::Display, future::Future};
+
+use actix_web::{
+    dev::{Service, ServiceRequest, ServiceResponse},
+    http::{
+        header::{self, HeaderName, HeaderValue},
+        StatusCode,
+    },
+    HttpMessage, HttpResponse, ResponseError,
+};
+use tracing_actix_web::RequestId;
+
+use crate::{
+    bot_protection::BotDetected,
+    domain::{SubscriberEmailError, ValidationError},
+    email_client::SendEmailError,
+    signed_links::InvalidToken,
+};
+
+pub const CORRELATION_ID_HEADER: &str = "x-correlation-id";
+const UNEXPECTED_ERROR_DETAIL: &str =
+    "An unexpected error occurred. Please quote the correlation id when reporting it.";
+
+tokio::task_local! {
+    static REQUEST_CONTEXT: RequestContext;
+}
+
+/// What error responses need to know about the request they answer.
+#[derive(Clone)]
+struct RequestContext {
+    correlation_id: String,
+    instance: String,
+}
+
+/// Makes the request's correlation id, the id `TracingLogger` tags its logs
+/// with, available to error responses and returns it in a header.
+pub fn propagate_request_context<S, B>(
+    request: ServiceRequest,
+    service: &S,
+) -> impl Future<Output = Result<ServiceResponse<B>, actix_web::Error>>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+{
+    let correlation_id = request
+        .extensions()
+        .get::<RequestId>()
+        .map_or_else(|| uuid::Uuid::new_v4().to_string(), ToString::to_string);
+    let header = HeaderValue::from_str(&correlation_id).ok();
+    let context = RequestContext {
+        correlation_id,
+        instance: request.path().to_string(),
+    };
+    let response = REQUEST_CONTEXT.scope(context, service.call(request));
+
+    async move {
+        let mut response = response.await?;
+        if let Some(header) = header {
+            response
+                .headers_mut()
+                .insert(HeaderName::from_static(CORRELATION_ID_HEADER), header);
+        }
+        Ok(response)
+    }
+}
+
+/// An invalid input, reported next to the others so that a form can flag
+/// every one of them at once.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct FieldError {
+    pub field: String,
+    pub code: &'static str,
+    pub message: String,
+}
+
+/// Collects the outcome of parsing each field of a request, so that all
+/// invalid fields are reported together instead of only the first one.
+#[derive(Debug, Default)]
+pub struct FieldErrors(Vec<FieldError>);
+
+impl FieldErrors {
+    pub fn check<T>(
+        &mut self,
+        field: impl Into<String>,
+        result: Result<T, impl Into<ValidationError>>,
+    ) -> Option<T> {
+        match result {
+            Ok(value) => Some(value),
+            Err(error) => {
+                self.push(field, error.into());
+                None
+            }
+        }
+    }
+
+    pub fn push(&mut self, field: impl Into<String>, error: ValidationError) {
+        self.0.push(FieldError {
+            field: field.into(),
+            code: error.code,
+            message: error.message,
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn into_vec(self) -> Vec<FieldError> {
+        self.0
+    }
+}
+
+/// An RFC 7807 `application/problem+json` error body.
+#[derive(Debug, serde::Serialize)]
+pub struct ProblemDetails {
+    #[serde(rename = "type")]
+    problem_type: &'static str,
+    title: &'static str,
+    status: u16,
+    detail: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    instance: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    correlation_id: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<FieldError>,
+}
+
+impl ProblemDetails {
+    /// Server errors never expose their message, which may describe
+    /// internals; it is in the logs under the correlation id instead.
+    pub fn new(status: StatusCode, error: &dyn Display) -> Self {
+        let detail = if status.is_server_error() {
+            UNEXPECTED_ERROR_DETAIL.to_string()
+        } else {
+            error.to_string()
+        };
+        let context = REQUEST_CONTEXT.try_with(RequestContext::clone).ok();
+        Self {
+            problem_type: "about:blank",
+            title: status.canonical_reason().unwrap_or("Error"),
+            status: status.as_u16(),
+            detail,
+            instance: context.as_ref().map(|context| context.instance.clone()),
+            correlation_id: context.map(|context| context.correlation_id),
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn with_errors(mut self, errors: Vec<FieldError>) -> Self {
+        self.errors = errors;
+        self
+    }
+
+    pub fn into_response(self) -> HttpResponse {
+        HttpResponse::build(
+            StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+        )
+        .content_type("application/problem+json")
+        .json(self)
+    }
+}
+
+/// Renders any route error as problem details.
+pub fn problem_response(error: &impl ResponseError) -> HttpResponse {
+    ProblemDetails::new(error.status_code(), error).into_response()
+}
+
+/// Errors of the routes, and of requests that could not be extracted.
+#[derive(thiserror::Error, Debug)]
+pub enum AppError {
+    #[error("Failed to query.")]
+    DatabaseError(#[from] sqlx::Error),
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("{}", describe_field_errors(.0))]
+    InvalidFields(Vec<FieldError>),
+    #[error(transparent)]
+    BotDetected(#[from] BotDetected),
+    #[error("{0}")]
+    InvalidLink(#[from] InvalidToken),
+    #[error("Missing or invalid admin credentials.")]
+    Unauthorized,
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{0}")]
+    Conflict(String),
+    #[error("Failed to send an email.")]
+    SendEmailError(#[from] SendEmailError),
+}
+
+fn describe_field_errors(errors: &[FieldError]) -> String {
+    errors
+        .iter()
+        .map(|error| format!("{}: {}", error.field, error.message))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+impl From<String> for AppError {
+    fn from(value: String) -> Self {
+        Self::ValidationError(value)
+    }
+}
+
+impl From<ValidationError> for AppError {
+    fn from(value: ValidationError) -> Self {
+        Self::ValidationError(value.message)
+    }
+}
+
+impl From<SubscriberEmailError> for AppError {
+    fn from(value: SubscriberEmailError) -> Self {
+        Self::ValidationError(value.to_string())
+    }
+}
+
+impl ResponseError for AppError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::ValidationError(_)
+            | Self::InvalidFields(_)
+            | Self::BotDetected(_)
+            | Self::InvalidLink(_) => StatusCode::BAD_REQUEST,
+            Self::Unauthorized => StatusCode::UNAUTHORIZED,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::Conflict(_) => StatusCode::CONFLICT,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            Self::InvalidFields(errors) => ProblemDetails::new(self.status_code(), self)
+                .with_errors(errors.clone())
+                .into_response(),
+            Self::Unauthorized => {
+                let mut response = problem_response(self);
+                response
+                    .headers_mut()
+                    .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+                response
+            }
+            _ => problem_response(self),
+        }
+    }
+}
+
+/// Answers requests to unknown routes.
+pub async fn not_found() -> Result<HttpResponse, AppError> {
+    Err(AppError::NotFound("No such resource.".to_string()))
+}
+
+/// Reports bodies, queries and paths that cannot be deserialized.
+pub fn extractor_error(error: impl Display) -> actix_web::Error {
+    AppError::ValidationError(error.to_string()).into()
+}
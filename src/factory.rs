@@ -1,8 +1,20 @@
+use std::{sync::Arc, time::Duration};
+
 use crate::{
-    configuration::{self, EmailClientSettings},
+    bot_protection::{BotProtection, HumanVerifier, ProofOfWork},
+    configuration::{
+        self, BotProtectionSettings, EmailClientSettings, EmailPolicySettings, LimitSettings,
+        RateLimitSettings, RateLimitStoreKind,
+    },
     db,
     domain::SubscriberEmail,
     email_client::EmailClient,
+    mail_domain::{DnsResolver, MailDomainCheck, MailDomainResolver},
+    rate_limit::{
+        InMemoryStore, PostgresStore, RateLimit, RateLimitStore, RateLimiter, TrustedProxies,
+    },
+    signed_links::SignedLinks,
+    suppression::EmailHasher,
 };
 use sqlx::{Connection, Database, PgConnection, PgPool, Pool};
 
@@ -12,6 +24,63 @@ pub fn get_email_client(email_client: &EmailClientSettings) -> EmailClient {
         SubscriberEmail::parse(email_client.sender_email.clone()).expect("Valid email for sender"),
         email_client.authorization_token.clone(),
         std::time::Duration::from_millis(email_client.timeout_millis),
+        email_client.smtputf8,
+    )
+}
+
+pub fn get_mail_domain_check(email_policy: &EmailPolicySettings) -> MailDomainCheck {
+    let resolver = email_policy
+        .check_mx
+        .then(|| Arc::new(DnsResolver::from_system_conf()) as Arc<dyn MailDomainResolver>);
+    MailDomainCheck::new(
+        resolver,
+        Duration::from_millis(email_policy.mx_timeout_millis),
+        Duration::from_secs(email_policy.mx_cache_ttl_secs),
+    )
+}
+
+pub fn get_bot_protection(
+    bot_protection: &BotProtectionSettings,
+    signed_links: SignedLinks,
+    used_tokens: Arc<dyn RateLimitStore>,
+) -> BotProtection {
+    let verifier = bot_protection
+        .proof_of_work_bits
+        .map(|bits| Arc::new(ProofOfWork::new(bits)) as Arc<dyn HumanVerifier>);
+    BotProtection::new(
+        signed_links,
+        bot_protection.require_form_token,
+        chrono::Duration::seconds(bot_protection.min_fill_secs as i64),
+        verifier,
+        used_tokens,
+    )
+}
+
+pub fn get_rate_limit_store(
+    rate_limit: &RateLimitSettings,
+    pool: PgPool,
+) -> Arc<dyn RateLimitStore> {
+    match rate_limit.store {
+        RateLimitStoreKind::Memory => Arc::new(InMemoryStore::default()),
+        RateLimitStoreKind::Postgres => Arc::new(PostgresStore::new(pool)),
+    }
+}
+
+pub fn get_rate_limiter(
+    rate_limit: &RateLimitSettings,
+    store: Arc<dyn RateLimitStore>,
+    email_hasher: EmailHasher,
+) -> RateLimiter {
+    let limit = |limit: &LimitSettings| RateLimit {
+        max_requests: limit.max_requests,
+        window: Duration::from_secs(limit.window_secs),
+    };
+    RateLimiter::new(
+        store,
+        limit(&rate_limit.per_ip),
+        limit(&rate_limit.per_email),
+        TrustedProxies::new(rate_limit.trusted_proxies.clone()),
+        email_hasher,
     )
 }
 
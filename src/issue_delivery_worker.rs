@@ -0,0 +1,235 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    domain::{custom_field_to_template_value, render_merge_tags, SubscriberEmail},
+    email_client::EmailClient,
+    signed_links::SignedLinks,
+};
+
+const EMPTY_QUEUE_BACKOFF: Duration = Duration::from_secs(5);
+const ERROR_BACKOFF: Duration = Duration::from_secs(1);
+
+pub enum ExecutionOutcome {
+    TaskCompleted,
+    EmptyQueue,
+}
+
+pub async fn run_worker_until_stopped(
+    pool: PgPool,
+    email_client: Arc<EmailClient>,
+    signed_links: SignedLinks,
+) {
+    loop {
+        match try_execute_task(&pool, &email_client, &signed_links).await {
+            Ok(ExecutionOutcome::EmptyQueue) => tokio::time::sleep(EMPTY_QUEUE_BACKOFF).await,
+            Ok(ExecutionOutcome::TaskCompleted) => {}
+            Err(_) => tokio::time::sleep(ERROR_BACKOFF).await,
+        }
+    }
+}
+
+#[tracing::instrument(
+    skip_all,
+    fields(newsletter_issue_id=tracing::field::Empty, subscriber_email=tracing::field::Empty),
+    err
+)]
+pub async fn try_execute_task(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    signed_links: &SignedLinks,
+) -> Result<ExecutionOutcome, sqlx::Error> {
+    let Some((mut transaction, newsletter_issue_id, email)) = dequeue_task(pool).await? else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    tracing::Span::current()
+        .record(
+            "newsletter_issue_id",
+            tracing::field::display(newsletter_issue_id),
+        )
+        .record("subscriber_email", tracing::field::display(&email));
+
+    let outcome = match SubscriberEmail::parse(email.clone()) {
+        Ok(recipient) => {
+            let issue = get_issue(pool, newsletter_issue_id).await?;
+            let variables = get_template_variables(pool, signed_links, &email).await?;
+            match email_client
+                .send_email(
+                    &recipient,
+                    &issue.title,
+                    &render_merge_tags(&issue.html_content, &variables, true),
+                    &render_merge_tags(&issue.text_content, &variables, false),
+                )
+                .await
+            {
+                Ok(()) => Ok(()),
+                Err(e) => {
+                    tracing::error!(
+                        error.cause_chain = ?e,
+                        "Failed to deliver issue to a confirmed subscriber. Skipping.",
+                    );
+                    Err(e.to_string())
+                }
+            }
+        }
+        Err(e) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                "Skipping a confirmed subscriber. Their stored contact details are invalid.",
+            );
+            Err(e.to_string())
+        }
+    };
+    record_email_event(&mut transaction, newsletter_issue_id, &email, outcome).await?;
+    delete_task(&mut transaction, newsletter_issue_id, &email).await?;
+    transaction.commit().await?;
+
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+type PgTransaction = Transaction<'static, Postgres>;
+
+#[tracing::instrument(skip_all)]
+async fn dequeue_task(pool: &PgPool) -> Result<Option<(PgTransaction, Uuid, String)>, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let task = sqlx::query!(
+        r#"
+        SELECT newsletter_issue_id, subscriber_email
+        FROM issue_delivery_queue
+        WHERE execute_after <= now()
+        FOR UPDATE
+        SKIP LOCKED
+        LIMIT 1
+        "#,
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+
+    Ok(task.map(|task| (transaction, task.newsletter_issue_id, task.subscriber_email)))
+}
+
+#[tracing::instrument(skip_all)]
+async fn delete_task(
+    transaction: &mut PgTransaction,
+    newsletter_issue_id: Uuid,
+    email: &str,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_queue
+        WHERE newsletter_issue_id = $1 AND subscriber_email = $2
+        "#,
+        newsletter_issue_id,
+        email
+    );
+    transaction.execute(query).await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn record_email_event(
+    transaction: &mut PgTransaction,
+    newsletter_issue_id: Uuid,
+    email: &str,
+    outcome: Result<(), String>,
+) -> Result<(), sqlx::Error> {
+    let (event_type, detail) = match outcome {
+        Ok(()) => ("delivered", None),
+        Err(detail) => ("failed", Some(detail)),
+    };
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO email_events (subscriber_id, newsletter_issue_id, event_type, detail)
+        SELECT id, $2, $3, $4 FROM subscriptions WHERE email = $1
+        "#,
+        email,
+        newsletter_issue_id,
+        event_type,
+        detail
+    );
+    transaction.execute(query).await?;
+
+    Ok(())
+}
+
+struct IssueContent {
+    title: String,
+    text_content: String,
+    html_content: String,
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_issue(pool: &PgPool, newsletter_issue_id: Uuid) -> Result<IssueContent, sqlx::Error> {
+    let issue = sqlx::query_as!(
+        IssueContent,
+        r#"
+        SELECT title, text_content, html_content
+        FROM newsletter_issues
+        WHERE id = $1
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(issue)
+}
+
+/// Merge tag values for a single recipient. Custom fields the subscriber has
+/// not filled in render as empty strings.
+#[tracing::instrument(skip_all)]
+async fn get_template_variables(
+    pool: &PgPool,
+    signed_links: &SignedLinks,
+    email: &str,
+) -> Result<HashMap<String, String>, sqlx::Error> {
+    let variables = get_subscriber_template_variables(pool, signed_links, email)
+        .await?
+        .unwrap_or_else(|| HashMap::from([("subscriber.email".to_string(), email.to_string())]));
+    Ok(variables)
+}
+
+/// Merge tag values for the subscriber with `email`, if there is one.
+#[tracing::instrument(skip_all)]
+pub async fn get_subscriber_template_variables(
+    pool: &PgPool,
+    signed_links: &SignedLinks,
+    email: &str,
+) -> Result<Option<HashMap<String, String>>, sqlx::Error> {
+    let subscriber = sqlx::query!(
+        "SELECT id, name, custom_fields FROM subscriptions WHERE email = $1",
+        email
+    )
+    .fetch_optional(pool)
+    .await?;
+    let Some(subscriber) = subscriber else {
+        return Ok(None);
+    };
+
+    let mut variables = HashMap::from([
+        ("subscriber.email".to_string(), email.to_string()),
+        ("subscriber.name".to_string(), subscriber.name),
+        (
+            "unsubscribe_url".to_string(),
+            signed_links.unsubscribe_url(subscriber.id),
+        ),
+    ]);
+    let custom_field_keys = sqlx::query!("SELECT key FROM custom_fields")
+        .fetch_all(pool)
+        .await?;
+    for field in custom_field_keys {
+        variables.insert(format!("subscriber.{}", field.key), String::new());
+    }
+    if let serde_json::Value::Object(fields) = subscriber.custom_fields {
+        for (key, value) in fields {
+            variables.insert(
+                format!("subscriber.{}", key),
+                custom_field_to_template_value(&value),
+            );
+        }
+    }
+    Ok(Some(variables))
+}
@@ -1,8 +1,18 @@
+pub mod admin_auth;
+pub mod bot_protection;
 pub mod configuration;
 pub mod db;
 pub mod domain;
 pub mod email_client;
+pub mod error;
 pub mod factory;
+pub mod issue_delivery_worker;
+pub mod mail_domain;
+pub mod newsletter_scheduler;
+pub mod rate_limit;
 pub mod routes;
+pub mod segment;
+pub mod signed_links;
 pub mod startup;
+pub mod suppression;
 pub mod telemetry;
@@ -0,0 +1,288 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use hickory_resolver::{error::ResolveErrorKind, TokioAsyncResolver};
+
+/// How many domains the check remembers answers for.
+const CACHE_CAPACITY: usize = 10_000;
+
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub struct ResolveError(String);
+
+/// Finds out whether a domain can receive mail.
+#[async_trait::async_trait]
+pub trait MailDomainResolver: Send + Sync {
+    async fn accepts_mail(&self, domain: &str) -> Result<bool, ResolveError>;
+}
+
+/// Looks up MX records, falling back to A/AAAA records for domains without
+/// any (RFC 5321, section 5.1).
+pub struct DnsResolver(TokioAsyncResolver);
+
+impl DnsResolver {
+    /// Uses the system's resolver configuration, or public resolvers if it
+    /// cannot be read.
+    pub fn from_system_conf() -> Self {
+        let resolver = TokioAsyncResolver::tokio_from_system_conf().unwrap_or_else(|e| {
+            tracing::warn!("Failed to read the system resolver configuration: {}", e);
+            TokioAsyncResolver::tokio(Default::default(), Default::default())
+        });
+        Self(resolver)
+    }
+}
+
+#[async_trait::async_trait]
+impl MailDomainResolver for DnsResolver {
+    async fn accepts_mail(&self, domain: &str) -> Result<bool, ResolveError> {
+        // Trailing dot: the domain is fully qualified, no search domains.
+        let domain = format!("{domain}.");
+        match self.0.mx_lookup(domain.as_str()).await {
+            // A single MX of `.` means the domain accepts no mail (RFC 7505).
+            Ok(mx) => Ok(mx.iter().any(|mx| !mx.exchange().is_root())),
+            Err(e) if matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. }) => {
+                match self.0.lookup_ip(domain.as_str()).await {
+                    Ok(ips) => Ok(ips.iter().next().is_some()),
+                    Err(e) if matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. }) => {
+                        Ok(false)
+                    }
+                    Err(e) => Err(ResolveError(e.to_string())),
+                }
+            }
+            Err(e) => Err(ResolveError(e.to_string())),
+        }
+    }
+}
+
+/// Answers from a fixed table, for tests. Unknown domains cannot receive
+/// mail.
+#[derive(Default)]
+pub struct StaticResolver {
+    answers: HashMap<String, Result<bool, String>>,
+    delay: Option<Duration>,
+    lookups: AtomicUsize,
+}
+
+impl StaticResolver {
+    pub fn new<'a>(domains: impl IntoIterator<Item = &'a str>) -> Self {
+        Self {
+            answers: domains
+                .into_iter()
+                .map(|domain| (domain.to_string(), Ok(true)))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Makes lookups of `domain` fail.
+    pub fn failing(mut self, domain: &str) -> Self {
+        self.answers
+            .insert(domain.to_string(), Err("SERVFAIL".to_string()));
+        self
+    }
+
+    /// Makes every lookup take `delay`.
+    pub fn delayed(mut self, delay: Duration) -> Self {
+        self.delay = Some(delay);
+        self
+    }
+
+    pub fn lookups(&self) -> usize {
+        self.lookups.load(Ordering::SeqCst)
+    }
+}
+
+#[async_trait::async_trait]
+impl MailDomainResolver for StaticResolver {
+    async fn accepts_mail(&self, domain: &str) -> Result<bool, ResolveError> {
+        self.lookups.fetch_add(1, Ordering::SeqCst);
+        if let Some(delay) = self.delay {
+            tokio::time::sleep(delay).await;
+        }
+        match self.answers.get(domain) {
+            Some(answer) => answer.clone().map_err(ResolveError),
+            None => Ok(false),
+        }
+    }
+}
+
+/// The optional signup check that an address's domain can receive mail.
+/// Answers are cached, up to `CACHE_CAPACITY` domains; lookups that fail or
+/// time out accept the domain, so that a DNS outage does not turn subscribers
+/// away.
+#[derive(Clone)]
+pub struct MailDomainCheck {
+    resolver: Option<Arc<dyn MailDomainResolver>>,
+    timeout: Duration,
+    cache_ttl: Duration,
+    cache: Arc<Mutex<HashMap<String, (bool, Instant)>>>,
+    cache_capacity: usize,
+}
+
+impl MailDomainCheck {
+    /// Without a resolver, every domain is accepted.
+    pub fn new(
+        resolver: Option<Arc<dyn MailDomainResolver>>,
+        timeout: Duration,
+        cache_ttl: Duration,
+    ) -> Self {
+        Self {
+            resolver,
+            timeout,
+            cache_ttl,
+            cache: Default::default(),
+            cache_capacity: CACHE_CAPACITY,
+        }
+    }
+
+    pub fn with_resolver(self, resolver: Arc<dyn MailDomainResolver>) -> Self {
+        Self::new(Some(resolver), self.timeout, self.cache_ttl)
+    }
+
+    #[tracing::instrument(name = "Checking that a domain can receive mail.", skip(self))]
+    pub async fn accepts_mail(&self, domain: &str) -> bool {
+        let Some(resolver) = &self.resolver else {
+            return true;
+        };
+        if let Some(&(accepts, checked_at)) = self.cache.lock().unwrap().get(domain) {
+            if checked_at.elapsed() < self.cache_ttl {
+                return accepts;
+            }
+        }
+
+        match tokio::time::timeout(self.timeout, resolver.accepts_mail(domain)).await {
+            Ok(Ok(accepts)) => {
+                self.cache_answer(domain, accepts);
+                accepts
+            }
+            Ok(Err(e)) => {
+                tracing::warn!("Failed to look up the mail servers of {}: {}", domain, e);
+                true
+            }
+            Err(_) => {
+                tracing::warn!("Timed out looking up the mail servers of {}.", domain);
+                true
+            }
+        }
+    }
+
+    /// Makes room by dropping expired answers, then the oldest one.
+    fn cache_answer(&self, domain: &str, accepts: bool) {
+        let mut cache = self.cache.lock().unwrap();
+        if cache.len() >= self.cache_capacity && !cache.contains_key(domain) {
+            cache.retain(|_, (_, checked_at)| checked_at.elapsed() < self.cache_ttl);
+            if cache.len() >= self.cache_capacity {
+                let oldest = cache
+                    .iter()
+                    .min_by_key(|(_, (_, checked_at))| *checked_at)
+                    .map(|(domain, _)| domain.clone());
+                if let Some(oldest) = oldest {
+                    cache.remove(&oldest);
+                }
+            }
+        }
+        cache.insert(domain.to_string(), (accepts, Instant::now()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use super::{MailDomainCheck, StaticResolver};
+
+    fn check(resolver: &Arc<StaticResolver>) -> MailDomainCheck {
+        MailDomainCheck::new(
+            Some(resolver.clone()),
+            Duration::from_millis(100),
+            Duration::from_secs(60),
+        )
+    }
+
+    #[tokio::test]
+    async fn answers_come_from_the_resolver() {
+        let resolver = Arc::new(StaticResolver::new(["example.com"]));
+        let check = check(&resolver);
+
+        assert!(check.accepts_mail("example.com").await);
+        assert!(!check.accepts_mail("no-mail.example").await);
+    }
+
+    #[tokio::test]
+    async fn answers_are_cached() {
+        let resolver = Arc::new(StaticResolver::new(["example.com"]));
+        let check = check(&resolver);
+
+        for _ in 0..3 {
+            assert!(check.accepts_mail("example.com").await);
+            assert!(!check.accepts_mail("no-mail.example").await);
+        }
+
+        assert_eq!(resolver.lookups(), 2);
+    }
+
+    #[tokio::test]
+    async fn expired_answers_are_looked_up_again() {
+        let resolver = Arc::new(StaticResolver::new(["example.com"]));
+        let check = MailDomainCheck::new(
+            Some(resolver.clone()),
+            Duration::from_millis(100),
+            Duration::ZERO,
+        );
+
+        check.accepts_mail("example.com").await;
+        check.accepts_mail("example.com").await;
+
+        assert_eq!(resolver.lookups(), 2);
+    }
+
+    #[tokio::test]
+    async fn the_cache_drops_the_oldest_answers_when_full() {
+        let resolver = Arc::new(StaticResolver::new(["a.example", "b.example"]));
+        let mut check = check(&resolver);
+        check.cache_capacity = 2;
+
+        for domain in ["a.example", "b.example", "c.example"] {
+            check.accepts_mail(domain).await;
+        }
+        assert_eq!(check.cache.lock().unwrap().len(), 2);
+
+        check.accepts_mail("b.example").await;
+        assert_eq!(resolver.lookups(), 3);
+        check.accepts_mail("a.example").await;
+        assert_eq!(resolver.lookups(), 4);
+        assert_eq!(check.cache.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn failed_lookups_accept_the_domain_and_are_not_cached() {
+        let resolver = Arc::new(StaticResolver::default().failing("example.com"));
+        let check = check(&resolver);
+
+        assert!(check.accepts_mail("example.com").await);
+        assert!(check.accepts_mail("example.com").await);
+
+        assert_eq!(resolver.lookups(), 2);
+    }
+
+    #[tokio::test]
+    async fn slow_lookups_accept_the_domain() {
+        let resolver = Arc::new(StaticResolver::default().delayed(Duration::from_secs(10)));
+        let check = check(&resolver);
+
+        assert!(check.accepts_mail("no-mail.example").await);
+    }
+
+    #[tokio::test]
+    async fn a_check_without_resolver_accepts_every_domain() {
+        let check = MailDomainCheck::new(None, Duration::ZERO, Duration::ZERO);
+
+        assert!(check.accepts_mail("no-mail.example").await);
+    }
+}
@@ -6,6 +6,6 @@ use zero2prod::telemetry;
 async fn main() -> Result<(), std::io::Error> {
     telemetry::init("zero2prod", "info", std::io::stdout);
     let build = NewsletterApp::build(configuration::get_configuration()).await?;
-    build.run()?.await?;
+    build.run_until_stopped().await?;
     Ok(())
 }
@@ -0,0 +1,167 @@
+use std::time::Duration;
+
+use sqlx::{PgPool, Postgres, QueryBuilder, Transaction};
+use uuid::Uuid;
+
+use crate::segment::Segment;
+
+const NO_DUE_ISSUE_BACKOFF: Duration = Duration::from_secs(10);
+const ERROR_BACKOFF: Duration = Duration::from_secs(1);
+
+pub enum SchedulingOutcome {
+    IssuePublished(Uuid),
+    IssueFailed(Uuid),
+    NoDueIssue,
+}
+
+pub async fn run_scheduler_until_stopped(pool: PgPool) {
+    loop {
+        match try_publish_due_issue(&pool).await {
+            Ok(SchedulingOutcome::NoDueIssue) => tokio::time::sleep(NO_DUE_ISSUE_BACKOFF).await,
+            Ok(SchedulingOutcome::IssuePublished(_) | SchedulingOutcome::IssueFailed(_)) => {}
+            Err(_) => tokio::time::sleep(ERROR_BACKOFF).await,
+        }
+    }
+}
+
+/// Promotes at most one due scheduled issue into the delivery queue.
+///
+/// The issue row stays locked until its delivery tasks are committed, so
+/// concurrent replicas skip it and it can only ever be published once. An
+/// issue that cannot be published for reasons a retry would not fix is
+/// marked as failed, so that it does not hold up the issues due after it.
+#[tracing::instrument(skip_all, err)]
+pub async fn try_publish_due_issue(pool: &PgPool) -> Result<SchedulingOutcome, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let due_issue = sqlx::query!(
+        r#"
+        SELECT id
+        FROM newsletter_issues
+        WHERE status = 'scheduled' AND scheduled_for <= now()
+        ORDER BY scheduled_for
+        FOR UPDATE
+        SKIP LOCKED
+        LIMIT 1
+        "#,
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+
+    let Some(due_issue) = due_issue else {
+        return Ok(SchedulingOutcome::NoDueIssue);
+    };
+
+    if let Err(e) = publish_issue(&mut transaction, due_issue.id).await {
+        transaction.rollback().await?;
+        if !is_permanent(&e) {
+            return Err(e);
+        }
+        tracing::error!(
+            newsletter_issue_id = %due_issue.id,
+            "Failed to publish scheduled newsletter issue: {}", e
+        );
+        sqlx::query!(
+            r#"
+            UPDATE newsletter_issues
+            SET status = 'failed', failure_reason = $2
+            WHERE id = $1 AND status = 'scheduled'
+            "#,
+            due_issue.id,
+            e.to_string()
+        )
+        .execute(pool)
+        .await?;
+        return Ok(SchedulingOutcome::IssueFailed(due_issue.id));
+    }
+    transaction.commit().await?;
+    tracing::info!(newsletter_issue_id = %due_issue.id, "Published scheduled newsletter issue.");
+
+    Ok(SchedulingOutcome::IssuePublished(due_issue.id))
+}
+
+/// Whether publishing an issue failed in a way that retrying would not fix:
+/// a segment that cannot be read, or data the database rejects.
+fn is_permanent(error: &sqlx::Error) -> bool {
+    match error {
+        sqlx::Error::Decode(_) | sqlx::Error::ColumnDecode { .. } => true,
+        // Data exceptions and integrity constraint violations.
+        sqlx::Error::Database(e) => e
+            .code()
+            .is_some_and(|code| code.starts_with("22") || code.starts_with("23")),
+        _ => false,
+    }
+}
+
+/// Marks a draft, scheduled or failed issue as published and enqueues a delivery task
+/// for every subscriber confirmed on at least one of the issue's lists.
+/// Returns `false` if the issue cannot be published, i.e. it does not exist
+/// or has already been published.
+///
+/// Issues with a local delivery time release each task once that wall-clock
+/// time is reached in the subscriber's timezone, falling back to UTC.
+/// Subscribers who chose a daily or weekly digest get the issues of a day or
+/// week together, at the start of the next day or week in their timezone.
+#[tracing::instrument(name = "Publishing newsletter issue.", skip(transaction))]
+pub async fn publish_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let published = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET status = 'published', published_at = now(), scheduled_for = NULL,
+            failure_reason = NULL
+        WHERE id = $1 AND status IN ('draft', 'scheduled', 'failed')
+        RETURNING segment
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_optional(&mut **transaction)
+    .await?;
+    let Some(published) = published else {
+        return Ok(false);
+    };
+    let segment = published
+        .segment
+        .map(serde_json::from_value::<Segment>)
+        .transpose()
+        .map_err(|e| sqlx::Error::Decode(Box::new(e)))?;
+
+    let mut query = QueryBuilder::<Postgres>::new(
+        r#"
+        INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email, execute_after)
+        SELECT DISTINCT
+            i.id,
+            s.email,
+            CASE s.digest_frequency
+                WHEN 'daily' THEN
+                    (date_trunc('day', d.due AT TIME ZONE z.tz) + interval '1 day') AT TIME ZONE z.tz
+                WHEN 'weekly' THEN
+                    (date_trunc('week', d.due AT TIME ZONE z.tz) + interval '1 week') AT TIME ZONE z.tz
+                ELSE d.due
+            END
+        FROM newsletter_issues i
+        JOIN newsletter_issue_lists il ON il.newsletter_issue_id = i.id
+        JOIN list_memberships m ON m.list_id = il.list_id AND m.status = 'confirmed'
+        JOIN subscriptions s ON s.id = m.subscriber_id
+        CROSS JOIN LATERAL (SELECT COALESCE(s.timezone, 'UTC') AS tz) z
+        CROSS JOIN LATERAL (
+            SELECT COALESCE(i.deliver_at_local AT TIME ZONE z.tz, now()) AS due
+        ) d
+        WHERE i.id = "#,
+    );
+    query.push_bind(newsletter_issue_id);
+    if let Some(segment) = segment {
+        query.push(" AND ");
+        segment.push_condition(&mut query);
+    }
+    query
+        .build()
+        .execute(&mut **transaction)
+        .await
+        .inspect_err(|e| {
+            tracing::error!("Failed to execute query {:?}.", e);
+        })?;
+
+    Ok(true)
+}
@@ -0,0 +1,497 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, VecDeque},
+    future::{ready, Future, Ready},
+    net::IpAddr,
+    pin::Pin,
+    rc::Rc,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use actix_web::{
+    body::EitherBody,
+    dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform},
+    http::{header, Method, StatusCode},
+    web, HttpMessage, HttpRequest,
+};
+use sqlx::PgPool;
+
+use crate::{domain::SubscriberEmail, error::ProblemDetails, suppression::EmailHasher};
+
+/// Requests that mail the address in their body, limited per address too.
+const EMAIL_LIMITED_PATHS: [&str; 3] = [
+    "/subscriptions",
+    "/subscriptions/preferences/link",
+    "/subscriptions/email",
+];
+/// How often expired hits are deleted from the Postgres store.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+    pub max_requests: u32,
+    pub window: Duration,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Decision {
+    Allowed,
+    Limited { retry_after: Duration },
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub struct StoreError(String);
+
+/// Counts requests per key over a sliding window.
+#[async_trait::async_trait]
+pub trait RateLimitStore: Send + Sync {
+    /// Records a request under `key`, unless `limit` is already reached.
+    async fn hit(&self, key: &str, limit: &RateLimit) -> Result<Decision, StoreError>;
+}
+
+/// Keeps the counts in this process, so each replica limits on its own.
+#[derive(Default)]
+pub struct InMemoryStore {
+    state: Mutex<InMemoryState>,
+}
+
+#[derive(Default)]
+struct InMemoryState {
+    /// When each counted request leaves the window, oldest first.
+    expiries: HashMap<String, VecDeque<Instant>>,
+    /// The same requests across keys, soonest to leave first, so that keys
+    /// can be dropped once idle without scanning the others.
+    by_expiry: BinaryHeap<Reverse<(Instant, String)>>,
+}
+
+impl InMemoryState {
+    fn drop_idle_keys(&mut self, now: Instant) {
+        while let Some(Reverse((expiry, _))) = self.by_expiry.peek() {
+            if *expiry > now {
+                break;
+            }
+            let Some(Reverse((_, key))) = self.by_expiry.pop() else {
+                break;
+            };
+            let idle = self
+                .expiries
+                .get(&key)
+                .is_some_and(|key_expiries| key_expiries.iter().all(|t| *t <= now));
+            if idle {
+                self.expiries.remove(&key);
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl RateLimitStore for InMemoryStore {
+    async fn hit(&self, key: &str, limit: &RateLimit) -> Result<Decision, StoreError> {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        state.drop_idle_keys(now);
+
+        let key_expiries = state.expiries.entry(key.to_string()).or_default();
+        while key_expiries.front().is_some_and(|t| *t <= now) {
+            key_expiries.pop_front();
+        }
+        if key_expiries.len() >= limit.max_requests as usize {
+            let retry_after = key_expiries.front().map_or(limit.window, |t| *t - now);
+            return Ok(Decision::Limited { retry_after });
+        }
+        key_expiries.push_back(now + limit.window);
+        state
+            .by_expiry
+            .push(Reverse((now + limit.window, key.to_string())));
+        Ok(Decision::Allowed)
+    }
+}
+
+/// Keeps the counts in Postgres, so that replicas share them.
+pub struct PostgresStore(PgPool);
+
+impl PostgresStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self(pool)
+    }
+
+    async fn try_hit(&self, key: &str, limit: &RateLimit) -> Result<Decision, sqlx::Error> {
+        let mut transaction = self.0.begin().await?;
+        // Serializes the requests of a key, which would otherwise all be
+        // counted before any of them is recorded.
+        sqlx::query!(
+            "SELECT 1 AS locked FROM (SELECT pg_advisory_xact_lock(hashtext($1))) AS lock",
+            key
+        )
+        .fetch_one(&mut *transaction)
+        .await?;
+        // Other keys are left to `run_sweeper_until_stopped`.
+        sqlx::query!(
+            "DELETE FROM rate_limit_hits WHERE key = $1 AND expires_at <= now()",
+            key
+        )
+        .execute(&mut *transaction)
+        .await?;
+        let hits = sqlx::query!(
+            r#"SELECT count(*) AS "count!",
+                EXTRACT(EPOCH FROM min(expires_at) - now())::float8 AS retry_after_secs
+            FROM rate_limit_hits WHERE key = $1"#,
+            key
+        )
+        .fetch_one(&mut *transaction)
+        .await?;
+        if hits.count >= i64::from(limit.max_requests) {
+            let retry_after = hits
+                .retry_after_secs
+                .map_or(limit.window, |secs| Duration::from_secs_f64(secs.max(0.0)));
+            return Ok(Decision::Limited { retry_after });
+        }
+        sqlx::query!(
+            "INSERT INTO rate_limit_hits (key, expires_at)
+            VALUES ($1, now() + make_interval(secs => $2))",
+            key,
+            limit.window.as_secs_f64()
+        )
+        .execute(&mut *transaction)
+        .await?;
+        transaction.commit().await?;
+        Ok(Decision::Allowed)
+    }
+}
+
+/// Deletes the expired hits of keys that are not hit again, which would
+/// otherwise stay in the Postgres store.
+pub async fn run_sweeper_until_stopped(pool: PgPool) {
+    loop {
+        if let Err(e) = sweep_expired_hits(&pool).await {
+            tracing::error!("Failed to sweep expired rate limit hits: {}", e);
+        }
+        tokio::time::sleep(SWEEP_INTERVAL).await;
+    }
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn sweep_expired_hits(pool: &PgPool) -> Result<(), sqlx::Error> {
+    sqlx::query!("DELETE FROM rate_limit_hits WHERE expires_at <= now()")
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+#[async_trait::async_trait]
+impl RateLimitStore for PostgresStore {
+    async fn hit(&self, key: &str, limit: &RateLimit) -> Result<Decision, StoreError> {
+        self.try_hit(key, limit).await.map_err(|e| {
+            tracing::error!("Failed to execute query {:?}.", e);
+            StoreError(e.to_string())
+        })
+    }
+}
+
+/// Limits requests to the public `/subscriptions` endpoints per client IP
+/// and, for those that send mail, per target address. Answers requests over
+/// either limit with `429 Too Many Requests`.
+#[derive(Clone)]
+pub struct RateLimiter {
+    store: Arc<dyn RateLimitStore>,
+    per_ip: RateLimit,
+    per_email: RateLimit,
+    trusted_proxies: TrustedProxies,
+    /// Addresses are counted under their hash, so that the store keeps no
+    /// erased address.
+    email_hasher: EmailHasher,
+}
+
+impl RateLimiter {
+    pub fn new(
+        store: Arc<dyn RateLimitStore>,
+        per_ip: RateLimit,
+        per_email: RateLimit,
+        trusted_proxies: TrustedProxies,
+        email_hasher: EmailHasher,
+    ) -> Self {
+        Self {
+            store,
+            per_ip,
+            per_email,
+            trusted_proxies,
+            email_hasher,
+        }
+    }
+
+    /// How long the client must wait, if the request is over a limit.
+    async fn check(&self, request: &mut ServiceRequest) -> Option<Duration> {
+        if !request.path().starts_with("/subscriptions") {
+            return None;
+        }
+
+        if let Some(ip) = self.trusted_proxies.client_ip(request.request()) {
+            let retry_after = self.hit(&format!("ip:{ip}"), &self.per_ip).await;
+            if retry_after.is_some() {
+                return retry_after;
+            }
+        }
+
+        if request.method() == Method::POST && EMAIL_LIMITED_PATHS.contains(&request.path()) {
+            if let Some(email) = target_email(request).await {
+                let key = format!("email:{}", self.email_hasher.hash(&email));
+                return self.hit(&key, &self.per_email).await;
+            }
+        }
+        None
+    }
+
+    /// Lets the request through if the store fails: an outage of the store
+    /// must not take the endpoints down with it.
+    async fn hit(&self, key: &str, limit: &RateLimit) -> Option<Duration> {
+        match self.store.hit(key, limit).await {
+            Ok(Decision::Allowed) => None,
+            Ok(Decision::Limited { retry_after }) => Some(retry_after),
+            Err(e) => {
+                tracing::error!("Failed to check the rate limit: {}", e);
+                None
+            }
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimiter
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Transform = RateLimiterMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimiterMiddleware {
+            service: Rc::new(service),
+            limiter: self.clone(),
+        }))
+    }
+}
+
+pub struct RateLimiterMiddleware<S> {
+    service: Rc<S>,
+    limiter: RateLimiter,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimiterMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut request: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let limiter = self.limiter.clone();
+        Box::pin(async move {
+            if let Some(retry_after) = limiter.check(&mut request).await {
+                let response = too_many_requests(retry_after);
+                return Ok(request.into_response(response).map_into_right_body());
+            }
+            service
+                .call(request)
+                .await
+                .map(ServiceResponse::map_into_left_body)
+        })
+    }
+}
+
+fn too_many_requests(retry_after: Duration) -> actix_web::HttpResponse {
+    // Rounded up, so that a client retrying on time is not limited again.
+    let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    let mut response = ProblemDetails::new(
+        StatusCode::TOO_MANY_REQUESTS,
+        &"Too many requests, please try again later.",
+    )
+    .into_response();
+    response.headers_mut().insert(
+        header::RETRY_AFTER,
+        header::HeaderValue::from(seconds.max(1)),
+    );
+    response
+}
+
+/// The proxies whose `X-Forwarded-For` entries are believed. Also app data,
+/// so that handlers record the same client address the limiter counts.
+#[derive(Clone, Default)]
+pub struct TrustedProxies(Arc<[IpAddr]>);
+
+impl TrustedProxies {
+    pub fn new(proxies: Vec<IpAddr>) -> Self {
+        Self(proxies.into())
+    }
+
+    pub fn client_ip(&self, request: &HttpRequest) -> Option<IpAddr> {
+        let forwarded_for = request
+            .headers()
+            .get_all("x-forwarded-for")
+            .filter_map(|value| value.to_str().ok())
+            .collect::<Vec<_>>()
+            .join(",");
+        let peer = request.peer_addr().map(|address| address.ip());
+        client_ip(peer, &forwarded_for, &self.0)
+    }
+}
+
+/// The address of the client: the peer's, unless the peer is a trusted proxy,
+/// in which case the right-most `X-Forwarded-For` entry that was not added by
+/// a trusted proxy. Entries left of it are set by the client and not trusted.
+pub fn client_ip(
+    peer: Option<IpAddr>,
+    forwarded_for: &str,
+    trusted_proxies: &[IpAddr],
+) -> Option<IpAddr> {
+    let mut client = peer?;
+    for entry in forwarded_for.rsplit(',') {
+        if !trusted_proxies.contains(&client) {
+            break;
+        }
+        match entry.trim().parse() {
+            Ok(ip) => client = ip,
+            Err(_) => break,
+        }
+    }
+    Some(client)
+}
+
+/// Reads the `email` field of the body and puts the body back for the
+/// handler.
+async fn target_email(request: &mut ServiceRequest) -> Option<String> {
+    let body = request.extract::<web::Bytes>().await.ok()?;
+    let (_, mut payload) = actix_http::h1::Payload::create(true);
+    payload.unread_data(body.clone());
+    request.set_payload(Payload::from(payload));
+    email_from_body(request.content_type(), &body)
+}
+
+/// The address an `email` field names, in the form that is stored, so that
+/// variants of the same address share a limit.
+fn email_from_body(content_type: &str, body: &[u8]) -> Option<String> {
+    let email = if content_type.starts_with("application/json") {
+        serde_json::from_slice::<serde_json::Value>(body)
+            .ok()?
+            .get("email")?
+            .as_str()?
+            .to_string()
+    } else {
+        url::form_urlencoded::parse(body)
+            .find(|(field, _)| field == "email")?
+            .1
+            .into_owned()
+    };
+    let email = match SubscriberEmail::parse(email.clone()) {
+        Ok(email) => email.as_ref().to_lowercase(),
+        Err(_) => email.trim().to_lowercase(),
+    };
+    Some(email)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::IpAddr, time::Duration};
+
+    use claims::{assert_none, assert_ok_eq, assert_some_eq};
+
+    use super::{client_ip, email_from_body, Decision, InMemoryStore, RateLimit, RateLimitStore};
+
+    fn ip(address: &str) -> IpAddr {
+        address.parse().unwrap()
+    }
+
+    #[tokio::test]
+    async fn the_in_memory_store_limits_each_key_over_its_window() {
+        let store = InMemoryStore::default();
+        let limit = RateLimit {
+            max_requests: 2,
+            window: Duration::from_millis(200),
+        };
+        assert_ok_eq!(store.hit("a", &limit).await, Decision::Allowed);
+        assert_ok_eq!(store.hit("a", &limit).await, Decision::Allowed);
+        assert_ok_eq!(store.hit("b", &limit).await, Decision::Allowed);
+        match store.hit("a", &limit).await.unwrap() {
+            Decision::Limited { retry_after } => assert!(retry_after <= limit.window),
+            Decision::Allowed => panic!("The third request was allowed."),
+        }
+
+        tokio::time::sleep(limit.window).await;
+        assert_ok_eq!(store.hit("a", &limit).await, Decision::Allowed);
+    }
+
+    #[tokio::test]
+    async fn the_in_memory_store_drops_keys_once_their_requests_expire() {
+        let store = InMemoryStore::default();
+        let short = RateLimit {
+            max_requests: 1,
+            window: Duration::from_millis(10),
+        };
+        let long = RateLimit {
+            max_requests: 1,
+            window: Duration::from_secs(60),
+        };
+        assert_ok_eq!(store.hit("a", &short).await, Decision::Allowed);
+        assert_ok_eq!(store.hit("b", &long).await, Decision::Allowed);
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_ok_eq!(store.hit("c", &short).await, Decision::Allowed);
+        let state = store.state.lock().unwrap();
+        let mut keys = state.expiries.keys().collect::<Vec<_>>();
+        keys.sort();
+        assert_eq!(keys, ["b", "c"]);
+    }
+
+    #[test]
+    fn the_peer_is_the_client_unless_it_is_a_trusted_proxy() {
+        let peer = Some(ip("10.0.0.1"));
+        assert_some_eq!(client_ip(peer, "203.0.113.7", &[]), ip("10.0.0.1"));
+        assert_some_eq!(
+            client_ip(peer, "203.0.113.7", &[ip("10.0.0.1")]),
+            ip("203.0.113.7")
+        );
+        assert_some_eq!(client_ip(peer, "", &[ip("10.0.0.1")]), ip("10.0.0.1"));
+        assert_none!(client_ip(None, "203.0.113.7", &[]));
+    }
+
+    #[test]
+    fn forwarded_entries_set_by_the_client_are_ignored() {
+        let trusted = [ip("10.0.0.1"), ip("10.0.0.2")];
+        let peer = Some(ip("10.0.0.1"));
+        // The client claims to be 192.0.2.1; the proxies saw 203.0.113.7.
+        assert_some_eq!(
+            client_ip(peer, "192.0.2.1, 203.0.113.7, 10.0.0.2", &trusted),
+            ip("203.0.113.7")
+        );
+        assert_some_eq!(
+            client_ip(peer, "192.0.2.1, not-an-ip", &trusted),
+            ip("10.0.0.1")
+        );
+    }
+
+    #[test]
+    fn the_email_is_read_from_json_and_form_bodies() {
+        assert_some_eq!(
+            email_from_body("application/json", br#"{"email": "Ursula@Example.COM"}"#),
+            "ursula@example.com"
+        );
+        assert_some_eq!(
+            email_from_body(
+                "application/x-www-form-urlencoded",
+                b"name=le%20guin&email=%20ursula%40example.com"
+            ),
+            "ursula@example.com"
+        );
+        assert_none!(email_from_body("application/json", b"{}"));
+        assert_none!(email_from_body("application/json", b"not json"));
+    }
+}
@@ -0,0 +1,99 @@
+use actix_web::{web, HttpResponse};
+use sqlx::{PgExecutor, PgPool};
+
+use crate::domain::{domain_and_parents, parse_domain, SubscriberEmail};
+use crate::error::AppError;
+
+#[derive(serde::Deserialize)]
+pub struct NewBlockedDomain {
+    domain: String,
+}
+
+#[derive(serde::Serialize)]
+struct BlockedDomain {
+    domain: String,
+    added_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[tracing::instrument(
+    name = "Blocking an email domain.",
+    skip(body, pool),
+    fields(domain=%body.domain)
+)]
+pub async fn block_domain(
+    body: web::Json<NewBlockedDomain>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AppError> {
+    let domain = parse_domain(&body.domain)?;
+    let blocked = sqlx::query_as!(
+        BlockedDomain,
+        r#"
+        INSERT INTO blocked_email_domains (domain)
+        VALUES ($1)
+        ON CONFLICT (domain) DO NOTHING
+        RETURNING domain, added_at
+        "#,
+        domain
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .inspect_err(|e| {
+        tracing::error!("Failed to execute query {:?}.", e);
+    })?
+    .ok_or_else(|| AppError::Conflict(format!("{} is already blocked.", domain)))?;
+
+    Ok(HttpResponse::Ok().json(blocked))
+}
+
+#[tracing::instrument(name = "Listing blocked email domains.", skip(pool))]
+pub async fn get_blocked_domains(pool: web::Data<PgPool>) -> Result<HttpResponse, AppError> {
+    let blocked = sqlx::query_as!(
+        BlockedDomain,
+        "SELECT domain, added_at FROM blocked_email_domains ORDER BY domain"
+    )
+    .fetch_all(pool.get_ref())
+    .await?;
+
+    Ok(HttpResponse::Ok().json(blocked))
+}
+
+#[tracing::instrument(name = "Unblocking an email domain.", skip(pool))]
+pub async fn unblock_domain(
+    domain: web::Path<String>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AppError> {
+    let domain = parse_domain(&domain)?;
+    let result = sqlx::query!(
+        "DELETE FROM blocked_email_domains WHERE domain = $1",
+        domain
+    )
+    .execute(pool.get_ref())
+    .await
+    .inspect_err(|e| {
+        tracing::error!("Failed to execute query {:?}.", e);
+    })?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound(format!("{} is not blocked.", domain)));
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// The admin-blocked domains that the address or one of its parent domains
+/// belongs to.
+#[tracing::instrument(name = "Fetching blocked email domains.", skip_all)]
+pub async fn get_blocked_domains_for(
+    executor: impl PgExecutor<'_>,
+    email: &SubscriberEmail,
+) -> Result<Vec<String>, sqlx::Error> {
+    let candidates = domain_and_parents(email.domain())
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    let rows = sqlx::query!(
+        "SELECT domain FROM blocked_email_domains WHERE domain = ANY($1)",
+        &candidates
+    )
+    .fetch_all(executor)
+    .await?;
+    Ok(rows.into_iter().map(|row| row.domain).collect())
+}
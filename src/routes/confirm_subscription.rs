@@ -1,6 +1,8 @@
-use actix_web::{web, HttpResponse};
+use actix_web::{web, HttpRequest, HttpResponse};
 use sqlx::PgPool;
 
+use crate::{error::AppError, rate_limit::TrustedProxies};
+
 #[derive(serde::Deserialize)]
 pub struct Token {
     token: String,
@@ -14,58 +16,95 @@ impl SubscriptionId {
     }
 }
 
-#[tracing::instrument(name = "Confirming a subscription.", skip(token, pool))]
+#[derive(Debug)]
+struct PendingMembership {
+    subscription_id: SubscriptionId,
+    list_id: uuid::Uuid,
+}
+
+#[tracing::instrument(
+    name = "Confirming a subscription.",
+    skip(request, token, pool, trusted_proxies)
+)]
 pub async fn confirm_subscription(
+    request: HttpRequest,
     token: web::Query<Token>,
     pool: web::Data<PgPool>,
-) -> HttpResponse {
-    let subscription_id = match get_subscriber_id_from_token(&pool, &token.token).await {
-        Ok(id) => id,
-        Err(_) => return HttpResponse::BadRequest().finish(),
-    };
+    trusted_proxies: web::Data<TrustedProxies>,
+) -> Result<HttpResponse, AppError> {
+    let membership = get_membership_from_token(&pool, &token.token)
+        .await?
+        .ok_or_else(|| AppError::ValidationError("Unknown confirmation token.".to_string()))?;
 
-    let activate_subscription = activate_subscription(&pool, &subscription_id).await;
+    let confirmation_ip = trusted_proxies.client_ip(&request).map(|ip| ip.to_string());
+    activate_subscription(&pool, &membership, confirmation_ip).await?;
 
-    match activate_subscription {
-        Ok(_) => HttpResponse::Ok().finish(),
-        Err(_) => HttpResponse::InternalServerError().finish(),
-    }
+    Ok(HttpResponse::Ok().finish())
 }
 
-async fn get_subscriber_id_from_token(
+async fn get_membership_from_token(
     pool: &PgPool,
     token: &str,
-) -> Result<SubscriptionId, sqlx::Error> {
-    let fetch_one = sqlx::query!(
+) -> Result<Option<PendingMembership>, sqlx::Error> {
+    let membership = sqlx::query!(
         r#"
-        SELECT subscription_id from subscription_tokens
+        SELECT subscription_id, list_id from subscription_tokens
         WHERE token = $1
         "#,
         token
     )
-    .fetch_one(pool)
+    .fetch_optional(pool)
     .await?;
 
-    let subscription_id = fetch_one.subscription_id;
-
-    Ok(SubscriptionId::new(subscription_id))
+    Ok(membership.map(|membership| PendingMembership {
+        subscription_id: SubscriptionId::new(membership.subscription_id),
+        list_id: membership.list_id,
+    }))
 }
 
+/// Only pending memberships are confirmed: replaying an old confirmation link
+/// must not resubscribe someone who has since unsubscribed.
 #[tracing::instrument(name = "Marking subscription as confirmed.", skip(pool))]
 async fn activate_subscription(
     pool: &PgPool,
-    subscription_id: &SubscriptionId,
+    membership: &PendingMembership,
+    confirmation_ip: Option<String>,
 ) -> Result<(), sqlx::Error> {
+    let mut transaction = pool.begin().await?;
     sqlx::query!(
         r#"
         UPDATE subscriptions
         SET status = 'confirmed'
-        WHERE id = $1
+        WHERE id = $1 AND status = 'pending_verification'
+        "#,
+        membership.subscription_id.0
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        UPDATE list_memberships
+        SET status = 'confirmed', confirmed_at = COALESCE(confirmed_at, now())
+        WHERE subscriber_id = $1 AND list_id = $2 AND status = 'pending_verification'
+        "#,
+        membership.subscription_id.0,
+        membership.list_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        UPDATE consent_records
+        SET confirmation_ip = $3, confirmed_at = now()
+        WHERE subscriber_id = $1 AND list_id = $2 AND confirmed_at IS NULL
         "#,
-        subscription_id.0
+        membership.subscription_id.0,
+        membership.list_id,
+        confirmation_ip
     )
-    .execute(pool)
+    .execute(&mut *transaction)
     .await?;
+    transaction.commit().await?;
 
     Ok(())
 }
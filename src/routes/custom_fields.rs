@@ -0,0 +1,97 @@
+use actix_web::{web, HttpResponse};
+use sqlx::{PgExecutor, PgPool};
+
+use crate::domain::{CustomFieldDefinition, CustomFieldType};
+use crate::error::AppError;
+
+#[derive(serde::Deserialize)]
+pub struct NewCustomField {
+    key: String,
+    label: String,
+    #[serde(rename = "type")]
+    field_type: CustomFieldType,
+    #[serde(default)]
+    options: Vec<String>,
+    #[serde(default)]
+    required: bool,
+}
+
+#[tracing::instrument(
+    name = "Creating a custom field.",
+    skip(field, pool),
+    fields(key=%field.key)
+)]
+pub async fn create_custom_field(
+    field: web::Json<NewCustomField>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AppError> {
+    let NewCustomField {
+        key,
+        label,
+        field_type,
+        options,
+        required,
+    } = field.0;
+    let definition = CustomFieldDefinition::parse(key, label, field_type, options, required)?;
+
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO custom_fields (key, label, field_type, options, required)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (key) DO NOTHING
+        "#,
+        definition.key,
+        definition.label,
+        definition.field_type.as_str(),
+        &definition.options,
+        definition.required,
+    )
+    .execute(pool.get_ref())
+    .await
+    .inspect_err(|e| {
+        tracing::error!("Failed to execute query {:?}.", e);
+    })?;
+
+    if result.rows_affected() != 1 {
+        return Err(AppError::Conflict(format!(
+            "A field with key {} already exists.",
+            definition.key
+        )));
+    }
+
+    Ok(HttpResponse::Ok().json(definition))
+}
+
+#[tracing::instrument(name = "Listing custom fields.", skip(pool))]
+pub async fn get_custom_fields(pool: web::Data<PgPool>) -> Result<HttpResponse, AppError> {
+    let definitions = get_custom_field_definitions(pool.get_ref()).await?;
+    Ok(HttpResponse::Ok().json(definitions))
+}
+
+#[tracing::instrument(name = "Fetching custom field definitions.", skip(executor))]
+pub async fn get_custom_field_definitions(
+    executor: impl PgExecutor<'_>,
+) -> Result<Vec<CustomFieldDefinition>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT key, label, field_type, options, required
+        FROM custom_fields
+        ORDER BY created_at, key
+        "#
+    )
+    .fetch_all(executor)
+    .await?;
+
+    rows.into_iter()
+        .map(|row| {
+            Ok(CustomFieldDefinition {
+                field_type: CustomFieldType::parse(&row.field_type)
+                    .map_err(|e| sqlx::Error::Decode(e.into()))?,
+                key: row.key,
+                label: row.label,
+                options: row.options,
+                required: row.required,
+            })
+        })
+        .collect()
+}
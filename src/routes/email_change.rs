@@ -0,0 +1,280 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use rand::{thread_rng, Rng};
+use sqlx::PgPool;
+
+use crate::{
+    domain::{escape_html, EmailPolicy, SubscriberEmail},
+    email_client::EmailClient,
+    error::AppError,
+    mail_domain::MailDomainCheck,
+    signed_links::{InvalidToken, LinkPurpose, SignedLinks},
+    suppression::{is_email_suppressed, EmailHasher},
+};
+
+use super::{email_rejection, ApplicationBaseUrl};
+
+const EMAIL_CHANGE_TTL_HOURS: i64 = 24;
+
+#[derive(serde::Deserialize)]
+pub struct EmailChangeToken {
+    token: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct EmailChangeRequest {
+    email: String,
+}
+
+fn generate_token() -> String {
+    let mut rng = thread_rng();
+    std::iter::repeat_with(|| rng.sample(rand::distributions::Alphanumeric))
+        .map(char::from)
+        .take(25)
+        .collect()
+}
+
+/// Starts an address change from the preference center. The new address is
+/// screened like a signup's, and only replaces the current one once the link
+/// mailed to it has been followed.
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(
+    name = "Requesting an email change.",
+    skip(
+        token,
+        form,
+        pool,
+        email_client,
+        email_policy,
+        mail_domain_check,
+        email_hasher,
+        signed_links,
+        base_url
+    )
+)]
+pub async fn request_email_change(
+    token: web::Query<EmailChangeToken>,
+    form: web::Form<EmailChangeRequest>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    email_policy: web::Data<EmailPolicy>,
+    mail_domain_check: web::Data<MailDomainCheck>,
+    email_hasher: web::Data<EmailHasher>,
+    signed_links: web::Data<SignedLinks>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, AppError> {
+    let subscriber_id = signed_links.verify(LinkPurpose::Preferences, &token.token)?;
+    let new_email = SubscriberEmail::parse(form.0.email)?;
+    let current = sqlx::query!(
+        "SELECT email FROM subscriptions WHERE id = $1",
+        subscriber_id
+    )
+    .fetch_optional(pool.get_ref())
+    .await?
+    .ok_or(AppError::InvalidLink(InvalidToken::Malformed))?;
+    if current.email == new_email.as_ref() {
+        return Err(AppError::ValidationError(
+            "This is already your email address.".to_string(),
+        ));
+    }
+
+    if let Some(rejection) = email_rejection(
+        &pool,
+        &email_client,
+        &email_policy,
+        &mail_domain_check,
+        &new_email,
+    )
+    .await?
+    {
+        return Err(AppError::ValidationError(rejection.message));
+    }
+    let requested = render_message(&format!(
+        "We sent a confirmation link to {}. Your address will change once you follow it.",
+        escape_html(new_email.as_ref())
+    ));
+    if is_email_suppressed(pool.get_ref(), &email_hasher, new_email.as_ref()).await? {
+        // The address belongs to an erased subscriber, who is not mailed
+        // again. Answer as usual so the suppression list cannot be probed.
+        tracing::info!("Ignoring an email change to a suppressed address.");
+        return Ok(requested);
+    }
+
+    // Only the latest request can be confirmed.
+    let confirmation_token = generate_token();
+    let mut transaction = pool.begin().await?;
+    sqlx::query!(
+        "DELETE FROM email_change_requests WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO email_change_requests (token, subscriber_id, new_email, expires_at)
+        VALUES ($1, $2, $3, now() + make_interval(hours => $4))
+        "#,
+        confirmation_token,
+        subscriber_id,
+        new_email.as_ref(),
+        EMAIL_CHANGE_TTL_HOURS as i32
+    )
+    .execute(&mut *transaction)
+    .await?;
+    transaction.commit().await?;
+
+    let link = format!(
+        "{}/subscriptions/email/confirm?token={}",
+        base_url.0, confirmation_token
+    );
+    email_client
+        .send_email(
+            &new_email,
+            "Confirm your new email address",
+            &format!(
+                "Confirm your new email address <a href=\"{}\">here</a>. The link is valid for {} hours.",
+                link, EMAIL_CHANGE_TTL_HOURS
+            ),
+            &format!(
+                "Confirm your new email address here: {}\nThe link is valid for {} hours.",
+                link, EMAIL_CHANGE_TTL_HOURS
+            ),
+        )
+        .await?;
+
+    Ok(requested)
+}
+
+// Mail scanners follow links, so the GET only asks for confirmation.
+#[tracing::instrument(name = "Showing the email change form.", skip(token, pool))]
+pub async fn email_change_form(
+    token: web::Query<EmailChangeToken>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AppError> {
+    let request = sqlx::query!(
+        "SELECT new_email FROM email_change_requests WHERE token = $1 AND expires_at > now()",
+        token.token
+    )
+    .fetch_optional(pool.get_ref())
+    .await?
+    .ok_or_else(invalid_link)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head><meta charset="utf-8"><title>Email address</title></head>
+<body>
+<form method="post" action="?token={}">
+<p>Change the email address of your subscription to {}?</p>
+<button type="submit">Change my email address</button>
+</form>
+</body>
+</html>"#,
+            escape_html(&token.token),
+            escape_html(&request.new_email)
+        )))
+}
+
+/// Replaces the address everywhere it is used to reach the subscriber,
+/// including deliveries that are still queued.
+#[tracing::instrument(name = "Confirming an email change.", skip(token, pool, email_client))]
+pub async fn confirm_email_change(
+    token: web::Query<EmailChangeToken>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+) -> Result<HttpResponse, AppError> {
+    let mut transaction = pool.begin().await?;
+    let request = sqlx::query!(
+        r#"
+        SELECT r.subscriber_id, r.new_email, s.email AS old_email
+        FROM email_change_requests r
+        JOIN subscriptions s ON s.id = r.subscriber_id
+        WHERE r.token = $1 AND r.expires_at > now()
+        FOR UPDATE OF r
+        "#,
+        token.token
+    )
+    .fetch_optional(&mut *transaction)
+    .await?
+    .ok_or_else(invalid_link)?;
+
+    sqlx::query!(
+        "UPDATE subscriptions SET email = $2 WHERE id = $1",
+        request.subscriber_id,
+        request.new_email
+    )
+    .execute(&mut *transaction)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(e) if e.is_unique_violation() => {
+            AppError::Conflict("Another subscription already uses this email address.".to_string())
+        }
+        e => {
+            tracing::error!("Failed to execute query {:?}.", e);
+            e.into()
+        }
+    })?;
+    sqlx::query!(
+        "UPDATE issue_delivery_queue SET subscriber_email = $2 WHERE subscriber_email = $1",
+        request.old_email,
+        request.new_email
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM email_change_requests WHERE subscriber_id = $1",
+        request.subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    transaction.commit().await?;
+
+    // The change is already committed; a failed notification should not
+    // make it look like it was not.
+    match SubscriberEmail::parse(request.old_email) {
+        Ok(old_email) => {
+            let notice = format!(
+                "The email address of your subscription was changed to {}. If you did not request this, please contact us.",
+                request.new_email
+            );
+            if let Err(e) = email_client
+                .send_email(
+                    &old_email,
+                    "Your email address was changed",
+                    &escape_html(&notice),
+                    &notice,
+                )
+                .await
+            {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    "Failed to notify the previous address of an email change."
+                );
+            }
+        }
+        Err(e) => tracing::warn!("Skipping email change notice to an invalid address: {}", e),
+    }
+
+    Ok(render_message(&format!(
+        "Your email address is now {}.",
+        escape_html(&request.new_email)
+    )))
+}
+
+fn invalid_link() -> AppError {
+    AppError::ValidationError("The link is invalid or has expired.".to_string())
+}
+
+fn render_message(message: &str) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head><meta charset="utf-8"><title>Email address</title></head>
+<body><p>{}</p></body>
+</html>"#,
+            message
+        ))
+}
@@ -0,0 +1,91 @@
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::domain::ListSlug;
+use crate::error::AppError;
+
+#[derive(serde::Deserialize)]
+pub struct NewList {
+    slug: String,
+    name: String,
+}
+
+#[derive(serde::Serialize)]
+struct MailingList {
+    id: Uuid,
+    slug: String,
+    name: String,
+    confirmed_subscribers: i64,
+}
+
+#[tracing::instrument(
+    name = "Creating a mailing list.",
+    skip(list, pool),
+    fields(slug=%list.slug)
+)]
+pub async fn create_list(
+    list: web::Json<NewList>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AppError> {
+    let NewList { slug, name } = list.0;
+    let slug = ListSlug::parse(slug)?;
+    if name.trim().is_empty() {
+        return Err(AppError::ValidationError(
+            "List name cannot be empty.".to_string(),
+        ));
+    }
+
+    let list_id = Uuid::new_v4();
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO lists (id, slug, name)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (slug) DO NOTHING
+        "#,
+        list_id,
+        slug.as_ref(),
+        name.trim(),
+    )
+    .execute(pool.get_ref())
+    .await
+    .inspect_err(|e| {
+        tracing::error!("Failed to execute query {:?}.", e);
+    })?;
+
+    if result.rows_affected() != 1 {
+        return Err(AppError::Conflict(format!(
+            "A list with slug {} already exists.",
+            slug
+        )));
+    }
+
+    Ok(HttpResponse::Ok().json(MailingList {
+        id: list_id,
+        slug: slug.as_ref().to_string(),
+        name: name.trim().to_string(),
+        confirmed_subscribers: 0,
+    }))
+}
+
+#[tracing::instrument(name = "Listing mailing lists.", skip(pool))]
+pub async fn get_lists(pool: web::Data<PgPool>) -> Result<HttpResponse, AppError> {
+    let lists = sqlx::query_as!(
+        MailingList,
+        r#"
+        SELECT
+            l.id,
+            l.slug,
+            l.name,
+            COUNT(m.subscriber_id) FILTER (WHERE m.status = 'confirmed') AS "confirmed_subscribers!"
+        FROM lists l
+        LEFT JOIN list_memberships m ON m.list_id = l.id
+        GROUP BY l.id
+        ORDER BY l.created_at, l.slug
+        "#,
+    )
+    .fetch_all(pool.get_ref())
+    .await?;
+
+    Ok(HttpResponse::Ok().json(lists))
+}
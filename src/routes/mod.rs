@@ -1,9 +1,23 @@
+mod blocked_domains;
 mod confirm_subscription;
+mod custom_fields;
+mod email_change;
 mod health_check;
-mod newsletter;
+mod lists;
+mod newsletters;
+mod preferences;
+mod subscribers;
 mod subscriptions;
+mod unsubscribe;
 
+pub use blocked_domains::*;
 pub use confirm_subscription::*;
+pub use custom_fields::*;
+pub use email_change::*;
 pub use health_check::*;
-pub use newsletter::*;
+pub use lists::*;
+pub use newsletters::*;
+pub use preferences::*;
+pub use subscribers::*;
 pub use subscriptions::*;
+pub use unsubscribe::*;
@@ -0,0 +1,162 @@
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::{check_content_merge_tags, get_newsletter_issue, issue_not_found};
+use crate::domain::{NewsletterContent, SanitizationReport, DEFAULT_LIST_SLUG};
+use crate::error::AppError;
+
+#[derive(serde::Deserialize)]
+pub struct Newsletter {
+    title: String,
+    content: Content,
+}
+
+#[derive(serde::Deserialize)]
+pub struct Content {
+    html: Option<String>,
+    text: Option<String>,
+    markdown: Option<String>,
+}
+
+impl Newsletter {
+    fn parse_content(self) -> Result<(String, NewsletterContent), String> {
+        let Newsletter { title, content } = self;
+        if title.trim().is_empty() {
+            return Err("Newsletter title cannot be empty.".to_string());
+        }
+        let content =
+            NewsletterContent::parse(&title, content.html, content.text, content.markdown)?;
+        Ok((title, content))
+    }
+}
+
+#[derive(serde::Serialize)]
+struct SavedNewsletter<'a> {
+    id: Uuid,
+    sanitization: &'a SanitizationReport,
+}
+
+#[tracing::instrument(
+    name = "Creating a newsletter draft.",
+    skip(newsletter, pool),
+    fields(title=%newsletter.title)
+)]
+pub async fn create_newsletter(
+    newsletter: web::Json<Newsletter>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AppError> {
+    let (title, content) = newsletter.0.parse_content()?;
+    let newsletter_issue_id = insert_newsletter_issue(&pool, &title, &content).await?;
+
+    Ok(HttpResponse::Ok().json(SavedNewsletter {
+        id: newsletter_issue_id,
+        sanitization: content.sanitization_report(),
+    }))
+}
+
+#[tracing::instrument(
+    name = "Updating a newsletter draft.",
+    skip(newsletter, pool),
+    fields(title=%newsletter.title)
+)]
+pub async fn update_newsletter(
+    newsletter_issue_id: web::Path<Uuid>,
+    newsletter: web::Json<Newsletter>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AppError> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let (title, content) = newsletter.0.parse_content()?;
+    // A scheduled issue goes out unattended, so a broken edit is caught
+    // here rather than when it is due.
+    let issue = get_newsletter_issue(&pool, newsletter_issue_id).await?;
+    if issue.is_some_and(|issue| issue.status == "scheduled") {
+        check_content_merge_tags(&pool, content.html(), content.text()).await?;
+    }
+
+    if !update_newsletter_draft(&pool, newsletter_issue_id, &title, &content).await? {
+        return match get_newsletter_issue(&pool, newsletter_issue_id).await? {
+            Some(_) => Err(AppError::Conflict(
+                "Published newsletter issues cannot be edited.".to_string(),
+            )),
+            None => Err(issue_not_found()),
+        };
+    }
+
+    Ok(HttpResponse::Ok().json(SavedNewsletter {
+        id: newsletter_issue_id,
+        sanitization: content.sanitization_report(),
+    }))
+}
+
+#[tracing::instrument(name = "Saving newsletter draft in the database.", skip(pool, content))]
+async fn insert_newsletter_issue(
+    pool: &PgPool,
+    title: &str,
+    content: &NewsletterContent,
+) -> Result<Uuid, sqlx::Error> {
+    let newsletter_issue_id = Uuid::new_v4();
+    let mut transaction = pool.begin().await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (
+            id, title, text_content, html_content, markdown_content, status
+        )
+        VALUES ($1, $2, $3, $4, $5, 'draft')
+        "#,
+        newsletter_issue_id,
+        title,
+        content.text(),
+        content.html(),
+        content.markdown(),
+    )
+    .execute(&mut *transaction)
+    .await
+    .inspect_err(|e| {
+        tracing::error!("Failed to execute query {:?}.", e);
+    })?;
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issue_lists (newsletter_issue_id, list_id)
+        SELECT $1, id FROM lists WHERE slug = $2
+        "#,
+        newsletter_issue_id,
+        DEFAULT_LIST_SLUG,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    transaction.commit().await?;
+
+    Ok(newsletter_issue_id)
+}
+
+#[tracing::instrument(
+    name = "Updating newsletter draft in the database.",
+    skip(pool, content)
+)]
+async fn update_newsletter_draft(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+    title: &str,
+    content: &NewsletterContent,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET title = $2, text_content = $3, html_content = $4, markdown_content = $5
+        WHERE id = $1 AND status IN ('draft', 'scheduled', 'failed')
+        "#,
+        newsletter_issue_id,
+        title,
+        content.text(),
+        content.html(),
+        content.markdown(),
+    )
+    .execute(pool)
+    .await
+    .inspect_err(|e| {
+        tracing::error!("Failed to execute query {:?}.", e);
+    })?;
+
+    Ok(result.rows_affected() == 1)
+}
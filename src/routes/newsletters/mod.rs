@@ -0,0 +1,180 @@
+mod create;
+mod preview;
+mod publish;
+mod schedule;
+mod test_send;
+
+pub use create::*;
+pub use preview::*;
+pub use publish::*;
+pub use schedule::*;
+pub use test_send::*;
+
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::domain::{validate_merge_tags, ListSlug};
+use crate::error::AppError;
+use crate::routes::get_custom_field_definitions;
+use crate::segment::Segment;
+
+fn issue_not_found() -> AppError {
+    AppError::NotFound("Newsletter issue not found.".to_string())
+}
+
+pub struct NewsletterIssue {
+    pub id: Uuid,
+    pub title: String,
+    pub text_content: String,
+    pub html_content: String,
+    pub status: String,
+}
+
+#[tracing::instrument(name = "Fetching newsletter issue.", skip(pool))]
+pub async fn get_newsletter_issue(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<NewsletterIssue>, sqlx::Error> {
+    let issue = sqlx::query_as!(
+        NewsletterIssue,
+        r#"
+        SELECT id, title, text_content, html_content, status
+        FROM newsletter_issues
+        WHERE id = $1
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(issue)
+}
+
+/// Rejects issues using merge tags that cannot be rendered for subscribers.
+/// Unknown and already published issues are left for the caller to report.
+#[tracing::instrument(name = "Validating newsletter merge tags.", skip(pool))]
+pub async fn check_merge_tags(pool: &PgPool, newsletter_issue_id: Uuid) -> Result<(), AppError> {
+    let Some(issue) = get_newsletter_issue(pool, newsletter_issue_id).await? else {
+        return Ok(());
+    };
+    if issue.status == "published" {
+        return Ok(());
+    }
+    check_content_merge_tags(pool, &issue.html_content, &issue.text_content).await
+}
+
+/// Rejects content using merge tags that cannot be rendered for subscribers.
+async fn check_content_merge_tags(pool: &PgPool, html: &str, text: &str) -> Result<(), AppError> {
+    let definitions = get_custom_field_definitions(pool).await?;
+    validate_merge_tags(
+        &[html, text],
+        definitions.iter().map(|definition| definition.key.as_str()),
+    )?;
+    Ok(())
+}
+
+/// Who a newsletter issue is delivered to. Parts left out of a request keep
+/// their current value; an explicit `"segment": null` removes the segment.
+#[derive(serde::Deserialize, Default)]
+pub struct Audience {
+    lists: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    segment: Option<serde_json::Value>,
+}
+
+fn deserialize_some<'de, D>(deserializer: D) -> Result<Option<serde_json::Value>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    serde::Deserialize::deserialize(deserializer).map(Some)
+}
+
+impl Audience {
+    pub async fn apply(
+        self,
+        transaction: &mut Transaction<'_, Postgres>,
+        newsletter_issue_id: Uuid,
+    ) -> Result<(), AppError> {
+        if let Some(lists) = self.lists {
+            set_issue_lists(transaction, newsletter_issue_id, lists).await?;
+        }
+        if let Some(segment) = self.segment {
+            let segment = match segment {
+                serde_json::Value::Null => None,
+                segment => Some(Segment::parse(segment)?),
+            };
+            set_issue_segment(transaction, newsletter_issue_id, segment).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Replaces the mailing lists a newsletter issue is delivered to.
+#[tracing::instrument(name = "Setting newsletter issue lists.", skip(transaction))]
+async fn set_issue_lists(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+    lists: Vec<String>,
+) -> Result<(), AppError> {
+    if lists.is_empty() {
+        return Err(AppError::ValidationError(
+            "Newsletter issues must be delivered to at least one list.".to_string(),
+        ));
+    }
+    let slugs = lists
+        .into_iter()
+        .map(|slug| ListSlug::parse(slug).map(|slug| slug.as_ref().to_string()))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let known = sqlx::query!("SELECT id, slug FROM lists WHERE slug = ANY($1)", &slugs)
+        .fetch_all(&mut **transaction)
+        .await?;
+    if let Some(unknown) = slugs
+        .iter()
+        .find(|slug| !known.iter().any(|list| &list.slug == *slug))
+    {
+        return Err(AppError::ValidationError(format!(
+            "Unknown list {}.",
+            unknown
+        )));
+    }
+    let list_ids = known.into_iter().map(|list| list.id).collect::<Vec<_>>();
+
+    sqlx::query!(
+        "DELETE FROM newsletter_issue_lists WHERE newsletter_issue_id = $1",
+        newsletter_issue_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issue_lists (newsletter_issue_id, list_id)
+        SELECT $1, UNNEST($2::uuid[])
+        "#,
+        newsletter_issue_id,
+        &list_ids
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(name = "Setting newsletter issue segment.", skip(transaction))]
+async fn set_issue_segment(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+    segment: Option<Segment>,
+) -> Result<(), AppError> {
+    let segment = segment
+        .map(|segment| serde_json::to_value(segment).expect("Segments always serialize to JSON."));
+    sqlx::query!(
+        "UPDATE newsletter_issues SET segment = $2 WHERE id = $1",
+        newsletter_issue_id,
+        segment
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,20 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::{get_newsletter_issue, issue_not_found};
+use crate::error::AppError;
+
+#[tracing::instrument(name = "Previewing a newsletter issue.", skip(pool))]
+pub async fn preview_newsletter(
+    newsletter_issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AppError> {
+    let issue = get_newsletter_issue(&pool, newsletter_issue_id.into_inner())
+        .await?
+        .ok_or(issue_not_found())?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(issue.html_content))
+}
@@ -0,0 +1,50 @@
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::{check_merge_tags, get_newsletter_issue, issue_not_found, Audience};
+use crate::error::AppError;
+use crate::newsletter_scheduler::publish_issue;
+
+#[derive(serde::Serialize)]
+struct PublishedNewsletter {
+    id: Uuid,
+    status: &'static str,
+}
+
+#[tracing::instrument(name = "Publishing a newsletter issue.", skip(body, pool))]
+pub async fn publish_newsletter(
+    newsletter_issue_id: web::Path<Uuid>,
+    body: web::Bytes,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AppError> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    // The body is optional: without one the issue goes to the audience it already targets.
+    let audience: Audience = if body.is_empty() {
+        Audience::default()
+    } else {
+        serde_json::from_slice(&body).map_err(|e| AppError::ValidationError(e.to_string()))?
+    };
+
+    check_merge_tags(&pool, newsletter_issue_id).await?;
+
+    let mut transaction = pool.begin().await?;
+    audience
+        .apply(&mut transaction, newsletter_issue_id)
+        .await?;
+    if !publish_issue(&mut transaction, newsletter_issue_id).await? {
+        transaction.rollback().await?;
+        return match get_newsletter_issue(&pool, newsletter_issue_id).await? {
+            Some(_) => Err(AppError::Conflict(
+                "Newsletter issue has already been published.".to_string(),
+            )),
+            None => Err(issue_not_found()),
+        };
+    }
+    transaction.commit().await?;
+
+    Ok(HttpResponse::Ok().json(PublishedNewsletter {
+        id: newsletter_issue_id,
+        status: "published",
+    }))
+}
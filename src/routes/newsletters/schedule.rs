@@ -0,0 +1,140 @@
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::{check_merge_tags, get_newsletter_issue, issue_not_found, Audience};
+use crate::error::AppError;
+
+// The furthest ahead of UTC any timezone gets (UTC+14, Line Islands).
+const MAX_UTC_OFFSET_HOURS: i64 = 14;
+
+#[derive(serde::Deserialize)]
+pub struct Schedule {
+    scheduled_for: Option<DateTime<Utc>>,
+    deliver_at_local: Option<NaiveDateTime>,
+    #[serde(flatten)]
+    audience: Audience,
+}
+
+impl Schedule {
+    /// Returns when the issue should be published, i.e. when its delivery
+    /// tasks are enqueued. Local deliveries are enqueued as soon as the
+    /// requested wall-clock time is reached in the earliest timezone; each
+    /// task then waits for the time to come around in its subscriber's zone.
+    fn scheduled_for(&self) -> Result<DateTime<Utc>, String> {
+        match (self.scheduled_for, self.deliver_at_local) {
+            (Some(scheduled_for), None) => Ok(scheduled_for),
+            (None, Some(deliver_at_local)) => Ok(DateTime::from_naive_utc_and_offset(
+                deliver_at_local - chrono::Duration::hours(MAX_UTC_OFFSET_HOURS),
+                Utc,
+            )),
+            _ => {
+                Err("A schedule must contain either scheduled_for or deliver_at_local.".to_string())
+            }
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct ScheduledNewsletter {
+    id: Uuid,
+    status: &'static str,
+    scheduled_for: Option<DateTime<Utc>>,
+    deliver_at_local: Option<NaiveDateTime>,
+}
+
+#[tracing::instrument(name = "Scheduling a newsletter issue.", skip(schedule, pool))]
+pub async fn schedule_newsletter(
+    newsletter_issue_id: web::Path<Uuid>,
+    schedule: web::Json<Schedule>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AppError> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let schedule = schedule.into_inner();
+    let scheduled_for = schedule.scheduled_for()?;
+    if scheduled_for <= Utc::now() {
+        return Err(AppError::ValidationError(
+            "Newsletter issues can only be scheduled in the future.".to_string(),
+        ));
+    }
+
+    check_merge_tags(&pool, newsletter_issue_id).await?;
+
+    let mut transaction = pool.begin().await?;
+    let result = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET status = 'scheduled', scheduled_for = $2, deliver_at_local = $3,
+            failure_reason = NULL
+        WHERE id = $1 AND status IN ('draft', 'scheduled', 'failed')
+        "#,
+        newsletter_issue_id,
+        scheduled_for,
+        schedule.deliver_at_local,
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    if result.rows_affected() != 1 {
+        transaction.rollback().await?;
+        return Err(not_schedulable(&pool, newsletter_issue_id).await?);
+    }
+    schedule
+        .audience
+        .apply(&mut transaction, newsletter_issue_id)
+        .await?;
+    transaction.commit().await?;
+
+    Ok(HttpResponse::Ok().json(ScheduledNewsletter {
+        id: newsletter_issue_id,
+        status: "scheduled",
+        scheduled_for: Some(scheduled_for),
+        deliver_at_local: schedule.deliver_at_local,
+    }))
+}
+
+#[tracing::instrument(name = "Cancelling a scheduled newsletter issue.", skip(pool))]
+pub async fn cancel_newsletter_schedule(
+    newsletter_issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AppError> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+
+    let result = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET status = 'draft', scheduled_for = NULL, deliver_at_local = NULL,
+            failure_reason = NULL
+        WHERE id = $1 AND status IN ('scheduled', 'failed')
+        "#,
+        newsletter_issue_id
+    )
+    .execute(pool.get_ref())
+    .await?;
+
+    if result.rows_affected() != 1 {
+        return Err(not_schedulable(&pool, newsletter_issue_id).await?);
+    }
+
+    Ok(HttpResponse::Ok().json(ScheduledNewsletter {
+        id: newsletter_issue_id,
+        status: "draft",
+        scheduled_for: None,
+        deliver_at_local: None,
+    }))
+}
+
+async fn not_schedulable(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<AppError, sqlx::Error> {
+    let error = match get_newsletter_issue(pool, newsletter_issue_id).await? {
+        Some(issue) => AppError::Conflict(format!(
+            "Newsletter issue is {} and cannot be rescheduled.",
+            issue.status
+        )),
+        None => issue_not_found(),
+    };
+    Ok(error)
+}
@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::{get_newsletter_issue, issue_not_found};
+use crate::error::AppError;
+use crate::{
+    domain::{render_merge_tags, SubscriberEmail},
+    email_client::EmailClient,
+    issue_delivery_worker::get_subscriber_template_variables,
+    signed_links::SignedLinks,
+};
+
+const MAX_TEST_RECIPIENTS: usize = 20;
+const SAMPLE_SUBSCRIBER_NAME: &str = "Test Subscriber";
+
+#[derive(serde::Deserialize)]
+pub struct TestSend {
+    recipients: Vec<String>,
+}
+
+impl TestSend {
+    fn parse_recipients(self) -> Result<Vec<SubscriberEmail>, String> {
+        if self.recipients.is_empty() {
+            return Err("At least one test recipient is required.".to_string());
+        }
+        if self.recipients.len() > MAX_TEST_RECIPIENTS {
+            return Err(format!(
+                "A test send cannot have more than {} recipients.",
+                MAX_TEST_RECIPIENTS
+            ));
+        }
+        self.recipients
+            .into_iter()
+            .map(|email| SubscriberEmail::parse(email).map_err(|e| e.to_string()))
+            .collect()
+    }
+}
+
+#[tracing::instrument(
+    name = "Test sending a newsletter issue.",
+    skip(test_send, pool, email_client, signed_links)
+)]
+pub async fn test_send_newsletter(
+    newsletter_issue_id: web::Path<Uuid>,
+    test_send: web::Json<TestSend>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    signed_links: web::Data<SignedLinks>,
+) -> Result<HttpResponse, AppError> {
+    let recipients = test_send.0.parse_recipients()?;
+    let issue = get_newsletter_issue(&pool, newsletter_issue_id.into_inner())
+        .await?
+        .ok_or(issue_not_found())?;
+
+    let subject = format!("[TEST] {}", issue.title);
+    for recipient in &recipients {
+        let variables = get_test_template_variables(&pool, &signed_links, recipient).await?;
+        email_client
+            .send_email(
+                recipient,
+                &subject,
+                &render_merge_tags(&issue.html_content, &variables, true),
+                &render_merge_tags(&issue.text_content, &variables, false),
+            )
+            .await?;
+    }
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Renders a test send the way the recipient would receive the issue if they
+/// are a subscriber, and with sample values otherwise.
+async fn get_test_template_variables(
+    pool: &PgPool,
+    signed_links: &SignedLinks,
+    recipient: &SubscriberEmail,
+) -> Result<HashMap<String, String>, sqlx::Error> {
+    let email = recipient.as_ref();
+    if let Some(variables) = get_subscriber_template_variables(pool, signed_links, email).await? {
+        return Ok(variables);
+    }
+
+    let mut variables = HashMap::from([
+        ("subscriber.email".to_string(), email.to_string()),
+        (
+            "subscriber.name".to_string(),
+            SAMPLE_SUBSCRIBER_NAME.to_string(),
+        ),
+        (
+            "unsubscribe_url".to_string(),
+            signed_links.unsubscribe_url(Uuid::nil()),
+        ),
+    ]);
+    let custom_field_keys = sqlx::query!("SELECT key FROM custom_fields")
+        .fetch_all(pool)
+        .await?;
+    for field in custom_field_keys {
+        variables.insert(format!("subscriber.{}", field.key), String::new());
+    }
+    Ok(variables)
+}
@@ -0,0 +1,349 @@
+use actix_web::{
+    http::{header::ContentType, StatusCode},
+    web, HttpRequest, HttpResponse,
+};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    domain::{escape_html, DigestFrequency, SubscriberEmail, SubscriberName},
+    email_client::EmailClient,
+    error::AppError,
+    rate_limit::TrustedProxies,
+    routes::{cancel_left_deliveries, erase_subscriber_data, get_subscriber_export},
+    signed_links::{InvalidToken, LinkPurpose, SignedLinks},
+    suppression::EmailHasher,
+};
+
+const PREFERENCES_LINK_TTL_HOURS: i64 = 24;
+
+#[derive(serde::Deserialize)]
+pub struct PreferencesToken {
+    token: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct PreferencesLinkRequest {
+    email: String,
+}
+
+struct Preferences {
+    name: String,
+    email: String,
+    digest_frequency: String,
+    lists: Vec<ListPreference>,
+}
+
+struct ListPreference {
+    slug: String,
+    name: String,
+    subscribed: bool,
+}
+
+#[tracing::instrument(
+    name = "Sending a preferences link.",
+    skip(form, pool, email_client, signed_links)
+)]
+pub async fn send_preferences_link(
+    form: web::Form<PreferencesLinkRequest>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    signed_links: web::Data<SignedLinks>,
+) -> Result<HttpResponse, AppError> {
+    let email = SubscriberEmail::parse(form.0.email)?;
+    let subscriber = sqlx::query!(
+        "SELECT id FROM subscriptions WHERE lower(email) = lower($1)",
+        email.as_ref()
+    )
+    .fetch_optional(pool.get_ref())
+    .await?;
+
+    // Answer the same whether or not the address is subscribed, so the
+    // endpoint cannot be used to find out who is.
+    if let Some(subscriber) = subscriber {
+        let expires_at = chrono::Utc::now() + chrono::Duration::hours(PREFERENCES_LINK_TTL_HOURS);
+        let link = signed_links.preferences_url(subscriber.id, expires_at);
+        let html_body = format!(
+            "Manage your subscription <a href=\"{}\">here</a>. The link is valid for {} hours.",
+            link, PREFERENCES_LINK_TTL_HOURS
+        );
+        let text_body = format!(
+            "Manage your subscription here: {}\nThe link is valid for {} hours.",
+            link, PREFERENCES_LINK_TTL_HOURS
+        );
+        email_client
+            .send_email(&email, "Manage your subscription", &html_body, &text_body)
+            .await?;
+    }
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[tracing::instrument(name = "Showing subscriber preferences.", skip_all)]
+pub async fn preferences_form(
+    token: web::Query<PreferencesToken>,
+    pool: web::Data<PgPool>,
+    signed_links: web::Data<SignedLinks>,
+) -> Result<HttpResponse, AppError> {
+    let subscriber_id = signed_links.verify(LinkPurpose::Preferences, &token.token)?;
+    let preferences = get_preferences(&pool, subscriber_id).await?;
+    Ok(render_preferences(&token.token, &preferences, None))
+}
+
+#[tracing::instrument(name = "Exporting subscriber data from preferences.", skip_all)]
+pub async fn export_preferences_data(
+    token: web::Query<PreferencesToken>,
+    pool: web::Data<PgPool>,
+    signed_links: web::Data<SignedLinks>,
+) -> Result<HttpResponse, AppError> {
+    let subscriber_id = signed_links.verify(LinkPurpose::Preferences, &token.token)?;
+    let export = get_subscriber_export(&pool, subscriber_id)
+        .await?
+        .ok_or(AppError::InvalidLink(InvalidToken::Malformed))?;
+    Ok(HttpResponse::Ok()
+        .insert_header((
+            "Content-Disposition",
+            "attachment; filename=\"subscription-data.json\"",
+        ))
+        .json(export))
+}
+
+#[tracing::instrument(name = "Erasing subscriber data from preferences.", skip_all)]
+pub async fn erase_preferences_data(
+    token: web::Query<PreferencesToken>,
+    pool: web::Data<PgPool>,
+    signed_links: web::Data<SignedLinks>,
+    email_hasher: web::Data<EmailHasher>,
+) -> Result<HttpResponse, AppError> {
+    let subscriber_id = signed_links.verify(LinkPurpose::Preferences, &token.token)?;
+    let mut transaction = pool.begin().await?;
+    if !erase_subscriber_data(&mut transaction, &email_hasher, subscriber_id).await? {
+        return Err(AppError::InvalidLink(InvalidToken::Malformed));
+    }
+    transaction.commit().await?;
+
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head><meta charset="utf-8"><title>Subscription preferences</title></head>
+<body><p>All your data has been erased.</p></body>
+</html>"#,
+    ))
+}
+
+#[tracing::instrument(name = "Updating subscriber preferences.", skip_all)]
+pub async fn update_preferences(
+    request: HttpRequest,
+    token: web::Query<PreferencesToken>,
+    form: web::Form<Vec<(String, String)>>,
+    pool: web::Data<PgPool>,
+    signed_links: web::Data<SignedLinks>,
+    trusted_proxies: web::Data<TrustedProxies>,
+) -> Result<HttpResponse, AppError> {
+    let subscriber_id = signed_links.verify(LinkPurpose::Preferences, &token.token)?;
+    let field = |name: &str| {
+        form.iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.clone())
+    };
+
+    let message = if field("unsubscribe_all").is_some() {
+        let mut transaction = pool.begin().await?;
+        sqlx::query!(
+            "UPDATE list_memberships SET status = 'unsubscribed' WHERE subscriber_id = $1",
+            subscriber_id
+        )
+        .execute(&mut *transaction)
+        .await?;
+        cancel_left_deliveries(&mut transaction, subscriber_id).await?;
+        transaction.commit().await?;
+        "You have been unsubscribed from all lists."
+    } else {
+        let parsed = SubscriberName::parse(field("name").unwrap_or_default())
+            .map_err(String::from)
+            .and_then(|name| {
+                let digest_frequency =
+                    DigestFrequency::parse(&field("digest_frequency").unwrap_or_default())?;
+                Ok((name, digest_frequency))
+            });
+        let (name, digest_frequency) = match parsed {
+            Ok(parsed) => parsed,
+            Err(error) => {
+                let preferences = get_preferences(&pool, subscriber_id).await?;
+                let mut response = render_preferences(&token.token, &preferences, Some(&error));
+                *response.status_mut() = StatusCode::BAD_REQUEST;
+                return Ok(response);
+            }
+        };
+        let lists = form
+            .iter()
+            .filter(|(key, _)| key == "lists")
+            .map(|(_, slug)| slug.clone())
+            .collect::<Vec<_>>();
+        let client_ip = trusted_proxies.client_ip(&request).map(|ip| ip.to_string());
+
+        let mut transaction = pool.begin().await?;
+        sqlx::query!(
+            "UPDATE subscriptions SET name = $2, digest_frequency = $3 WHERE id = $1",
+            subscriber_id,
+            name.as_ref(),
+            digest_frequency.as_ref()
+        )
+        .execute(&mut *transaction)
+        .await?;
+        // The link was delivered to the subscriber's address, which is as good
+        // as a confirmation for any list they opt into here.
+        let joined = sqlx::query_scalar!(
+            r#"
+            INSERT INTO list_memberships (subscriber_id, list_id, status, confirmed_at)
+            SELECT $1, id, 'confirmed', now() FROM lists WHERE slug = ANY($2)
+            ON CONFLICT (subscriber_id, list_id) DO UPDATE
+            SET status = 'confirmed',
+                confirmed_at = COALESCE(list_memberships.confirmed_at, now())
+            WHERE list_memberships.status <> 'confirmed'
+            RETURNING list_id
+            "#,
+            subscriber_id,
+            &lists
+        )
+        .fetch_all(&mut *transaction)
+        .await?;
+        sqlx::query!(
+            r#"
+            INSERT INTO consent_records
+                (subscriber_id, list_id, source_ip, form_id, confirmation_ip, confirmed_at)
+            SELECT $1, list_id, $3, 'preferences', $3, now()
+            FROM UNNEST($2::uuid[]) AS list_id
+            "#,
+            subscriber_id,
+            &joined,
+            client_ip
+        )
+        .execute(&mut *transaction)
+        .await?;
+        sqlx::query!(
+            r#"
+            UPDATE list_memberships m
+            SET status = 'unsubscribed'
+            FROM lists l
+            WHERE l.id = m.list_id AND m.subscriber_id = $1 AND l.slug <> ALL($2)
+            "#,
+            subscriber_id,
+            &lists
+        )
+        .execute(&mut *transaction)
+        .await?;
+        cancel_left_deliveries(&mut transaction, subscriber_id).await?;
+        transaction.commit().await?;
+        "Your preferences have been saved."
+    };
+
+    let preferences = get_preferences(&pool, subscriber_id).await?;
+    Ok(render_preferences(
+        &token.token,
+        &preferences,
+        Some(message),
+    ))
+}
+
+async fn get_preferences(pool: &PgPool, subscriber_id: Uuid) -> Result<Preferences, AppError> {
+    let subscriber = sqlx::query!(
+        "SELECT name, email, digest_frequency FROM subscriptions WHERE id = $1",
+        subscriber_id
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or(AppError::InvalidLink(InvalidToken::Malformed))?;
+    let lists = sqlx::query_as!(
+        ListPreference,
+        r#"
+        SELECT
+            l.slug,
+            l.name,
+            COALESCE(m.status = 'confirmed', FALSE) AS "subscribed!"
+        FROM lists l
+        LEFT JOIN list_memberships m ON m.list_id = l.id AND m.subscriber_id = $1
+        ORDER BY l.created_at, l.slug
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(Preferences {
+        name: subscriber.name,
+        email: subscriber.email,
+        digest_frequency: subscriber.digest_frequency,
+        lists,
+    })
+}
+
+fn render_preferences(
+    token: &str,
+    preferences: &Preferences,
+    message: Option<&str>,
+) -> HttpResponse {
+    let lists = preferences
+        .lists
+        .iter()
+        .map(|list| {
+            format!(
+                r#"<label><input type="checkbox" name="lists" value="{}"{}> {}</label><br>"#,
+                escape_html(&list.slug),
+                if list.subscribed { " checked" } else { "" },
+                escape_html(&list.name)
+            )
+        })
+        .collect::<String>();
+    let frequencies = DigestFrequency::ALL
+        .iter()
+        .map(|frequency| {
+            format!(
+                r#"<option value="{0}"{1}>{0}</option>"#,
+                frequency.as_ref(),
+                if frequency.as_ref() == preferences.digest_frequency {
+                    " selected"
+                } else {
+                    ""
+                }
+            )
+        })
+        .collect::<String>();
+
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head><meta charset="utf-8"><title>Subscription preferences</title></head>
+<body>
+{message}
+<p>Preferences for {email}</p>
+<form method="post" action="?token={token}">
+<label>Name <input type="text" name="name" value="{name}"></label><br>
+<label>Digest frequency <select name="digest_frequency">{frequencies}</select></label><br>
+{lists}
+<button type="submit">Save</button>
+</form>
+<form method="post" action="/subscriptions/email?token={token}">
+<label>New email address <input type="email" name="email"></label>
+<button type="submit">Change email address</button>
+</form>
+<form method="post" action="?token={token}">
+<input type="hidden" name="unsubscribe_all" value="on">
+<button type="submit">Unsubscribe from everything</button>
+</form>
+<p><a href="/subscriptions/preferences/export?token={token}">Download your data</a></p>
+<form method="post" action="/subscriptions/preferences/erase?token={token}">
+<button type="submit">Erase all my data</button>
+</form>
+</body>
+</html>"#,
+            message = message
+                .map(|message| format!("<p><i>{}</i></p>", escape_html(message)))
+                .unwrap_or_default(),
+            email = escape_html(&preferences.email),
+            name = escape_html(&preferences.name),
+            token = escape_html(token),
+        ))
+}
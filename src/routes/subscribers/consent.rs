@@ -0,0 +1,42 @@
+use chrono::{DateTime, Utc};
+use sqlx::PgExecutor;
+use uuid::Uuid;
+
+#[derive(serde::Serialize)]
+pub struct ConsentRecord {
+    list: String,
+    source_ip: Option<String>,
+    user_agent: Option<String>,
+    form_id: Option<String>,
+    consent_text_version: Option<String>,
+    consented_at: DateTime<Utc>,
+    confirmation_ip: Option<String>,
+    confirmed_at: Option<DateTime<Utc>>,
+}
+
+pub async fn get_consent_records(
+    executor: impl PgExecutor<'_>,
+    subscriber_id: Uuid,
+) -> Result<Vec<ConsentRecord>, sqlx::Error> {
+    sqlx::query_as!(
+        ConsentRecord,
+        r#"
+        SELECT
+            l.slug AS list,
+            c.source_ip,
+            c.user_agent,
+            c.form_id,
+            c.consent_text_version,
+            c.consented_at,
+            c.confirmation_ip,
+            c.confirmed_at
+        FROM consent_records c
+        JOIN lists l ON l.id = c.list_id
+        WHERE c.subscriber_id = $1
+        ORDER BY c.consented_at
+        "#,
+        subscriber_id
+    )
+    .fetch_all(executor)
+    .await
+}
@@ -0,0 +1,65 @@
+use actix_web::{web, HttpResponse};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use super::subscriber_not_found;
+use crate::error::AppError;
+use crate::suppression::{suppress_email, EmailHasher};
+
+#[tracing::instrument(name = "Erasing a subscriber.", skip(pool, email_hasher))]
+pub async fn erase_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    email_hasher: web::Data<EmailHasher>,
+) -> Result<HttpResponse, AppError> {
+    let mut transaction = pool.begin().await?;
+    if !erase_subscriber_data(&mut transaction, &email_hasher, subscriber_id.into_inner()).await? {
+        return Err(subscriber_not_found());
+    }
+    transaction.commit().await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Removes all personal data of a subscriber. Their address is added to the
+/// suppression list and their delivery events are kept, detached from them,
+/// so that issue statistics stay accurate. Returns `false` if there is no
+/// such subscriber.
+pub async fn erase_subscriber_data(
+    transaction: &mut Transaction<'_, Postgres>,
+    email_hasher: &EmailHasher,
+    subscriber_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let Some(subscriber) = sqlx::query!(
+        "SELECT email FROM subscriptions WHERE id = $1 FOR UPDATE",
+        subscriber_id
+    )
+    .fetch_optional(&mut **transaction)
+    .await?
+    else {
+        return Ok(false);
+    };
+
+    sqlx::query!(
+        "DELETE FROM issue_delivery_queue WHERE subscriber_email = $1",
+        subscriber.email
+    )
+    .execute(&mut **transaction)
+    .await?;
+    // Failure details may quote the address.
+    sqlx::query!(
+        "UPDATE email_events SET detail = NULL WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+    suppress_email(&mut **transaction, email_hasher, &subscriber.email).await?;
+    // Tokens, memberships, tags and pending email changes cascade.
+    sqlx::query!("DELETE FROM subscriptions WHERE id = $1", subscriber_id)
+        .execute(&mut **transaction)
+        .await
+        .inspect_err(|e| {
+            tracing::error!("Failed to execute query {:?}.", e);
+        })?;
+
+    Ok(true)
+}
@@ -0,0 +1,213 @@
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::{get_consent_records, subscriber_not_found, ConsentRecord};
+use crate::error::AppError;
+
+/// Everything stored about a subscriber, as returned for subject access
+/// requests.
+#[derive(serde::Serialize)]
+pub struct SubscriberExport {
+    exported_at: DateTime<Utc>,
+    subscription: SubscriptionRecord,
+    custom_fields: serde_json::Value,
+    tags: Vec<TagRecord>,
+    list_memberships: Vec<MembershipRecord>,
+    consent_records: Vec<ConsentRecord>,
+    subscription_tokens: Vec<TokenRecord>,
+    email_change_requests: Vec<EmailChangeRecord>,
+    pending_deliveries: Vec<PendingDeliveryRecord>,
+    email_events: Vec<EmailEventRecord>,
+}
+
+#[derive(serde::Serialize)]
+struct SubscriptionRecord {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    timezone: Option<String>,
+    digest_frequency: String,
+    subscribed_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+struct TagRecord {
+    tag: String,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+struct MembershipRecord {
+    list: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+    confirmed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(serde::Serialize)]
+struct TokenRecord {
+    token: String,
+    list: String,
+}
+
+#[derive(serde::Serialize)]
+struct EmailChangeRecord {
+    new_email: String,
+    requested_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+struct PendingDeliveryRecord {
+    newsletter_issue_id: Uuid,
+    title: String,
+    execute_after: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+struct EmailEventRecord {
+    newsletter_issue_id: Option<Uuid>,
+    title: Option<String>,
+    event_type: String,
+    detail: Option<String>,
+    occurred_at: DateTime<Utc>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ExportQuery {
+    email: String,
+}
+
+#[tracing::instrument(name = "Exporting subscriber data.", skip(query, pool))]
+pub async fn export_subscriber_data(
+    query: web::Query<ExportQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AppError> {
+    let subscriber = sqlx::query!(
+        "SELECT id FROM subscriptions WHERE lower(email) = lower($1)",
+        query.email.trim()
+    )
+    .fetch_optional(pool.get_ref())
+    .await?
+    .ok_or(subscriber_not_found())?;
+    let export = get_subscriber_export(&pool, subscriber.id)
+        .await?
+        .ok_or(subscriber_not_found())?;
+    Ok(HttpResponse::Ok().json(export))
+}
+
+pub async fn get_subscriber_export(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<SubscriberExport>, sqlx::Error> {
+    let Some(subscriber) = sqlx::query!(
+        r#"
+        SELECT id, email, name, status, timezone, digest_frequency, subscribed_at, custom_fields
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(pool)
+    .await?
+    else {
+        return Ok(None);
+    };
+
+    let tags = sqlx::query_as!(
+        TagRecord,
+        "SELECT tag, created_at FROM subscriber_tags WHERE subscriber_id = $1 ORDER BY tag",
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await?;
+    let list_memberships = sqlx::query_as!(
+        MembershipRecord,
+        r#"
+        SELECT l.slug AS list, m.status, m.subscribed_at, m.confirmed_at
+        FROM list_memberships m
+        JOIN lists l ON l.id = m.list_id
+        WHERE m.subscriber_id = $1
+        ORDER BY l.slug
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await?;
+    let consent_records = get_consent_records(pool, subscriber_id).await?;
+    let subscription_tokens = sqlx::query_as!(
+        TokenRecord,
+        r#"
+        SELECT t.token, l.slug AS list
+        FROM subscription_tokens t
+        JOIN lists l ON l.id = t.list_id
+        WHERE t.subscription_id = $1
+        ORDER BY l.slug, t.token
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await?;
+    let email_change_requests = sqlx::query_as!(
+        EmailChangeRecord,
+        r#"
+        SELECT new_email, requested_at, expires_at
+        FROM email_change_requests
+        WHERE subscriber_id = $1
+        ORDER BY requested_at
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await?;
+    let pending_deliveries = sqlx::query_as!(
+        PendingDeliveryRecord,
+        r#"
+        SELECT q.newsletter_issue_id, n.title, q.execute_after
+        FROM issue_delivery_queue q
+        JOIN newsletter_issues n ON n.id = q.newsletter_issue_id
+        WHERE q.subscriber_email = $1
+        ORDER BY q.execute_after
+        "#,
+        subscriber.email
+    )
+    .fetch_all(pool)
+    .await?;
+    let email_events = sqlx::query_as!(
+        EmailEventRecord,
+        r#"
+        SELECT e.newsletter_issue_id, n.title AS "title?", e.event_type, e.detail, e.occurred_at
+        FROM email_events e
+        LEFT JOIN newsletter_issues n ON n.id = e.newsletter_issue_id
+        WHERE e.subscriber_id = $1
+        ORDER BY e.occurred_at
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(Some(SubscriberExport {
+        exported_at: Utc::now(),
+        subscription: SubscriptionRecord {
+            id: subscriber.id,
+            email: subscriber.email,
+            name: subscriber.name,
+            status: subscriber.status,
+            timezone: subscriber.timezone,
+            digest_frequency: subscriber.digest_frequency,
+            subscribed_at: subscriber.subscribed_at,
+        },
+        custom_fields: subscriber.custom_fields,
+        tags,
+        list_memberships,
+        consent_records,
+        subscription_tokens,
+        email_change_requests,
+        pending_deliveries,
+        email_events,
+    }))
+}
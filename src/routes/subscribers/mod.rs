@@ -0,0 +1,17 @@
+mod consent;
+mod erase;
+mod export;
+mod profile;
+mod tags;
+
+pub use consent::*;
+pub use erase::*;
+pub use export::*;
+pub use profile::*;
+pub use tags::*;
+
+use crate::error::AppError;
+
+fn subscriber_not_found() -> AppError {
+    AppError::NotFound("Subscriber not found.".to_string())
+}
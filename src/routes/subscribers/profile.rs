@@ -0,0 +1,127 @@
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::{get_consent_records, subscriber_not_found, ConsentRecord};
+use crate::domain::CustomFields;
+use crate::error::AppError;
+use crate::routes::get_custom_field_definitions;
+
+#[derive(serde::Serialize)]
+struct SubscriberProfile {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    timezone: Option<String>,
+    digest_frequency: String,
+    subscribed_at: DateTime<Utc>,
+    custom_fields: serde_json::Value,
+    tags: Vec<String>,
+    lists: Vec<ListMembership>,
+    consents: Vec<ConsentRecord>,
+}
+
+#[derive(serde::Serialize)]
+struct ListMembership {
+    slug: String,
+    status: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct Fields {
+    fields: serde_json::Map<String, serde_json::Value>,
+}
+
+#[tracing::instrument(name = "Fetching a subscriber profile.", skip(pool))]
+pub async fn get_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AppError> {
+    let profile = get_subscriber_profile(&pool, subscriber_id.into_inner())
+        .await?
+        .ok_or(subscriber_not_found())?;
+    Ok(HttpResponse::Ok().json(profile))
+}
+
+#[tracing::instrument(name = "Replacing subscriber custom fields.", skip(fields, pool))]
+pub async fn set_subscriber_fields(
+    subscriber_id: web::Path<Uuid>,
+    fields: web::Json<Fields>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AppError> {
+    let subscriber_id = subscriber_id.into_inner();
+    let definitions = get_custom_field_definitions(pool.get_ref()).await?;
+    let custom_fields = CustomFields::parse(&definitions, fields.0.fields)?;
+
+    let result = sqlx::query!(
+        "UPDATE subscriptions SET custom_fields = $2 WHERE id = $1",
+        subscriber_id,
+        custom_fields.as_json()
+    )
+    .execute(pool.get_ref())
+    .await?;
+    if result.rows_affected() != 1 {
+        return Err(subscriber_not_found());
+    }
+
+    get_subscriber(web::Path::from(subscriber_id), pool).await
+}
+
+async fn get_subscriber_profile(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<SubscriberProfile>, sqlx::Error> {
+    let Some(subscriber) = sqlx::query!(
+        r#"
+        SELECT id, email, name, status, timezone, digest_frequency, subscribed_at, custom_fields
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(pool)
+    .await?
+    else {
+        return Ok(None);
+    };
+
+    let tags = sqlx::query!(
+        "SELECT tag FROM subscriber_tags WHERE subscriber_id = $1 ORDER BY tag",
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|row| row.tag)
+    .collect();
+    let lists = sqlx::query_as!(
+        ListMembership,
+        r#"
+        SELECT l.slug, m.status
+        FROM list_memberships m
+        JOIN lists l ON l.id = m.list_id
+        WHERE m.subscriber_id = $1
+        ORDER BY l.slug
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await?;
+    let consents = get_consent_records(pool, subscriber_id).await?;
+
+    Ok(Some(SubscriberProfile {
+        id: subscriber.id,
+        email: subscriber.email,
+        name: subscriber.name,
+        status: subscriber.status,
+        timezone: subscriber.timezone,
+        digest_frequency: subscriber.digest_frequency,
+        subscribed_at: subscriber.subscribed_at,
+        custom_fields: subscriber.custom_fields,
+        tags,
+        lists,
+        consents,
+    }))
+}
@@ -0,0 +1,69 @@
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::subscriber_not_found;
+use crate::domain::SubscriberTag;
+use crate::error::AppError;
+
+#[derive(serde::Deserialize)]
+pub struct Tags {
+    tags: Vec<String>,
+}
+
+#[derive(serde::Serialize)]
+struct TaggedSubscriber {
+    id: Uuid,
+    tags: Vec<String>,
+}
+
+#[tracing::instrument(name = "Replacing subscriber tags.", skip(tags, pool))]
+pub async fn set_subscriber_tags(
+    subscriber_id: web::Path<Uuid>,
+    tags: web::Json<Tags>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AppError> {
+    let subscriber_id = subscriber_id.into_inner();
+    let mut tags = tags
+        .0
+        .tags
+        .into_iter()
+        .map(|tag| SubscriberTag::parse(tag).map(|tag| tag.as_ref().to_string()))
+        .collect::<Result<Vec<_>, _>>()?;
+    tags.sort();
+    tags.dedup();
+
+    let mut transaction = pool.begin().await?;
+    let subscriber = sqlx::query!(
+        "SELECT id FROM subscriptions WHERE id = $1 FOR UPDATE",
+        subscriber_id
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+    if subscriber.is_none() {
+        return Err(subscriber_not_found());
+    }
+
+    sqlx::query!(
+        "DELETE FROM subscriber_tags WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_tags (subscriber_id, tag)
+        SELECT $1, UNNEST($2::text[])
+        "#,
+        subscriber_id,
+        &tags
+    )
+    .execute(&mut *transaction)
+    .await?;
+    transaction.commit().await?;
+
+    Ok(HttpResponse::Ok().json(TaggedSubscriber {
+        id: subscriber_id,
+        tags,
+    }))
+}
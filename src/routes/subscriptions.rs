@@ -1,18 +1,158 @@
-use actix_web::{web, HttpResponse, ResponseError};
+use std::{collections::HashMap, net::IpAddr};
+
+use actix_web::{http::header, web, Either, HttpRequest, HttpResponse};
 use rand::{thread_rng, Rng};
-use reqwest::StatusCode;
 use sqlx::{Executor, PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
 use crate::{
-    domain::{NewSubscriber, SubscriberEmail, SubscriberName},
-    email_client::EmailClient,
+    bot_protection::{BotDetected, BotProtection, Submission, Verified},
+    domain::{
+        CustomFieldDefinition, CustomFields, EmailPolicy, ListSlug, NewSubscriber, SubscriberEmail,
+        SubscriberName, SubscriberTimezone, ValidationError,
+    },
+    email_client::{EmailClient, SendEmailError},
+    error::{AppError, FieldErrors},
+    mail_domain::MailDomainCheck,
+    rate_limit::TrustedProxies,
+    routes::{get_blocked_domains_for, get_custom_field_definitions},
+    suppression::{is_email_suppressed, EmailHasher},
 };
 
+/// A signup, submitted either as a form or as JSON. Custom fields are sent as
+/// `fields[<key>]=<value>` in forms and as a `fields` object in JSON.
 #[derive(serde::Deserialize)]
 pub struct FormData {
     name: String,
     email: String,
+    timezone: Option<String>,
+    list: Option<String>,
+    /// Identifies the form or page the signup came from.
+    form: Option<String>,
+    consent_version: Option<String>,
+    /// The honeypot, see `HONEYPOT_FIELD`.
+    website: Option<String>,
+    /// From `/subscriptions/form-token`, signing when the form was served.
+    form_token: Option<String>,
+    /// A CAPTCHA response or proof of work, when one is required.
+    verification: Option<String>,
+    #[serde(flatten)]
+    extra: HashMap<String, serde_json::Value>,
+}
+
+/// A validated signup.
+struct Signup {
+    list_slug: ListSlug,
+    subscriber: NewSubscriber,
+    consent: Consent,
+}
+
+impl FormData {
+    /// Validates every field, reporting all the invalid ones at once.
+    fn parse(
+        self,
+        request: &HttpRequest,
+        client_ip: Option<IpAddr>,
+        definitions: &[CustomFieldDefinition],
+    ) -> Result<Signup, AppError> {
+        let mut errors = FieldErrors::default();
+        let name = errors.check("name", SubscriberName::parse(self.name));
+        let email = errors.check("email", SubscriberEmail::parse(self.email));
+        let timezone = errors.check(
+            "timezone",
+            self.timezone
+                .filter(|timezone| !timezone.trim().is_empty())
+                .map(SubscriberTimezone::parse)
+                .transpose(),
+        );
+        let list_slug = errors.check(
+            "list",
+            match self.list.filter(|list| !list.trim().is_empty()) {
+                Some(list) => ListSlug::parse(list).map_err(ValidationError::invalid),
+                None => Ok(ListSlug::default()),
+            },
+        );
+        let custom_fields = errors.check(
+            "fields",
+            custom_field_values(self.extra)
+                .and_then(|values| CustomFields::parse(definitions, values))
+                .map_err(ValidationError::invalid),
+        );
+        let form_id = errors.check("form", parse_consent_field(self.form));
+        let consent_text_version =
+            errors.check("consent_version", parse_consent_field(self.consent_version));
+
+        let (
+            Some(name),
+            Some(email),
+            Some(timezone),
+            Some(list_slug),
+            Some(custom_fields),
+            Some(form_id),
+            Some(consent_text_version),
+        ) = (
+            name,
+            email,
+            timezone,
+            list_slug,
+            custom_fields,
+            form_id,
+            consent_text_version,
+        )
+        else {
+            return Err(AppError::InvalidFields(errors.into_vec()));
+        };
+        Ok(Signup {
+            list_slug,
+            subscriber: NewSubscriber {
+                name,
+                email,
+                timezone,
+                custom_fields,
+            },
+            consent: Consent::new(request, client_ip, form_id, consent_text_version),
+        })
+    }
+}
+
+/// Custom field values, submitted as `fields[<key>]=<value>` in forms and as
+/// a `fields` object in JSON.
+fn custom_field_values(
+    extra: HashMap<String, serde_json::Value>,
+) -> Result<serde_json::Map<String, serde_json::Value>, String> {
+    let mut fields = serde_json::Map::new();
+    for (key, value) in extra {
+        if key == "fields" {
+            let serde_json::Value::Object(values) = value else {
+                return Err("fields must be an object.".to_string());
+            };
+            fields.extend(values);
+        } else if let Some(key) = key
+            .strip_prefix("fields[")
+            .and_then(|key| key.strip_suffix(']'))
+        {
+            fields.insert(key.to_string(), value);
+        }
+    }
+    Ok(fields)
+}
+
+fn parse_consent_field(value: Option<String>) -> Result<Option<String>, ValidationError> {
+    let value = value
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty());
+    match value {
+        Some(value) if value.chars().count() > MAX_CONSENT_FIELD_LENGTH => {
+            Err(ValidationError::new(
+                "too_long",
+                format!(
+                    "Cannot be longer than {} characters.",
+                    MAX_CONSENT_FIELD_LENGTH
+                ),
+            ))
+        }
+        value => Ok(value),
+    }
 }
 
 struct SubscriberId(Uuid);
@@ -22,6 +162,11 @@ impl SubscriberId {
     }
 }
 
+struct MailingList {
+    id: Uuid,
+    name: String,
+}
+
 struct SubscriptionToken(String);
 impl SubscriptionToken {
     fn new() -> SubscriptionToken {
@@ -37,45 +182,336 @@ impl SubscriptionToken {
 
 pub struct ApplicationBaseUrl(pub String);
 
-#[tracing::instrument(
-    name = "Adding a new subscriber.", 
-    skip(form, pool, email_client, base_url),
-    fields(email=%form.email, name=%form.name)
-)]
+const MAX_CONSENT_FIELD_LENGTH: usize = 256;
+
+/// How a signup was made, recorded as proof of consent.
+struct Consent {
+    source_ip: Option<String>,
+    user_agent: Option<String>,
+    form_id: Option<String>,
+    consent_text_version: Option<String>,
+}
+
+impl Consent {
+    fn new(
+        request: &HttpRequest,
+        client_ip: Option<IpAddr>,
+        form_id: Option<String>,
+        consent_text_version: Option<String>,
+    ) -> Self {
+        Self {
+            source_ip: client_ip.map(|ip| ip.to_string()),
+            user_agent: request
+                .headers()
+                .get("User-Agent")
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.chars().take(MAX_CONSENT_FIELD_LENGTH).collect()),
+            form_id,
+            consent_text_version,
+        }
+    }
+}
+
+const SUBSCRIBED_MESSAGE: &str = "Check your inbox to confirm your subscription.";
+
+/// Accepts both form and JSON bodies, and answers in JSON to clients that
+/// ask for it. Errors are always problem details.
+#[allow(clippy::too_many_arguments)]
 pub async fn subscribe(
-    form: web::Form<FormData>,
+    request: HttpRequest,
+    body: Either<web::Json<FormData>, web::Form<FormData>>,
     pool: web::Data<PgPool>,
     email_client: web::Data<EmailClient>,
+    email_policy: web::Data<EmailPolicy>,
+    mail_domain_check: web::Data<MailDomainCheck>,
+    bot_protection: web::Data<BotProtection>,
+    email_hasher: web::Data<EmailHasher>,
+    trusted_proxies: web::Data<TrustedProxies>,
     base_url: web::Data<ApplicationBaseUrl>,
-) -> Result<HttpResponse, SubscribeError> {
+) -> Result<HttpResponse, AppError> {
+    let form = match body {
+        Either::Left(json) => json.into_inner(),
+        Either::Right(form) => form.into_inner(),
+    };
+    let client_ip = trusted_proxies.client_ip(&request);
+    let remote_ip = client_ip.map(|ip| ip.to_string());
+    let submission = Submission {
+        honeypot: form.website.as_deref(),
+        form_token: form.form_token.as_deref(),
+        verification: form.verification.as_deref(),
+        remote_ip: remote_ip.as_deref(),
+    };
+    let verified = bot_protection
+        .verify(&submission)
+        .await
+        .map_err(reject_bot)?;
+    add_subscriber(
+        &request,
+        client_ip,
+        form,
+        &pool,
+        &email_client,
+        &email_policy,
+        &mail_domain_check,
+        &bot_protection,
+        verified,
+        &email_hasher,
+        &base_url,
+    )
+    .await?;
+
+    if accepts_json(&request) {
+        Ok(HttpResponse::Ok().json(serde_json::json!({ "message": SUBSCRIBED_MESSAGE })))
+    } else {
+        Ok(HttpResponse::Ok().finish())
+    }
+}
+
+fn reject_bot(detected: BotDetected) -> AppError {
+    tracing::info!("Rejecting a signup from a bot: {:?}.", detected);
+    detected.into()
+}
+
+/// Issues the token signup forms submit, to prove they were not filled in
+/// faster than a person could.
+pub async fn signup_form_token(bot_protection: web::Data<BotProtection>) -> HttpResponse {
+    HttpResponse::Ok().json(serde_json::json!({ "token": bot_protection.form_token() }))
+}
+
+/// Whether the client asked for JSON, or sent JSON and accepts anything.
+fn accepts_json(request: &HttpRequest) -> bool {
+    let header = |name| {
+        request
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+    };
+    let accept = header(header::ACCEPT);
+    accept.contains("application/json")
+        || ((accept.is_empty() || accept.contains("*/*"))
+            && header(header::CONTENT_TYPE).starts_with("application/json"))
+}
+
+#[tracing::instrument(
+    name = "Adding a new subscriber.",
+    skip(
+        request,
+        client_ip,
+        form,
+        pool,
+        email_client,
+        email_policy,
+        mail_domain_check,
+        bot_protection,
+        verified,
+        email_hasher,
+        base_url
+    ),
+    fields(email=%form.email, name=%form.name, list=?form.list)
+)]
+#[allow(clippy::too_many_arguments)]
+async fn add_subscriber(
+    request: &HttpRequest,
+    client_ip: Option<IpAddr>,
+    form: FormData,
+    pool: &PgPool,
+    email_client: &EmailClient,
+    email_policy: &EmailPolicy,
+    mail_domain_check: &MailDomainCheck,
+    bot_protection: &BotProtection,
+    verified: Verified,
+    email_hasher: &EmailHasher,
+    base_url: &ApplicationBaseUrl,
+) -> Result<(), AppError> {
+    let definitions = get_custom_field_definitions(pool).await?;
+    let Signup {
+        list_slug,
+        subscriber,
+        consent,
+    } = form.parse(request, client_ip, &definitions)?;
+    if let Some(error) = email_rejection(
+        pool,
+        email_client,
+        email_policy,
+        mail_domain_check,
+        &subscriber.email,
+    )
+    .await?
+    {
+        let mut errors = FieldErrors::default();
+        errors.push("email", error);
+        return Err(AppError::InvalidFields(errors.into_vec()));
+    }
+    // Only a valid signup uses up its form token, so that a corrected one
+    // can be submitted with the same form.
+    bot_protection.consume(verified).await.map_err(reject_bot)?;
+
     let mut transaction = pool.begin().await?;
-    let subscriber: NewSubscriber = form.0.try_into()?;
+    if is_email_suppressed(&mut *transaction, email_hasher, subscriber.email.as_ref()).await? {
+        // The address belongs to an erased subscriber. Answer as usual so the
+        // suppression list cannot be probed.
+        tracing::info!("Ignoring a signup from a suppressed address.");
+        return Ok(());
+    }
+    let list = get_list(&mut transaction, &list_slug)
+        .await?
+        .ok_or_else(|| {
+            let mut errors = FieldErrors::default();
+            errors.push(
+                "list",
+                ValidationError::new("unknown_list", format!("Unknown list {}.", list_slug)),
+            );
+            AppError::InvalidFields(errors.into_vec())
+        })?;
     let subscriber_id = insert_subscription(&mut transaction, &subscriber).await?;
-    let subscription_token = insert_subscription_token(&mut transaction, &subscriber_id).await?;
+    if !insert_list_membership(&mut transaction, &subscriber_id, &list).await? {
+        // Already confirmed on this list, there is nothing left to confirm.
+        transaction.commit().await?;
+        return Ok(());
+    }
+    insert_consent_record(&mut transaction, &subscriber_id, &list, &consent).await?;
+    let subscription_token =
+        insert_subscription_token(&mut transaction, &subscriber_id, &list).await?;
     transaction.commit().await?;
 
-    send_welcome_email(&email_client, &subscriber, &base_url, &subscription_token).await?;
+    send_welcome_email(
+        email_client,
+        &subscriber,
+        &list,
+        base_url,
+        &subscription_token,
+    )
+    .await?;
 
-    Ok(HttpResponse::Ok().finish())
+    Ok(())
+}
+
+/// Why an address cannot be subscribed, beyond its syntax: the email policy,
+/// the domains blocked by admins, whether the email provider can deliver to
+/// it and, when enabled, whether its domain can receive mail.
+#[tracing::instrument(name = "Screening an email address.", skip_all)]
+pub async fn email_rejection(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    email_policy: &EmailPolicy,
+    mail_domain_check: &MailDomainCheck,
+    email: &SubscriberEmail,
+) -> Result<Option<ValidationError>, sqlx::Error> {
+    let blocked_domains = get_blocked_domains_for(pool, email).await?;
+    let rejection = match email_policy.check(email, &blocked_domains) {
+        Err(violation) => Some(violation.into()),
+        Ok(()) if !email_client.can_deliver_to(email) => Some(ValidationError::new(
+            "unsupported_address",
+            "Addresses with non-ASCII characters before the @ are not supported.",
+        )),
+        Ok(()) if !mail_domain_check.accepts_mail(email.domain()).await => {
+            Some(ValidationError::new(
+                "domain_cannot_receive_mail",
+                format!("{} cannot receive email.", email.domain()),
+            ))
+        }
+        Ok(()) => None,
+    };
+    Ok(rejection)
+}
+
+#[tracing::instrument(name = "Fetching mailing list.", skip(transaction))]
+async fn get_list(
+    transaction: &mut Transaction<'_, Postgres>,
+    slug: &ListSlug,
+) -> Result<Option<MailingList>, sqlx::Error> {
+    sqlx::query_as!(
+        MailingList,
+        "SELECT id, name FROM lists WHERE slug = $1",
+        slug.as_ref()
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+}
+
+/// Adds the subscriber to the list, awaiting confirmation, including when
+/// they had unsubscribed from it. Returns `false` if the subscriber has
+/// already confirmed their membership of the list.
+#[tracing::instrument(
+    name = "Saving list membership in the database.",
+    skip(transaction, subscriber_id, list)
+)]
+async fn insert_list_membership(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: &SubscriberId,
+    list: &MailingList,
+) -> Result<bool, sqlx::Error> {
+    let membership = sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (subscriber_id, list_id, status)
+        VALUES ($1, $2, 'pending_verification')
+        ON CONFLICT (subscriber_id, list_id) DO UPDATE SET status = CASE
+            WHEN list_memberships.status = 'unsubscribed' THEN 'pending_verification'
+            ELSE list_memberships.status
+        END
+        RETURNING status
+        "#,
+        subscriber_id.0,
+        list.id,
+    )
+    .fetch_one(&mut **transaction)
+    .await
+    .inspect_err(|e| {
+        tracing::error!("Failed to execute query {:?}.", e);
+    })?;
+
+    Ok(membership.status != "confirmed")
+}
+
+#[tracing::instrument(
+    name = "Recording consent.",
+    skip(transaction, subscriber_id, list, consent)
+)]
+async fn insert_consent_record(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: &SubscriberId,
+    list: &MailingList,
+    consent: &Consent,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO consent_records
+            (subscriber_id, list_id, source_ip, user_agent, form_id, consent_text_version)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+        subscriber_id.0,
+        list.id,
+        consent.source_ip,
+        consent.user_agent,
+        consent.form_id,
+        consent.consent_text_version,
+    );
+    transaction.execute(query).await.inspect_err(|e| {
+        tracing::error!("Failed to execute query {:?}.", e);
+    })?;
+
+    Ok(())
 }
 
 async fn insert_subscription_token(
     transaction: &mut Transaction<'_, Postgres>,
     subscriber_id: &SubscriberId,
+    list: &MailingList,
 ) -> Result<SubscriptionToken, sqlx::Error> {
     let subscription_token = SubscriptionToken::new();
     let query = sqlx::query!(
         r#"
-        INSERT INTO subscription_tokens (subscription_id, token)
-        VALUES ($1, $2)
+        INSERT INTO subscription_tokens (subscription_id, token, list_id)
+        VALUES ($1, $2, $3)
         "#,
         subscriber_id.0,
         subscription_token.0,
+        list.id,
     );
 
-    transaction.execute(query).await.map_err(|e| {
+    transaction.execute(query).await.inspect_err(|_| {
         tracing::error!("Failed to execute query");
-        e
     })?;
 
     Ok(subscription_token)
@@ -83,17 +519,18 @@ async fn insert_subscription_token(
 
 #[tracing::instrument(
     name = "Sending welcome email to new subscriber.",
-    skip(email_client, subscriber, base_url, subscription_token)
+    skip(email_client, subscriber, list, base_url, subscription_token)
 )]
 async fn send_welcome_email(
     email_client: &EmailClient,
     subscriber: &NewSubscriber,
+    list: &MailingList,
     base_url: &ApplicationBaseUrl,
     subscription_token: &SubscriptionToken,
-) -> Result<(), reqwest::Error> {
+) -> Result<(), SendEmailError> {
     let email_body = format!(
-        "Welcome to our newsletter <a href=\"{}/subscriptions/confirm?token={}\">here</a>",
-        base_url.0, subscription_token.0
+        "Welcome to {}! Confirm your subscription <a href=\"{}/subscriptions/confirm?token={}\">here</a>",
+        list.name, base_url.0, subscription_token.0
     );
 
     email_client
@@ -111,56 +548,29 @@ pub async fn insert_subscription(
     transaction: &mut Transaction<'_, Postgres>,
     subscriber: &NewSubscriber,
 ) -> Result<SubscriberId, sqlx::Error> {
-    let subscriber_id = SubscriberId::new();
-    let query = sqlx::query!(
+    // Subscribers joining another list keep their existing record.
+    let subscriber = sqlx::query!(
         r#"
-        INSERT INTO subscriptions (id, name, email, subscribed_at, status)
-        VALUES ($1, $2, $3, $4, 'pending_verification')
+        INSERT INTO subscriptions (
+            id, name, email, subscribed_at, status, timezone, custom_fields
+        )
+        VALUES ($1, $2, $3, $4, 'pending_verification', $5, $6)
+        ON CONFLICT (lower(email)) WHERE duplicate_of IS NULL
+        DO UPDATE SET email = subscriptions.email
+        RETURNING id
         "#,
-        subscriber_id.0,
+        SubscriberId::new().0,
         subscriber.name.as_ref(),
         subscriber.email.as_ref(),
-        chrono::Utc::now()
-    );
-    transaction.execute(query).await.map_err(|e| {
+        chrono::Utc::now(),
+        subscriber.timezone.as_ref().map(AsRef::as_ref),
+        subscriber.custom_fields.as_json(),
+    )
+    .fetch_one(&mut **transaction)
+    .await
+    .inspect_err(|e| {
         tracing::error!("Failed to execute query {:?}.", e);
-        e
     })?;
 
-    Ok(subscriber_id)
-}
-
-impl TryFrom<FormData> for NewSubscriber {
-    type Error = String;
-
-    fn try_from(value: FormData) -> Result<Self, Self::Error> {
-        let name = SubscriberName::parse(value.name)?;
-        let email = SubscriberEmail::parse(value.email)?;
-        Ok(NewSubscriber { name, email })
-    }
-}
-
-#[derive(thiserror::Error, Debug)]
-pub enum SubscribeError {
-    #[error("Failed to query.")]
-    DatabaseError(#[from] sqlx::Error),
-    #[error("{0}")]
-    ValidationError(String),
-    #[error("Error when sending a confirmation email")]
-    ConfirmationError(#[from] reqwest::Error),
-}
-
-impl From<String> for SubscribeError {
-    fn from(value: String) -> Self {
-        SubscribeError::ValidationError(value)
-    }
-}
-
-impl ResponseError for SubscribeError {
-    fn status_code(&self) -> reqwest::StatusCode {
-        match self {
-            Self::ValidationError(_) => StatusCode::BAD_REQUEST,
-            _ => StatusCode::INTERNAL_SERVER_ERROR,
-        }
-    }
+    Ok(SubscriberId(subscriber.id))
 }
@@ -0,0 +1,99 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::domain::escape_html;
+use crate::error::AppError;
+use crate::signed_links::{LinkPurpose, SignedLinks};
+
+#[derive(serde::Deserialize)]
+pub struct UnsubscribeToken {
+    token: String,
+}
+
+// Mail scanners follow links, so the GET only asks for confirmation.
+#[tracing::instrument(name = "Showing the unsubscribe form.", skip(token, signed_links))]
+pub async fn unsubscribe_form(
+    token: web::Query<UnsubscribeToken>,
+    signed_links: web::Data<SignedLinks>,
+) -> Result<HttpResponse, AppError> {
+    signed_links
+        .verify(LinkPurpose::Unsubscribe, &token.token)
+        .map_err(|e| AppError::ValidationError(e.to_string()))?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head><meta charset="utf-8"><title>Unsubscribe</title></head>
+<body>
+<form method="post" action="?token={}">
+<p>Stop receiving our emails?</p>
+<button type="submit">Unsubscribe</button>
+</form>
+</body>
+</html>"#,
+            escape_html(&token.token)
+        )))
+}
+
+#[tracing::instrument(name = "Unsubscribing a subscriber.", skip(token, pool, signed_links))]
+pub async fn unsubscribe(
+    token: web::Query<UnsubscribeToken>,
+    pool: web::Data<PgPool>,
+    signed_links: web::Data<SignedLinks>,
+) -> Result<HttpResponse, AppError> {
+    let subscriber_id = signed_links
+        .verify(LinkPurpose::Unsubscribe, &token.token)
+        .map_err(|e| AppError::ValidationError(e.to_string()))?;
+
+    let mut transaction = pool.begin().await?;
+    sqlx::query!(
+        r#"
+        UPDATE list_memberships
+        SET status = 'unsubscribed'
+        WHERE subscriber_id = $1
+        "#,
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .inspect_err(|e| {
+        tracing::error!("Failed to execute query {:?}.", e);
+    })?;
+    cancel_left_deliveries(&mut transaction, subscriber_id).await?;
+    transaction.commit().await?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body("<p>You have been unsubscribed.</p>"))
+}
+
+/// Drops the queued deliveries to a subscriber of issues sent only to lists
+/// they are no longer confirmed on, including those held for their digest.
+pub async fn cancel_left_deliveries(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_queue q
+        USING subscriptions s
+        WHERE s.id = $1
+            AND q.subscriber_email = s.email
+            AND NOT EXISTS (
+                SELECT 1
+                FROM newsletter_issue_lists il
+                JOIN list_memberships m ON m.list_id = il.list_id
+                WHERE il.newsletter_issue_id = q.newsletter_issue_id
+                    AND m.subscriber_id = $1
+                    AND m.status = 'confirmed'
+            )
+        "#,
+        subscriber_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
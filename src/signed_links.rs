@@ -0,0 +1,181 @@
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use uuid::Uuid;
+
+/// What a signed token grants access to. Tokens minted for one purpose are
+/// rejected for any other.
+#[derive(Debug, Clone, Copy)]
+pub enum LinkPurpose {
+    Unsubscribe,
+    Preferences,
+    SignupForm,
+}
+
+impl LinkPurpose {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Unsubscribe => "unsubscribe",
+            Self::Preferences => "preferences",
+            Self::SignupForm => "signup_form",
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum InvalidToken {
+    #[error("The link is malformed or has been tampered with.")]
+    Malformed,
+    #[error("The link has expired.")]
+    Expired,
+}
+
+/// Builds and verifies links carrying an HMAC-signed subscriber id, so that
+/// subscribers can manage their subscription without an account.
+///
+/// Tokens have the form `<subscriber id>.<expiry as unix seconds, 0 if none>.<hex signature>`.
+#[derive(Clone)]
+pub struct SignedLinks {
+    base_url: String,
+    key: Secret<String>,
+}
+
+impl SignedLinks {
+    pub fn new(base_url: String, key: Secret<String>) -> Self {
+        Self { base_url, key }
+    }
+
+    pub fn unsubscribe_url(&self, subscriber_id: Uuid) -> String {
+        format!(
+            "{}/subscriptions/unsubscribe?token={}",
+            self.base_url,
+            self.sign(LinkPurpose::Unsubscribe, subscriber_id, None)
+        )
+    }
+
+    pub fn preferences_url(&self, subscriber_id: Uuid, expires_at: DateTime<Utc>) -> String {
+        format!(
+            "{}/subscriptions/preferences?token={}",
+            self.base_url,
+            self.sign(LinkPurpose::Preferences, subscriber_id, Some(expires_at))
+        )
+    }
+
+    pub fn sign(
+        &self,
+        purpose: LinkPurpose,
+        subscriber_id: Uuid,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> String {
+        let payload = format!(
+            "{}.{}",
+            subscriber_id,
+            expires_at.map_or(0, |expires_at| expires_at.timestamp())
+        );
+        let signature = self.mac(purpose, &payload).finalize().into_bytes();
+        format!("{}.{}", payload, hex::encode(signature))
+    }
+
+    pub fn verify(&self, purpose: LinkPurpose, token: &str) -> Result<Uuid, InvalidToken> {
+        self.verify_with_expiry(purpose, token)
+            .map(|(subscriber_id, _)| subscriber_id)
+    }
+
+    /// Like `verify`, also returning when the token expires.
+    pub fn verify_with_expiry(
+        &self,
+        purpose: LinkPurpose,
+        token: &str,
+    ) -> Result<(Uuid, Option<DateTime<Utc>>), InvalidToken> {
+        let (payload, signature) = token.rsplit_once('.').ok_or(InvalidToken::Malformed)?;
+        let signature = hex::decode(signature).map_err(|_| InvalidToken::Malformed)?;
+        self.mac(purpose, payload)
+            .verify_slice(&signature)
+            .map_err(|_| InvalidToken::Malformed)?;
+
+        let (subscriber_id, expires_at) = payload.split_once('.').ok_or(InvalidToken::Malformed)?;
+        let expires_at = expires_at
+            .parse::<i64>()
+            .map_err(|_| InvalidToken::Malformed)?;
+        if expires_at != 0 && expires_at < Utc::now().timestamp() {
+            return Err(InvalidToken::Expired);
+        }
+        let subscriber_id = Uuid::parse_str(subscriber_id).map_err(|_| InvalidToken::Malformed)?;
+        let expires_at = match expires_at {
+            0 => None,
+            expires_at => {
+                Some(DateTime::from_timestamp(expires_at, 0).ok_or(InvalidToken::Malformed)?)
+            }
+        };
+        Ok((subscriber_id, expires_at))
+    }
+
+    fn mac(&self, purpose: LinkPurpose, payload: &str) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.key.expose_secret().as_bytes())
+            .expect("HMAC accepts keys of any length.");
+        mac.update(purpose.as_str().as_bytes());
+        mac.update(b":");
+        mac.update(payload.as_bytes());
+        mac
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{InvalidToken, LinkPurpose, SignedLinks};
+    use claims::{assert_err_eq, assert_ok_eq};
+    use uuid::Uuid;
+
+    fn links(key: &str) -> SignedLinks {
+        SignedLinks::new("http://localhost".to_string(), key.to_string().into())
+    }
+
+    #[test]
+    fn signed_tokens_verify() {
+        let id = Uuid::new_v4();
+        let token = links("key").sign(LinkPurpose::Unsubscribe, id, None);
+        assert_ok_eq!(links("key").verify(LinkPurpose::Unsubscribe, &token), id);
+    }
+
+    #[test]
+    fn tokens_signed_with_another_key_are_rejected() {
+        let token = links("key").sign(LinkPurpose::Unsubscribe, Uuid::new_v4(), None);
+        assert_err_eq!(
+            links("other key").verify(LinkPurpose::Unsubscribe, &token),
+            InvalidToken::Malformed
+        );
+    }
+
+    #[test]
+    fn tampered_tokens_are_rejected() {
+        let token = links("key").sign(LinkPurpose::Unsubscribe, Uuid::new_v4(), None);
+        let (_, rest) = token.split_once('.').unwrap();
+        let tampered = format!("{}.{}", Uuid::new_v4(), rest);
+        for token in [tampered.as_str(), "", "not-a-token", "a.b.c"] {
+            assert_err_eq!(
+                links("key").verify(LinkPurpose::Unsubscribe, token),
+                InvalidToken::Malformed
+            );
+        }
+    }
+
+    #[test]
+    fn tokens_are_bound_to_their_purpose() {
+        let token = links("key").sign(LinkPurpose::Unsubscribe, Uuid::new_v4(), None);
+        assert_err_eq!(
+            links("key").verify(LinkPurpose::Preferences, &token),
+            InvalidToken::Malformed
+        );
+    }
+
+    #[test]
+    fn expired_tokens_are_rejected() {
+        let expired = chrono::Utc::now() - chrono::Duration::minutes(1);
+        let token = links("key").sign(LinkPurpose::Unsubscribe, Uuid::new_v4(), Some(expired));
+        assert_err_eq!(
+            links("key").verify(LinkPurpose::Unsubscribe, &token),
+            InvalidToken::Expired
+        );
+    }
+}
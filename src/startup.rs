@@ -1,21 +1,49 @@
+use crate::admin_auth::AdminAuth;
+use crate::bot_protection::{BotProtection, HumanVerifier};
 use crate::configuration::Settings;
+use crate::domain::EmailPolicy;
 use crate::email_client::EmailClient;
+use crate::error::{extractor_error, not_found, propagate_request_context, AppError};
 use crate::factory;
+use crate::issue_delivery_worker;
+use crate::mail_domain::{MailDomainCheck, MailDomainResolver};
+use crate::newsletter_scheduler;
+use crate::rate_limit::{self, RateLimiter, TrustedProxies};
 use crate::routes::{
-    confirm_subscription, health_check, publish_newsletter, subscribe, ApplicationBaseUrl,
+    block_domain, cancel_newsletter_schedule, confirm_email_change, confirm_subscription,
+    create_custom_field, create_list, create_newsletter, email_change_form, erase_preferences_data,
+    erase_subscriber, export_preferences_data, export_subscriber_data, get_blocked_domains,
+    get_custom_fields, get_lists, get_subscriber, health_check, preferences_form,
+    preview_newsletter, publish_newsletter, request_email_change, schedule_newsletter,
+    send_preferences_link, set_subscriber_fields, set_subscriber_tags, signup_form_token,
+    subscribe, test_send_newsletter, unblock_domain, unsubscribe, unsubscribe_form,
+    update_newsletter, update_preferences, ApplicationBaseUrl,
 };
+use crate::signed_links::SignedLinks;
+use crate::suppression::{self, EmailHasher};
 use actix_web::dev::Server;
+use actix_web::middleware::Condition;
 use actix_web::{web, App, HttpServer};
 use sqlx::PgPool;
 use tracing_actix_web::TracingLogger;
 
 use std::net::TcpListener;
+use std::sync::Arc;
 
 pub struct NewsletterApp {
     port: u16,
     listener: TcpListener,
     pg_pool: PgPool,
-    email_client: EmailClient,
+    email_client: Arc<EmailClient>,
+    signed_links: SignedLinks,
+    email_hasher: EmailHasher,
+    email_policy: EmailPolicy,
+    mail_domain_check: MailDomainCheck,
+    bot_protection: BotProtection,
+    rate_limiter: RateLimiter,
+    rate_limiting: bool,
+    trusted_proxies: TrustedProxies,
+    admin_auth: AdminAuth,
     base_url: String,
 }
 
@@ -40,34 +68,203 @@ impl NewsletterApp {
         listener: TcpListener,
     ) -> Result<NewsletterApp, std::io::Error> {
         let pg_pool = factory::get_pool_with(&configuration.database).await;
-        let email_client = factory::get_email_client(&configuration.email_client);
+        let email_client = Arc::new(factory::get_email_client(&configuration.email_client));
         let port = listener.local_addr().unwrap().port();
+        let email_hasher = EmailHasher::new(configuration.application.hmac_secret.clone());
+        suppression::rekey_legacy_hashes(&pg_pool, &email_hasher)
+            .await
+            .map_err(std::io::Error::other)?;
+        let signed_links = SignedLinks::new(
+            configuration.application.base_url.clone(),
+            configuration.application.hmac_secret,
+        );
+        // Also remembers used form tokens, so that replicas sharing a
+        // Postgres store reject tokens used on one another.
+        let rate_limit_store =
+            factory::get_rate_limit_store(&configuration.rate_limit, pg_pool.clone());
+        let bot_protection = factory::get_bot_protection(
+            &configuration.bot_protection,
+            signed_links.clone(),
+            rate_limit_store.clone(),
+        );
+        let rate_limiter = factory::get_rate_limiter(
+            &configuration.rate_limit,
+            rate_limit_store,
+            email_hasher.clone(),
+        );
         Ok(NewsletterApp {
             listener,
             port,
             pg_pool,
             email_client,
+            signed_links,
+            email_hasher,
+            email_policy: EmailPolicy::new(configuration.email_policy.reject_role_accounts),
+            mail_domain_check: factory::get_mail_domain_check(&configuration.email_policy),
+            bot_protection,
+            rate_limiter,
+            rate_limiting: configuration.rate_limit.enabled,
+            trusted_proxies: TrustedProxies::new(configuration.rate_limit.trusted_proxies),
+            admin_auth: AdminAuth::new(configuration.application.admin_token),
             base_url: configuration.application.base_url,
         })
     }
 
+    /// Checks that the domains of new subscribers can receive mail with
+    /// `resolver`, whether or not the check is enabled in the settings.
+    pub fn with_mail_domain_resolver(mut self, resolver: Arc<dyn MailDomainResolver>) -> Self {
+        self.mail_domain_check = self.mail_domain_check.with_resolver(resolver);
+        self
+    }
+
+    /// Verifies that signups are made by people with `verifier`, whether or
+    /// not a proof of work is configured.
+    pub fn with_human_verifier(mut self, verifier: Arc<dyn HumanVerifier>) -> Self {
+        self.bot_protection = self.bot_protection.with_verifier(verifier);
+        self
+    }
+
+    pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
+        let delivery_worker = issue_delivery_worker::run_worker_until_stopped(
+            self.pg_pool.clone(),
+            self.email_client.clone(),
+            self.signed_links.clone(),
+        );
+        let scheduler = newsletter_scheduler::run_scheduler_until_stopped(self.pg_pool.clone());
+        let rate_limit_sweeper = rate_limit::run_sweeper_until_stopped(self.pg_pool.clone());
+        let server = self.run()?;
+
+        tokio::select! {
+            outcome = server => outcome,
+            () = delivery_worker => Ok(()),
+            () = scheduler => Ok(()),
+            () = rate_limit_sweeper => Ok(()),
+        }
+    }
+
     pub fn run(self) -> Result<Server, std::io::Error> {
         let pool = web::Data::new(self.pg_pool);
-        let email_client = web::Data::new(self.email_client);
+        let email_client = web::Data::from(self.email_client);
         let application_url = web::Data::new(ApplicationBaseUrl(self.base_url.clone()));
+        let signed_links = web::Data::new(self.signed_links);
+        let email_hasher = web::Data::new(self.email_hasher);
+        let email_policy = web::Data::new(self.email_policy);
+        let mail_domain_check = web::Data::new(self.mail_domain_check);
+        let bot_protection = web::Data::new(self.bot_protection);
+        let trusted_proxies = web::Data::new(self.trusted_proxies);
+        let admin_auth = self.admin_auth;
+        let rate_limiter = self.rate_limiter;
+        let rate_limiting = self.rate_limiting;
         let server = HttpServer::new(move || {
             App::new()
+                .wrap(Condition::new(rate_limiting, rate_limiter.clone()))
+                .wrap_fn(propagate_request_context)
                 .wrap(TracingLogger::default())
                 .route("/health_check", web::get().to(health_check))
                 .route("/subscriptions", web::post().to(subscribe))
+                .route(
+                    "/subscriptions/form-token",
+                    web::get().to(signup_form_token),
+                )
                 .route(
                     "/subscriptions/confirm",
                     web::post().to(confirm_subscription),
                 )
-                .route("/newsletter", web::post().to(publish_newsletter))
+                .route(
+                    "/subscriptions/unsubscribe",
+                    web::get().to(unsubscribe_form),
+                )
+                .route("/subscriptions/unsubscribe", web::post().to(unsubscribe))
+                .route(
+                    "/subscriptions/preferences",
+                    web::get().to(preferences_form),
+                )
+                .route(
+                    "/subscriptions/preferences",
+                    web::post().to(update_preferences),
+                )
+                .route(
+                    "/subscriptions/preferences/link",
+                    web::post().to(send_preferences_link),
+                )
+                .route(
+                    "/subscriptions/preferences/export",
+                    web::get().to(export_preferences_data),
+                )
+                .route(
+                    "/subscriptions/preferences/erase",
+                    web::post().to(erase_preferences_data),
+                )
+                .route("/subscriptions/email", web::post().to(request_email_change))
+                .route(
+                    "/subscriptions/email/confirm",
+                    web::get().to(email_change_form),
+                )
+                .route(
+                    "/subscriptions/email/confirm",
+                    web::post().to(confirm_email_change),
+                )
+                .service(
+                    web::scope("/admin")
+                        .wrap(admin_auth.clone())
+                        .route("/lists", web::get().to(get_lists))
+                        .route("/lists", web::post().to(create_list))
+                        .route("/custom-fields", web::get().to(get_custom_fields))
+                        .route("/custom-fields", web::post().to(create_custom_field))
+                        .route("/blocked-domains", web::get().to(get_blocked_domains))
+                        .route("/blocked-domains", web::post().to(block_domain))
+                        .route(
+                            "/blocked-domains/{domain}",
+                            web::delete().to(unblock_domain),
+                        )
+                        .route("/subscribers/export", web::get().to(export_subscriber_data))
+                        .route("/subscribers/{id}", web::get().to(get_subscriber))
+                        .route("/subscribers/{id}", web::delete().to(erase_subscriber))
+                        .route(
+                            "/subscribers/{id}/fields",
+                            web::put().to(set_subscriber_fields),
+                        )
+                        .route("/subscribers/{id}/tags", web::put().to(set_subscriber_tags))
+                        .route("/newsletters", web::post().to(create_newsletter))
+                        .route("/newsletters/{id}", web::put().to(update_newsletter))
+                        .route(
+                            "/newsletters/{id}/preview",
+                            web::get().to(preview_newsletter),
+                        )
+                        .route(
+                            "/newsletters/{id}/test",
+                            web::post().to(test_send_newsletter),
+                        )
+                        .route(
+                            "/newsletters/{id}/publish",
+                            web::post().to(publish_newsletter),
+                        )
+                        .route(
+                            "/newsletters/{id}/schedule",
+                            web::put().to(schedule_newsletter),
+                        )
+                        .route(
+                            "/newsletters/{id}/schedule",
+                            web::delete().to(cancel_newsletter_schedule),
+                        ),
+                )
                 .app_data(pool.clone())
                 .app_data(email_client.clone())
                 .app_data(application_url.clone())
+                .app_data(signed_links.clone())
+                .app_data(email_hasher.clone())
+                .app_data(email_policy.clone())
+                .app_data(mail_domain_check.clone())
+                .app_data(bot_protection.clone())
+                .app_data(trusted_proxies.clone())
+                .app_data(web::JsonConfig::default().error_handler(|e, _| extractor_error(e)))
+                .app_data(web::FormConfig::default().error_handler(|e, _| extractor_error(e)))
+                .app_data(web::QueryConfig::default().error_handler(|e, _| extractor_error(e)))
+                .app_data(
+                    web::PathConfig::default()
+                        .error_handler(|e, _| AppError::NotFound(e.to_string()).into()),
+                )
+                .default_service(web::route().to(not_found))
         })
         .listen(self.listener)?
         .run();
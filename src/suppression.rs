@@ -0,0 +1,135 @@
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use sqlx::{PgExecutor, PgPool};
+
+/// Hashes addresses with a key, so that erased addresses can be recognised
+/// if they are submitted again but not recovered by hashing candidate
+/// addresses without the key.
+#[derive(Clone)]
+pub struct EmailHasher {
+    key: Secret<String>,
+}
+
+impl EmailHasher {
+    pub fn new(key: Secret<String>) -> Self {
+        Self { key }
+    }
+
+    /// An HMAC of the unkeyed SHA-256 of the address that earlier versions
+    /// stored, so that those can be rekeyed without knowing the address.
+    pub fn hash(&self, email: &str) -> String {
+        self.rekey(&legacy_email_hash(email))
+    }
+
+    fn rekey(&self, legacy_hash: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.key.expose_secret().as_bytes())
+            .expect("HMAC accepts keys of any length.");
+        mac.update(b"email_hash:");
+        mac.update(legacy_hash.as_bytes());
+        hex::encode(mac.finalize().into_bytes())
+    }
+}
+
+fn legacy_email_hash(email: &str) -> String {
+    hex::encode(Sha256::digest(email.trim().to_lowercase().as_bytes()))
+}
+
+#[tracing::instrument(name = "Suppressing an email address.", skip_all)]
+pub async fn suppress_email(
+    executor: impl PgExecutor<'_>,
+    hasher: &EmailHasher,
+    email: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "INSERT INTO suppressed_emails (email_hash, keyed) VALUES ($1, TRUE) ON CONFLICT DO NOTHING",
+        hasher.hash(email)
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Checking the suppression list.", skip_all)]
+pub async fn is_email_suppressed(
+    executor: impl PgExecutor<'_>,
+    hasher: &EmailHasher,
+    email: &str,
+) -> Result<bool, sqlx::Error> {
+    let suppressed = sqlx::query!(
+        "SELECT email_hash FROM suppressed_emails WHERE email_hash = $1",
+        hasher.hash(email)
+    )
+    .fetch_optional(executor)
+    .await?;
+    Ok(suppressed.is_some())
+}
+
+/// Replaces the unkeyed hashes stored by earlier versions with keyed ones.
+/// Safe to run concurrently from several replicas.
+#[tracing::instrument(name = "Rekeying the suppression list.", skip_all)]
+pub async fn rekey_legacy_hashes(pool: &PgPool, hasher: &EmailHasher) -> Result<(), sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let legacy = sqlx::query!(
+        "SELECT email_hash FROM suppressed_emails WHERE NOT keyed FOR UPDATE SKIP LOCKED"
+    )
+    .fetch_all(&mut *transaction)
+    .await?;
+    for row in &legacy {
+        sqlx::query!(
+            "INSERT INTO suppressed_emails (email_hash, keyed) VALUES ($1, TRUE) ON CONFLICT DO NOTHING",
+            hasher.rekey(&row.email_hash)
+        )
+        .execute(&mut *transaction)
+        .await?;
+        sqlx::query!(
+            "DELETE FROM suppressed_emails WHERE email_hash = $1 AND NOT keyed",
+            row.email_hash
+        )
+        .execute(&mut *transaction)
+        .await?;
+    }
+    transaction.commit().await?;
+    if !legacy.is_empty() {
+        tracing::info!("Rekeyed {} suppressed email hashes.", legacy.len());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{legacy_email_hash, EmailHasher};
+
+    fn hasher(key: &str) -> EmailHasher {
+        EmailHasher::new(key.to_string().into())
+    }
+
+    #[test]
+    fn hashes_ignore_case_and_surrounding_whitespace() {
+        assert_eq!(
+            hasher("key").hash(" Ursula@Example.com "),
+            hasher("key").hash("ursula@example.com")
+        );
+        assert_ne!(
+            hasher("key").hash("ursula@example.com"),
+            "ursula@example.com"
+        );
+    }
+
+    #[test]
+    fn hashes_cannot_be_computed_without_the_key() {
+        let email = "ursula@example.com";
+        let hash = hasher("key").hash(email);
+        assert_ne!(hash, legacy_email_hash(email));
+        assert_ne!(hash, hasher("other key").hash(email));
+    }
+
+    #[test]
+    fn legacy_hashes_rekey_to_the_hash_of_the_address() {
+        let email = "ursula@example.com";
+        assert_eq!(
+            hasher("key").rekey(&legacy_email_hash(email)),
+            hasher("key").hash(email)
+        );
+    }
+}
@@ -0,0 +1,59 @@
+use crate::helpers::{client, create_confirmed_subscriber, get, spawn_app, ADMIN_TOKEN};
+
+const EXPORT_PATH: &str = "admin/subscribers/export?email=ursula_le_guin%40gmail.com";
+
+#[tokio::test]
+async fn admin_requests_without_the_admin_token_are_rejected() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let url = format!("{}/{}", app.address, EXPORT_PATH);
+
+    let test_cases = [
+        (client().get(&url), "no credentials"),
+        (
+            client().get(&url).bearer_auth("not-the-token"),
+            "wrong token",
+        ),
+        (
+            client().get(&url).basic_auth("admin", Some(ADMIN_TOKEN)),
+            "basic credentials",
+        ),
+    ];
+    for (request, description) in test_cases {
+        let response = request.send().await.unwrap();
+
+        assert_eq!(response.status().as_u16(), 401, "{}", description);
+        assert_eq!(
+            response.headers()["WWW-Authenticate"],
+            "Bearer",
+            "{}",
+            description
+        );
+        let problem: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(problem["status"], 401);
+        assert!(problem.get("subscriber").is_none());
+    }
+}
+
+#[tokio::test]
+async fn admin_requests_with_the_admin_token_are_admitted() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let response = get(&app.address, EXPORT_PATH).send().await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn public_endpoints_do_not_need_the_admin_token() {
+    let app = spawn_app().await;
+
+    let response = client()
+        .get(format!("{}/health_check", app.address))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+}
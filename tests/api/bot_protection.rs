@@ -0,0 +1,226 @@
+use std::sync::Arc;
+
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+use zero2prod::bot_protection::{ProofOfWork, HONEYPOT_FIELD};
+use zero2prod::configuration::RateLimitStoreKind;
+
+use crate::helpers::{
+    get, post, spawn_app, spawn_app_with, spawn_app_with_human_verifier, TestApp,
+};
+
+async fn form_token(app: &TestApp) -> String {
+    let response = get(&app.address, "subscriptions/form-token")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(200, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    body["token"].as_str().unwrap().to_string()
+}
+
+async fn subscribe(app: &TestApp, extra: &[(&str, &str)]) -> reqwest::Response {
+    let mut form = vec![("name", "le guin"), ("email", "ursula_le_guin@gmail.com")];
+    form.extend_from_slice(extra);
+    post(&app.address, "subscriptions")
+        .form(&form)
+        .send()
+        .await
+        .unwrap()
+}
+
+/// Nothing a bot submits may be saved or sent.
+async fn assert_nothing_happened(app: &TestApp) {
+    let subscribers = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_all(&app.pool)
+        .await
+        .unwrap();
+    assert!(subscribers.is_empty());
+    assert!(app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+async fn mount_email_server(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+}
+
+#[tokio::test]
+async fn a_filled_in_honeypot_is_rejected() {
+    let app = spawn_app().await;
+    mount_email_server(&app).await;
+
+    let response = subscribe(&app, &[(HONEYPOT_FIELD, "https://spam.example")]).await;
+
+    assert_eq!(400, response.status().as_u16());
+    assert_nothing_happened(&app).await;
+}
+
+#[tokio::test]
+async fn an_empty_honeypot_is_accepted() {
+    let app = spawn_app().await;
+    mount_email_server(&app).await;
+
+    let response = subscribe(&app, &[(HONEYPOT_FIELD, "")]).await;
+
+    assert_eq!(200, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn the_form_token_is_required_when_configured() {
+    let app = spawn_app_with(|config| config.bot_protection.require_form_token = true).await;
+    mount_email_server(&app).await;
+
+    let response = subscribe(&app, &[]).await;
+
+    assert_eq!(400, response.status().as_u16());
+    assert_nothing_happened(&app).await;
+}
+
+#[tokio::test]
+async fn forms_submitted_too_quickly_are_rejected() {
+    let app = spawn_app_with(|config| {
+        config.bot_protection.require_form_token = true;
+        config.bot_protection.min_fill_secs = 60;
+    })
+    .await;
+    mount_email_server(&app).await;
+
+    let token = form_token(&app).await;
+    let response = subscribe(&app, &[("form_token", &token)]).await;
+
+    assert_eq!(400, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        body["detail"],
+        "The form was submitted too quickly, please try again."
+    );
+    assert_nothing_happened(&app).await;
+}
+
+#[tokio::test]
+async fn forms_submitted_with_a_valid_token_are_accepted() {
+    let app = spawn_app_with(|config| {
+        config.bot_protection.require_form_token = true;
+        config.bot_protection.min_fill_secs = 0;
+    })
+    .await;
+    mount_email_server(&app).await;
+
+    let token = form_token(&app).await;
+    let response = subscribe(&app, &[("form_token", &token)]).await;
+
+    assert_eq!(200, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn form_tokens_cannot_be_replayed() {
+    for store in [RateLimitStoreKind::Memory, RateLimitStoreKind::Postgres] {
+        let app = spawn_app_with(|config| {
+            config.bot_protection.require_form_token = true;
+            config.bot_protection.min_fill_secs = 0;
+            config.rate_limit.store = store;
+        })
+        .await;
+        mount_email_server(&app).await;
+
+        let token = form_token(&app).await;
+        let response = subscribe(&app, &[("form_token", &token)]).await;
+        assert_eq!(200, response.status().as_u16());
+
+        let response = post(&app.address, "subscriptions")
+            .form(&[
+                ("name", "octavia butler"),
+                ("email", "octavia_butler@gmail.com"),
+                ("form_token", &token),
+            ])
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(400, response.status().as_u16());
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(
+            body["detail"],
+            "The form token has already been used, please reload the form."
+        );
+        let subscribers = sqlx::query!("SELECT email FROM subscriptions")
+            .fetch_all(&app.pool)
+            .await
+            .unwrap();
+        assert_eq!(subscribers.len(), 1);
+    }
+}
+
+#[tokio::test]
+async fn form_tokens_survive_signups_with_invalid_fields() {
+    let app = spawn_app_with(|config| {
+        config.bot_protection.require_form_token = true;
+        config.bot_protection.min_fill_secs = 0;
+    })
+    .await;
+    mount_email_server(&app).await;
+    let token = form_token(&app).await;
+
+    let response = post(&app.address, "subscriptions")
+        .form(&[
+            ("name", "le guin"),
+            ("email", "ursula_le_guin"),
+            ("form_token", &token),
+        ])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(400, response.status().as_u16());
+
+    let response = subscribe(&app, &[("form_token", &token)]).await;
+    assert_eq!(200, response.status().as_u16());
+    let subscribers = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_all(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(subscribers.len(), 1);
+}
+
+#[tokio::test]
+async fn tampered_form_tokens_are_rejected() {
+    let app = spawn_app_with(|config| config.bot_protection.min_fill_secs = 0).await;
+    mount_email_server(&app).await;
+
+    let token = form_token(&app).await;
+    let tampered = format!("{}0", token);
+    let response = subscribe(&app, &[("form_token", &tampered)]).await;
+
+    assert_eq!(400, response.status().as_u16());
+    assert_nothing_happened(&app).await;
+}
+
+#[tokio::test]
+async fn signups_must_pass_the_human_verification() {
+    let proof_of_work = Arc::new(ProofOfWork::new(16));
+    let app = spawn_app_with_human_verifier(proof_of_work.clone()).await;
+    mount_email_server(&app).await;
+    let token = form_token(&app).await;
+    // Past the minimum time to fill in the form.
+    tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+
+    let response = subscribe(
+        &app,
+        &[("form_token", &token), ("verification", "not-a-nonce")],
+    )
+    .await;
+    assert_eq!(400, response.status().as_u16());
+    assert_nothing_happened(&app).await;
+
+    let nonce = proof_of_work.solve(&token);
+    let response = subscribe(&app, &[("form_token", &token), ("verification", &nonce)]).await;
+    assert_eq!(200, response.status().as_u16());
+}
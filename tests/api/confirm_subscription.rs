@@ -30,7 +30,7 @@ async fn confirm_subscription_link_found_in_email_returns_200() {
         .mount(&app.email_server)
         .await;
 
-    app.post_subscriptions(&body).await.unwrap();
+    app.post_subscriptions(body).await.unwrap();
 
     let body = &app.email_server.received_requests().await.unwrap()[0].body;
     let from_slice: serde_json::Value = serde_json::from_slice(body).unwrap();
@@ -57,7 +57,7 @@ async fn confirm_subscription_link_found_in_email_sets_the_status_of_subscriptio
         .mount(&app.email_server)
         .await;
 
-    app.post_subscriptions(&body).await.unwrap();
+    app.post_subscriptions(body).await.unwrap();
 
     let body = &app.email_server.received_requests().await.unwrap()[0].body;
     let from_slice: serde_json::Value = serde_json::from_slice(body).unwrap();
@@ -78,3 +78,101 @@ async fn confirm_subscription_link_found_in_email_sets_the_status_of_subscriptio
 
     assert_eq!(subscriptions.status, "confirmed");
 }
+
+#[tokio::test]
+async fn confirming_a_list_membership_leaves_other_lists_pending() {
+    let app = spawn_app().await;
+    app.create_list("engineering-digest", "Engineering digest")
+        .await
+        .unwrap();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com")
+        .await
+        .unwrap();
+    app.post_subscriptions(
+        "name=le%20guin&email=ursula_le_guin%40gmail.com&list=engineering-digest",
+    )
+    .await
+    .unwrap();
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[1];
+    let confirmation_link = app.get_confirmation_link(email_request);
+    let response = reqwest::Client::new()
+        .post(confirmation_link)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let memberships = sqlx::query!(
+        r#"
+        SELECT l.slug, m.status, m.confirmed_at
+        FROM list_memberships m
+        JOIN lists l ON l.id = m.list_id
+        ORDER BY l.slug
+        "#
+    )
+    .fetch_all(&app.pool)
+    .await
+    .unwrap();
+    assert_eq!(memberships[0].slug, "engineering-digest");
+    assert_eq!(memberships[0].status, "confirmed");
+    assert!(memberships[0].confirmed_at.is_some());
+    assert_eq!(memberships[1].slug, "newsletter");
+    assert_eq!(memberships[1].status, "pending_verification");
+    assert!(memberships[1].confirmed_at.is_none());
+}
+
+#[tokio::test]
+async fn replaying_a_confirmation_link_does_not_resubscribe() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com")
+        .await
+        .unwrap();
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_link = app.get_confirmation_link(email_request);
+    let response = client().post(&confirmation_link).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let subscriber = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    let unsubscribe_url = app.signed_links.unsubscribe_url(subscriber.id);
+    let response = client().post(&unsubscribe_url).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = client().post(&confirmation_link).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let membership = sqlx::query!("SELECT status FROM list_memberships")
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(membership.status, "unsubscribed");
+
+    // Subscribing again takes a new confirmation.
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com")
+        .await
+        .unwrap();
+    let email_request = app.email_server.received_requests().await.unwrap();
+    let confirmation_link = app.get_confirmation_link(email_request.last().unwrap());
+    let response = client().post(&confirmation_link).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let membership = sqlx::query!("SELECT status FROM list_memberships")
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(membership.status, "confirmed");
+}
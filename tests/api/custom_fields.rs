@@ -0,0 +1,237 @@
+use serde_json::json;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{create_confirmed_subscriber, get, put, spawn_app, TestApp};
+
+async fn create_fields(app: &TestApp) {
+    let fields = [
+        json!({ "key": "company", "label": "Company", "type": "string" }),
+        json!({ "key": "seats", "label": "Seats", "type": "number" }),
+        json!({ "key": "renewal", "label": "Renewal date", "type": "date" }),
+        json!({ "key": "plan", "label": "Plan", "type": "enum", "options": ["free", "pro"] }),
+    ];
+    for field in fields {
+        let response = app.create_custom_field(&field).await.unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+    }
+}
+
+async fn mount_email_server(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+}
+
+async fn subscriber_id(app: &TestApp) -> uuid::Uuid {
+    sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.pool)
+        .await
+        .unwrap()
+        .id
+}
+
+#[tokio::test]
+async fn custom_fields_are_listed_in_creation_order() {
+    let app = spawn_app().await;
+    create_fields(&app).await;
+
+    let response = get(&app.address, "admin/custom-fields")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let fields: serde_json::Value = response.json().await.unwrap();
+    let keys = fields
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|field| field["key"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(keys, ["company", "seats", "renewal", "plan"]);
+    assert_eq!(fields[3]["options"], json!(["free", "pro"]));
+}
+
+#[tokio::test]
+async fn invalid_or_duplicate_custom_fields_are_rejected() {
+    let app = spawn_app().await;
+    create_fields(&app).await;
+
+    let test_cases = [
+        (
+            json!({ "key": "company", "label": "Company", "type": "string" }),
+            409,
+        ),
+        (
+            json!({ "key": "email", "label": "Email", "type": "string" }),
+            400,
+        ),
+        (
+            json!({ "key": "tier", "label": "Tier", "type": "enum" }),
+            400,
+        ),
+        (
+            json!({ "key": "tier", "label": "Tier", "type": "boolean" }),
+            400,
+        ),
+    ];
+    for (field, status) in test_cases {
+        let response = app.create_custom_field(&field).await.unwrap();
+        assert_eq!(response.status().as_u16(), status, "{}", field);
+    }
+}
+
+#[tokio::test]
+async fn subscription_form_stores_typed_custom_fields() {
+    let app = spawn_app().await;
+    create_fields(&app).await;
+    mount_email_server(&app).await;
+
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com\
+        &fields%5Bcompany%5D=Acme&fields%5Bseats%5D=12\
+        &fields%5Brenewal%5D=2025-01-31&fields%5Bplan%5D=pro";
+    let response = app.post_subscriptions(body).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT custom_fields FROM subscriptions")
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(
+        saved.custom_fields,
+        json!({ "company": "Acme", "seats": 12, "renewal": "2025-01-31", "plan": "pro" })
+    );
+}
+
+#[tokio::test]
+async fn subscription_form_rejects_invalid_custom_fields() {
+    let app = spawn_app().await;
+    create_fields(&app).await;
+    app.create_custom_field(&json!({
+        "key": "role", "label": "Role", "type": "string", "required": true
+    }))
+    .await
+    .unwrap();
+
+    let test_cases = [
+        (
+            "fields%5Brole%5D=cto&fields%5Bseats%5D=many",
+            "a non-numeric number",
+        ),
+        (
+            "fields%5Brole%5D=cto&fields%5Bplan%5D=gold",
+            "an unknown enum option",
+        ),
+        (
+            "fields%5Brole%5D=cto&fields%5Brenewal%5D=tomorrow",
+            "an invalid date",
+        ),
+        (
+            "fields%5Brole%5D=cto&fields%5Bshoe_size%5D=42",
+            "an undefined field",
+        ),
+        ("fields%5Bcompany%5D=Acme", "a missing required field"),
+    ];
+    for (fields, description) in test_cases {
+        let body = format!("name=le%20guin&email=ursula_le_guin%40gmail.com&{}", fields);
+        let response = app.post_subscriptions(&body).await.unwrap();
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not fail with 400 Bad Request when the form had {}.",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn admin_api_exposes_and_updates_custom_fields() {
+    let app = spawn_app().await;
+    create_fields(&app).await;
+    create_confirmed_subscriber(&app).await;
+    let id = subscriber_id(&app).await;
+
+    let response = put(&app.address, &format!("admin/subscribers/{}/fields", id))
+        .json(&json!({ "fields": { "company": "Acme", "seats": 3 } }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = get(&app.address, &format!("admin/subscribers/{}", id))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let profile: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(profile["email"], "ursula_le_guin@gmail.com");
+    assert_eq!(
+        profile["custom_fields"],
+        json!({ "company": "Acme", "seats": 3 })
+    );
+    assert_eq!(
+        profile["lists"],
+        json!([{ "slug": "newsletter", "status": "confirmed" }])
+    );
+
+    let response = put(&app.address, &format!("admin/subscribers/{}/fields", id))
+        .json(&json!({ "fields": { "seats": "three" } }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn unknown_subscribers_return_404() {
+    let app = spawn_app().await;
+
+    let response = get(
+        &app.address,
+        &format!("admin/subscribers/{}", uuid::Uuid::new_v4()),
+    )
+    .send()
+    .await
+    .unwrap();
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn custom_fields_are_available_as_template_variables() {
+    let app = spawn_app().await;
+    create_fields(&app).await;
+    create_confirmed_subscriber(&app).await;
+    sqlx::query!(r#"UPDATE subscriptions SET custom_fields = '{"company": "<Acme & Co>"}'"#)
+        .execute(&app.pool)
+        .await
+        .unwrap();
+    mount_email_server(&app).await;
+
+    let response = app
+        .create_newsletter(&json!({
+            "title": "Newsletter title",
+            "content": { "markdown": "Hello {{ subscriber.company }}!" }
+        }))
+        .await
+        .unwrap();
+    let body: serde_json::Value = response.json().await.unwrap();
+    app.publish_newsletter(body["id"].as_str().unwrap())
+        .await
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    let requests = app.email_server.received_requests().await.unwrap();
+    let email: serde_json::Value = serde_json::from_slice(&requests.last().unwrap().body).unwrap();
+    assert!(email["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .contains("Hello &lt;Acme &amp; Co&gt;!"));
+    assert!(email["TextBody"]
+        .as_str()
+        .unwrap()
+        .contains("Hello <Acme & Co>!"));
+}
@@ -0,0 +1,130 @@
+use serde_json::json;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{create_confirmed_subscriber, get, spawn_app, TestApp};
+
+async fn subscriber_id(app: &TestApp) -> uuid::Uuid {
+    sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.pool)
+        .await
+        .unwrap()
+        .id
+}
+
+async fn publish_issue(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let response = app
+        .create_newsletter(&json!({
+            "title": "Newsletter title",
+            "content": { "html": "<p>Hi</p>", "text": "Hi" }
+        }))
+        .await
+        .unwrap();
+    let body: serde_json::Value = response.json().await.unwrap();
+    let response = app
+        .publish_newsletter(body["id"].as_str().unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn admins_can_export_everything_stored_about_an_email() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let id = subscriber_id(&app).await;
+    app.set_subscriber_tags(&id.to_string(), &["beta"])
+        .await
+        .unwrap();
+    publish_issue(&app).await;
+
+    let response = get(&app.address, "admin/subscribers/export")
+        .query(&[("email", "ursula_le_guin@gmail.com")])
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let export: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(export["subscription"]["id"], id.to_string());
+    assert_eq!(export["subscription"]["email"], "ursula_le_guin@gmail.com");
+    assert_eq!(export["subscription"]["status"], "confirmed");
+    assert_eq!(export["custom_fields"], json!({}));
+    assert_eq!(export["tags"][0]["tag"], "beta");
+    assert_eq!(export["list_memberships"][0]["list"], "newsletter");
+    assert_eq!(export["list_memberships"][0]["status"], "confirmed");
+    assert_eq!(export["consent_records"][0]["list"], "newsletter");
+    assert!(export["consent_records"][0]["confirmed_at"].is_string());
+    assert_eq!(export["subscription_tokens"][0]["list"], "newsletter");
+    assert_eq!(export["pending_deliveries"], json!([]));
+    let events = export["email_events"].as_array().unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0]["event_type"], "delivered");
+    assert_eq!(events[0]["title"], "Newsletter title");
+}
+
+#[tokio::test]
+async fn exporting_an_unknown_email_returns_404() {
+    let app = spawn_app().await;
+
+    let response = get(&app.address, "admin/subscribers/export")
+        .query(&[("email", "nobody@example.com")])
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn subscribers_can_download_their_data_from_the_preference_center() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let preferences_url = app.signed_links.preferences_url(
+        subscriber_id(&app).await,
+        chrono::Utc::now() + chrono::Duration::hours(1),
+    );
+    let export_url = preferences_url.replace("/preferences?", "/preferences/export?");
+
+    let page = reqwest::get(&preferences_url)
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(page.contains("/subscriptions/preferences/export?token="));
+    let response = reqwest::get(&export_url).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.headers()["Content-Disposition"]
+        .to_str()
+        .unwrap()
+        .starts_with("attachment"));
+    let export: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(export["subscription"]["email"], "ursula_le_guin@gmail.com");
+}
+
+#[tokio::test]
+async fn data_exports_require_a_valid_link() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let id = subscriber_id(&app).await;
+    let unsubscribe_token =
+        app.signed_links
+            .sign(zero2prod::signed_links::LinkPurpose::Unsubscribe, id, None);
+
+    for token in ["forged", unsubscribe_token.as_str()] {
+        let response = get(&app.address, "subscriptions/preferences/export")
+            .query(&[("token", token)])
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 400);
+    }
+}
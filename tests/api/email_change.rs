@@ -0,0 +1,214 @@
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{create_confirmed_subscriber, spawn_app, TestApp};
+
+async fn subscriber_email(app: &TestApp) -> String {
+    sqlx::query!("SELECT email FROM subscriptions WHERE name = 'le guin' ORDER BY subscribed_at")
+        .fetch_all(&app.pool)
+        .await
+        .unwrap()
+        .remove(0)
+        .email
+}
+
+async fn request_email_change(app: &TestApp, email: &str) -> reqwest::Response {
+    let subscriber =
+        sqlx::query!("SELECT id FROM subscriptions WHERE email = 'ursula_le_guin@gmail.com'")
+            .fetch_one(&app.pool)
+            .await
+            .unwrap();
+    let preferences_url = app.signed_links.preferences_url(
+        subscriber.id,
+        chrono::Utc::now() + chrono::Duration::hours(1),
+    );
+    let (_, query) = preferences_url.split_once('?').unwrap();
+    reqwest::Client::new()
+        .post(format!("{}/subscriptions/email?{}", app.address, query))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body(format!("email={}", email.replace('@', "%40")))
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn confirm(link: &str) -> reqwest::Response {
+    reqwest::Client::new().post(link).send().await.unwrap()
+}
+
+fn recipient(request: &wiremock::Request) -> String {
+    let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+    body["To"].as_str().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn email_changes_apply_only_once_confirmed() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let response = request_email_change(&app, "ursula@earthsea.org").await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_email(&app).await, "ursula_le_guin@gmail.com");
+
+    let requests = app.email_server.received_requests().await.unwrap();
+    let confirmation = requests.last().unwrap();
+    assert_eq!(recipient(confirmation), "ursula@earthsea.org");
+    let link = app.get_confirmation_link(confirmation);
+    // Following the link only asks for confirmation.
+    let response = reqwest::get(&link).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("<form method=\"post\""));
+    assert_eq!(subscriber_email(&app).await, "ursula_le_guin@gmail.com");
+
+    let response = confirm(&link).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_email(&app).await, "ursula@earthsea.org");
+
+    let requests = app.email_server.received_requests().await.unwrap();
+    assert_eq!(
+        recipient(requests.last().unwrap()),
+        "ursula_le_guin@gmail.com"
+    );
+
+    // Confirmation links are single use.
+    let response = confirm(&link).await;
+    assert_eq!(response.status().as_u16(), 400);
+    let response = reqwest::get(&link).await.unwrap();
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn only_the_latest_email_change_can_be_confirmed() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    request_email_change(&app, "ursula@earthsea.org").await;
+    let requests = app.email_server.received_requests().await.unwrap();
+    let first_link = app.get_confirmation_link(requests.last().unwrap());
+    request_email_change(&app, "ursula@anarres.org").await;
+
+    let response = confirm(&first_link).await;
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(subscriber_email(&app).await, "ursula_le_guin@gmail.com");
+}
+
+#[tokio::test]
+async fn invalid_email_change_requests_are_rejected() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    for email in [
+        "",
+        "not-an-email",
+        "ursula_le_guin@gmail.com",
+        "ursula@mailinator.com",
+    ] {
+        let response = request_email_change(&app, email).await;
+        assert_eq!(response.status().as_u16(), 400, "{} was accepted", email);
+    }
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/subscriptions/email?token=forged", app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body("email=ursula%40earthsea.org")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn email_changes_to_a_taken_address_are_rejected_on_confirmation() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    request_email_change(&app, "ursula@earthsea.org").await;
+    let requests = app.email_server.received_requests().await.unwrap();
+    let link = app.get_confirmation_link(requests.last().unwrap());
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES (gen_random_uuid(), 'ursula@earthsea.org', 'ursula', now(), 'confirmed')
+        "#
+    )
+    .execute(&app.pool)
+    .await
+    .unwrap();
+
+    let response = confirm(&link).await;
+    assert_eq!(response.status().as_u16(), 409);
+    assert_eq!(subscriber_email(&app).await, "ursula_le_guin@gmail.com");
+}
+
+#[tokio::test]
+async fn queued_deliveries_follow_the_new_address() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let newsletter_issue_id = app.create_draft_newsletter().await;
+    app.publish_newsletter(&newsletter_issue_id).await.unwrap();
+    sqlx::query!("UPDATE issue_delivery_queue SET execute_after = now() + interval '1 hour'")
+        .execute(&app.pool)
+        .await
+        .unwrap();
+
+    request_email_change(&app, "ursula@earthsea.org").await;
+    let requests = app.email_server.received_requests().await.unwrap();
+    let link = app.get_confirmation_link(requests.last().unwrap());
+    let response = confirm(&link).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let queued = sqlx::query!("SELECT subscriber_email FROM issue_delivery_queue")
+        .fetch_all(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(queued.len(), 1);
+    assert_eq!(queued[0].subscriber_email, "ursula@earthsea.org");
+}
+
+#[tokio::test]
+async fn suppressed_addresses_are_not_mailed_an_email_change() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    zero2prod::suppression::suppress_email(&app.pool, &app.email_hasher, "ursula@earthsea.org")
+        .await
+        .unwrap();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = request_email_change(&app, "ursula@earthsea.org").await;
+    assert_eq!(response.status().as_u16(), 200);
+    let requests = sqlx::query!("SELECT token FROM email_change_requests")
+        .fetch_all(&app.pool)
+        .await
+        .unwrap();
+    assert!(requests.is_empty());
+}
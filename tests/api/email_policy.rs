@@ -0,0 +1,220 @@
+use std::{sync::Arc, time::Duration};
+
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use zero2prod::mail_domain::StaticResolver;
+
+use crate::helpers::{
+    delete, get, post, spawn_app, spawn_app_with, spawn_app_with_mail_domain_resolver, TestApp,
+};
+
+async fn assert_email_rejected(app: &TestApp, email: &str, code: &str) {
+    let response = post(&app.address, "subscriptions")
+        .form(&[("name", "le guin"), ("email", email)])
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(400, response.status().as_u16(), "{} was accepted", email);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["errors"][0]["field"], "email");
+    assert_eq!(body["errors"][0]["code"], code);
+}
+
+#[tokio::test]
+async fn subscribe_rejects_disposable_domains() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    for email in ["ursula@mailinator.com", "ursula@eu.yopmail.com"] {
+        assert_email_rejected(&app, email, "disposable_domain").await;
+    }
+
+    let subscribers = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_all(&app.pool)
+        .await
+        .unwrap();
+    assert!(subscribers.is_empty());
+}
+
+#[tokio::test]
+async fn subscribe_rejects_addresses_the_provider_cannot_deliver_to() {
+    let app = spawn_app_with(|config| config.email_client.smtputf8 = false).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    assert_email_rejected(&app, "пользователь@example.com", "unsupported_address").await;
+
+    // Internationalized domains are sent in punycode.
+    let response = post(&app.address, "subscriptions")
+        .form(&[("name", "le guin"), ("email", "ursula@пример.рф")])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(200, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn admins_can_block_and_unblock_domains() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let response = post(&app.address, "admin/blocked-domains")
+        .json(&serde_json::json!({ "domain": " Throwaway.Example " }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(200, response.status().as_u16());
+    let blocked: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(blocked["domain"], "throwaway.example");
+
+    assert_email_rejected(&app, "ursula@mail.throwaway.example", "disposable_domain").await;
+
+    let listed: serde_json::Value = get(&app.address, "admin/blocked-domains")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(listed[0]["domain"], "throwaway.example");
+
+    let response = delete(&app.address, "admin/blocked-domains/throwaway.example")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(204, response.status().as_u16());
+
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula%40mail.throwaway.example")
+        .await
+        .unwrap();
+    assert_eq!(200, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn blocking_a_domain_twice_is_a_conflict() {
+    let app = spawn_app().await;
+
+    for expected in [200, 409] {
+        let response = post(&app.address, "admin/blocked-domains")
+            .json(&serde_json::json!({ "domain": "throwaway.example" }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(expected, response.status().as_u16());
+    }
+}
+
+#[tokio::test]
+async fn blocking_an_invalid_domain_is_rejected() {
+    let app = spawn_app().await;
+
+    let response = post(&app.address, "admin/blocked-domains")
+        .json(&serde_json::json!({ "domain": "not a domain" }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn unblocking_a_domain_that_is_not_blocked_returns_404() {
+    let app = spawn_app().await;
+
+    let response = delete(&app.address, "admin/blocked-domains/example.com")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(404, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn role_accounts_are_accepted_by_default() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions("name=le%20guin&email=info%40example.com")
+        .await
+        .unwrap();
+
+    assert_eq!(200, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn role_accounts_are_rejected_when_configured() {
+    let app = spawn_app_with(|config| config.email_policy.reject_role_accounts = true).await;
+
+    for email in ["info@example.com", "NoReply+news@example.com"] {
+        assert_email_rejected(&app, email, "role_account").await;
+    }
+}
+
+#[tokio::test]
+async fn subscribe_rejects_domains_that_cannot_receive_mail() {
+    let resolver = Arc::new(StaticResolver::new(["example.com"]));
+    let app = spawn_app_with_mail_domain_resolver(resolver.clone()).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    assert_email_rejected(&app, "ursula@no-mail.example", "domain_cannot_receive_mail").await;
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula%40example.com")
+        .await
+        .unwrap();
+    assert_eq!(200, response.status().as_u16());
+
+    // The rejected domain is answered from the cache.
+    assert_email_rejected(
+        &app,
+        "le_guin@no-mail.example",
+        "domain_cannot_receive_mail",
+    )
+    .await;
+    assert_eq!(resolver.lookups(), 2);
+}
+
+#[tokio::test]
+async fn subscribe_accepts_the_domain_when_the_lookup_times_out() {
+    let resolver = StaticResolver::default().delayed(Duration::from_secs(30));
+    let app = spawn_app_with_mail_domain_resolver(Arc::new(resolver)).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula%40no-mail.example")
+        .await
+        .unwrap();
+
+    assert_eq!(200, response.status().as_u16());
+}
@@ -0,0 +1,208 @@
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use zero2prod::suppression::{is_email_suppressed, rekey_legacy_hashes};
+
+use crate::helpers::{client, create_confirmed_subscriber, delete, spawn_app, TestApp};
+
+async fn subscriber_id(app: &TestApp) -> uuid::Uuid {
+    sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.pool)
+        .await
+        .unwrap()
+        .id
+}
+
+async fn publish_issue(app: &TestApp) {
+    let response = app
+        .create_newsletter(&json!({
+            "title": "Newsletter title",
+            "content": { "html": "<p>Hi</p>", "text": "Hi" }
+        }))
+        .await
+        .unwrap();
+    let body: serde_json::Value = response.json().await.unwrap();
+    let response = app
+        .publish_newsletter(body["id"].as_str().unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+async fn count(app: &TestApp, table: &str) -> i64 {
+    sqlx::query_scalar::<_, i64>(&format!("SELECT count(*) FROM {}", table))
+        .fetch_one(&app.pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn erasure_removes_personal_data_and_keeps_delivery_counts() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let id = subscriber_id(&app).await;
+    app.set_subscriber_tags(&id.to_string(), &["beta"])
+        .await
+        .unwrap();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    publish_issue(&app).await;
+    app.dispatch_all_pending_emails().await;
+    publish_issue(&app).await;
+
+    let response = delete(&app.address, &format!("admin/subscribers/{}", id))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 204);
+    for table in [
+        "subscriptions",
+        "subscription_tokens",
+        "list_memberships",
+        "subscriber_tags",
+        "issue_delivery_queue",
+    ] {
+        assert_eq!(count(&app, table).await, 0, "{} still has rows", table);
+    }
+    let events = sqlx::query!("SELECT subscriber_id, event_type FROM email_events")
+        .fetch_all(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].subscriber_id, None);
+    assert_eq!(events[0].event_type, "delivered");
+    let suppressed = sqlx::query!("SELECT email_hash FROM suppressed_emails")
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(
+        suppressed.email_hash,
+        app.email_hasher.hash("ursula_le_guin@gmail.com")
+    );
+}
+
+#[tokio::test]
+async fn erasing_an_unknown_subscriber_returns_404() {
+    let app = spawn_app().await;
+
+    let response = delete(
+        &app.address,
+        &format!("admin/subscribers/{}", uuid::Uuid::new_v4()),
+    )
+    .send()
+    .await
+    .unwrap();
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn erasure_requires_the_admin_token() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let id = subscriber_id(&app).await;
+
+    let response = client()
+        .delete(format!("{}/admin/subscribers/{}", app.address, id))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(count(&app, "subscriptions").await, 1);
+    assert_eq!(count(&app, "suppressed_emails").await, 0);
+}
+
+#[tokio::test]
+async fn erased_addresses_cannot_be_subscribed_again() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let id = subscriber_id(&app).await;
+    delete(&app.address, &format!("admin/subscribers/{}", id))
+        .send()
+        .await
+        .unwrap();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions("name=le%20guin&email=Ursula_Le_Guin%40gmail.com")
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(count(&app, "subscriptions").await, 0);
+}
+
+#[tokio::test]
+async fn unkeyed_hashes_from_earlier_versions_are_rekeyed() {
+    let app = spawn_app().await;
+    let legacy_hash = hex::encode(Sha256::digest("ursula_le_guin@gmail.com"));
+    sqlx::query!(
+        "INSERT INTO suppressed_emails (email_hash) VALUES ($1)",
+        legacy_hash
+    )
+    .execute(&app.pool)
+    .await
+    .unwrap();
+
+    rekey_legacy_hashes(&app.pool, &app.email_hasher)
+        .await
+        .unwrap();
+
+    let suppressed = sqlx::query!("SELECT email_hash, keyed FROM suppressed_emails")
+        .fetch_all(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(suppressed.len(), 1);
+    assert!(suppressed[0].keyed);
+    assert!(
+        is_email_suppressed(&app.pool, &app.email_hasher, "Ursula_Le_Guin@gmail.com")
+            .await
+            .unwrap()
+    );
+}
+
+#[tokio::test]
+async fn subscribers_can_erase_their_data_from_the_preference_center() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let preferences_url = app.signed_links.preferences_url(
+        subscriber_id(&app).await,
+        chrono::Utc::now() + chrono::Duration::hours(1),
+    );
+    let erase_url = preferences_url.replace("/preferences?", "/preferences/erase?");
+
+    let response = reqwest::Client::new()
+        .post(format!(
+            "{}&",
+            erase_url.replace("?token=", "?token=forged")
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(count(&app, "subscriptions").await, 1);
+
+    let response = reqwest::Client::new()
+        .post(&erase_url)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(count(&app, "subscriptions").await, 0);
+
+    // The link is useless once the subscriber is gone.
+    let response = reqwest::get(&preferences_url).await.unwrap();
+    assert_eq!(response.status().as_u16(), 400);
+}
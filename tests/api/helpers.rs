@@ -1,8 +1,24 @@
+use std::sync::Arc;
+
 use once_cell::sync::Lazy;
 use reqwest::Response;
 use sqlx::{Executor, PgPool};
-use wiremock::MockServer;
-use zero2prod::{configuration::Settings, factory, startup::NewsletterApp, telemetry};
+use wiremock::{
+    matchers::{method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+use zero2prod::{
+    bot_protection::HumanVerifier,
+    configuration::Settings,
+    email_client::EmailClient,
+    factory,
+    issue_delivery_worker::{try_execute_task, ExecutionOutcome},
+    mail_domain::MailDomainResolver,
+    signed_links::SignedLinks,
+    startup::NewsletterApp,
+    suppression::EmailHasher,
+    telemetry,
+};
 
 pub static TRACING: Lazy<()> = Lazy::new(|| {
     if std::env::var("TEST_LOG").is_ok() {
@@ -12,25 +28,107 @@ pub static TRACING: Lazy<()> = Lazy::new(|| {
     };
 });
 
+/// The admin token the test apps are configured with.
+pub const ADMIN_TOKEN: &str = "test-admin-token";
+
 pub fn post(address: &str, path: &str) -> reqwest::RequestBuilder {
-    client().post(format!("{}/{}", address, path))
+    request(reqwest::Method::POST, address, path)
 }
 
 pub fn get(address: &str, path: &str) -> reqwest::RequestBuilder {
-    client().get(format!("{}/{}", address, path))
+    request(reqwest::Method::GET, address, path)
+}
+
+pub fn put(address: &str, path: &str) -> reqwest::RequestBuilder {
+    request(reqwest::Method::PUT, address, path)
+}
+
+pub fn delete(address: &str, path: &str) -> reqwest::RequestBuilder {
+    request(reqwest::Method::DELETE, address, path)
+}
+
+/// Requests to the admin endpoints carry the admin token; tests of the
+/// authentication itself go through `client()` directly.
+fn request(method: reqwest::Method, address: &str, path: &str) -> reqwest::RequestBuilder {
+    let builder = client().request(method, format!("{}/{}", address, path));
+    if path.starts_with("admin/") {
+        builder.bearer_auth(ADMIN_TOKEN)
+    } else {
+        builder
+    }
 }
 
 pub fn client() -> reqwest::Client {
     reqwest::Client::new()
 }
 
+pub async fn create_confirmed_subscriber(app: &TestApp) {
+    create_confirmed_subscriber_on_list(app, "ursula_le_guin@gmail.com", "newsletter").await;
+}
+
+pub async fn create_confirmed_subscriber_on_list(app: &TestApp, email: &str, list: &str) {
+    let body = format!(
+        "name=le%20guin&email={}&list={}",
+        email.replace('@', "%40"),
+        list
+    );
+
+    let _mock = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Create confirmed subscriber")
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    let response = app.post_subscriptions(&body).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let email_request = email_requests.last().unwrap();
+    let confirmation_link = app.get_confirmation_link(email_request);
+    let response = reqwest::Client::new()
+        .post(confirmation_link)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+}
+
 pub struct TestApp {
     pub address: String,
     pub pool: PgPool,
     pub email_server: MockServer,
+    pub email_client: EmailClient,
+    pub signed_links: SignedLinks,
+    pub email_hasher: EmailHasher,
 }
 
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+/// Spawns the app with settings adjusted by `configure`.
+pub async fn spawn_app_with(configure: impl FnOnce(&mut Settings)) -> TestApp {
+    spawn(configure, |app| app).await
+}
+
+/// Spawns the app, checking that subscriber domains can receive mail with
+/// `resolver`.
+pub async fn spawn_app_with_mail_domain_resolver(resolver: Arc<dyn MailDomainResolver>) -> TestApp {
+    spawn(|_| {}, |app| app.with_mail_domain_resolver(resolver)).await
+}
+
+/// Spawns the app, verifying that signups are made by people with
+/// `verifier`.
+pub async fn spawn_app_with_human_verifier(verifier: Arc<dyn HumanVerifier>) -> TestApp {
+    spawn(|_| {}, |app| app.with_human_verifier(verifier)).await
+}
+
+async fn spawn(
+    configure: impl FnOnce(&mut Settings),
+    customize: impl FnOnce(NewsletterApp) -> NewsletterApp,
+) -> TestApp {
     Lazy::force(&TRACING);
 
     let email_server = MockServer::start().await;
@@ -39,12 +137,15 @@ pub async fn spawn_app() -> TestApp {
         let mut config = zero2prod::configuration::get_configuration();
         config.application.port = 0;
         config.email_client.base_url = email_server.uri();
+        config.application.admin_token = ADMIN_TOKEN.to_string().into();
+        configure(&mut config);
         config
     };
 
     let configuration = setup_test_database(configuration).await;
 
     let pg_pool = factory::get_pool_with(&configuration.database).await;
+    let email_client = factory::get_email_client(&configuration.email_client);
 
     let listener = NewsletterApp::bind(&configuration).unwrap();
     let configuration = {
@@ -57,20 +158,30 @@ pub async fn spawn_app() -> TestApp {
         config
     };
 
+    let signed_links = SignedLinks::new(
+        configuration.application.base_url.clone(),
+        configuration.application.hmac_secret.clone(),
+    );
+    let email_hasher = EmailHasher::new(configuration.application.hmac_secret.clone());
+
     let build = NewsletterApp::build_with(configuration, listener)
         .await
+        .map(customize)
         .expect("Failed to build app");
 
     let port = build.port();
 
     let run = build.run().expect("Error running app");
 
-    let _ = tokio::spawn(run);
+    tokio::spawn(run);
 
     TestApp {
         address: format!("http://{}:{}", "127.0.0.1", port),
         pool: pg_pool,
         email_server,
+        email_client,
+        signed_links,
+        email_hasher,
     }
 }
 
@@ -118,4 +229,135 @@ impl TestApp {
             .await?;
         Ok(response)
     }
+
+    pub async fn create_list(&self, slug: &str, name: &str) -> Result<Response, reqwest::Error> {
+        let response = post(&self.address, "admin/lists")
+            .json(&serde_json::json!({ "slug": slug, "name": name }))
+            .send()
+            .await?;
+        Ok(response)
+    }
+
+    pub async fn create_custom_field(
+        &self,
+        body: &serde_json::Value,
+    ) -> Result<Response, reqwest::Error> {
+        let response = post(&self.address, "admin/custom-fields")
+            .json(body)
+            .send()
+            .await?;
+        Ok(response)
+    }
+
+    pub async fn create_newsletter(
+        &self,
+        body: &serde_json::Value,
+    ) -> Result<Response, reqwest::Error> {
+        let response = post(&self.address, "admin/newsletters")
+            .json(body)
+            .send()
+            .await?;
+        Ok(response)
+    }
+
+    pub async fn update_newsletter(
+        &self,
+        id: &str,
+        body: &serde_json::Value,
+    ) -> Result<Response, reqwest::Error> {
+        let response = put(&self.address, &format!("admin/newsletters/{}", id))
+            .json(body)
+            .send()
+            .await?;
+        Ok(response)
+    }
+
+    pub async fn schedule_newsletter(
+        &self,
+        id: &str,
+        body: &serde_json::Value,
+    ) -> Result<Response, reqwest::Error> {
+        let response = put(&self.address, &format!("admin/newsletters/{}/schedule", id))
+            .json(body)
+            .send()
+            .await?;
+        Ok(response)
+    }
+
+    pub async fn publish_newsletter(&self, id: &str) -> Result<Response, reqwest::Error> {
+        let response = post(&self.address, &format!("admin/newsletters/{}/publish", id))
+            .send()
+            .await?;
+        Ok(response)
+    }
+
+    pub async fn publish_newsletter_to_lists(
+        &self,
+        id: &str,
+        lists: &[&str],
+    ) -> Result<Response, reqwest::Error> {
+        self.publish_newsletter_to(id, &serde_json::json!({ "lists": lists }))
+            .await
+    }
+
+    pub async fn publish_newsletter_to(
+        &self,
+        id: &str,
+        audience: &serde_json::Value,
+    ) -> Result<Response, reqwest::Error> {
+        let response = post(&self.address, &format!("admin/newsletters/{}/publish", id))
+            .json(audience)
+            .send()
+            .await?;
+        Ok(response)
+    }
+
+    pub async fn set_subscriber_tags(
+        &self,
+        id: &str,
+        tags: &[&str],
+    ) -> Result<Response, reqwest::Error> {
+        let response = put(&self.address, &format!("admin/subscribers/{}/tags", id))
+            .json(&serde_json::json!({ "tags": tags }))
+            .send()
+            .await?;
+        Ok(response)
+    }
+
+    pub async fn create_draft_newsletter(&self) -> String {
+        let newsletter = serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>"
+            }
+        });
+        let response = self.create_newsletter(&newsletter).await.unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+        let body: serde_json::Value = response.json().await.unwrap();
+        body["id"].as_str().unwrap().to_string()
+    }
+
+    pub async fn dispatch_all_pending_emails(&self) {
+        loop {
+            if let ExecutionOutcome::EmptyQueue =
+                try_execute_task(&self.pool, &self.email_client, &self.signed_links)
+                    .await
+                    .unwrap()
+            {
+                break;
+            }
+        }
+    }
+
+    pub fn get_confirmation_link(&self, email_request: &wiremock::Request) -> String {
+        let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+        let html = body.get("HtmlBody").unwrap().as_str().unwrap();
+        linkify::LinkFinder::new()
+            .links(html)
+            .filter(|l| *l.kind() == linkify::LinkKind::Url)
+            .map(|l| l.as_str().to_owned())
+            .next()
+            .expect("No link found in the email.")
+    }
 }
@@ -0,0 +1,52 @@
+use crate::helpers::{get, spawn_app};
+
+#[tokio::test]
+async fn created_lists_are_listed_next_to_the_default_list() {
+    let app = spawn_app().await;
+
+    let response = app
+        .create_list("engineering-digest", "Engineering digest")
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = get(&app.address, "admin/lists").send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let lists: serde_json::Value = response.json().await.unwrap();
+    let slugs = lists
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|list| list["slug"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(slugs, ["newsletter", "engineering-digest"]);
+}
+
+#[tokio::test]
+async fn creating_a_list_with_a_taken_slug_returns_409() {
+    let app = spawn_app().await;
+
+    let response = app.create_list("newsletter", "Another one").await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 409);
+}
+
+#[tokio::test]
+async fn creating_a_list_with_invalid_data_returns_400() {
+    let app = spawn_app().await;
+    let test_cases = vec![
+        ("Product Updates", "Product updates", "uppercase slug"),
+        ("", "Product updates", "empty slug"),
+        ("product-updates", " ", "empty name"),
+    ];
+
+    for (slug, name, description) in test_cases {
+        let response = app.create_list(slug, name).await.unwrap();
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not fail with 400 Bad Request when the list had an {}.",
+            description
+        );
+    }
+}
@@ -1,5 +1,19 @@
+mod admin_auth;
+mod bot_protection;
 mod confirm_subscription;
+mod custom_fields;
+mod data_export;
+mod email_change;
+mod email_policy;
+mod erasure;
 mod health_check;
 mod helpers;
+mod lists;
+mod merge_tags;
 mod newsletter;
+mod newsletter_schedule;
+mod preferences;
+mod problem_details;
+mod rate_limit;
+mod segments;
 mod subscriptions;
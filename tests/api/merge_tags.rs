@@ -0,0 +1,292 @@
+use serde_json::json;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{client, create_confirmed_subscriber, get, post, spawn_app, TestApp};
+
+async fn mount_email_server(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+}
+
+async fn create_issue(app: &TestApp, html: &str, text: &str) -> String {
+    let response = app
+        .create_newsletter(&json!({
+            "title": "Newsletter title",
+            "content": { "html": html, "text": text }
+        }))
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    body["id"].as_str().unwrap().to_string()
+}
+
+async fn last_email(app: &TestApp) -> serde_json::Value {
+    let requests = app.email_server.received_requests().await.unwrap();
+    serde_json::from_slice(&requests.last().unwrap().body).unwrap()
+}
+
+fn find_link(text: &str) -> String {
+    linkify::LinkFinder::new()
+        .links(text)
+        .find(|l| *l.kind() == linkify::LinkKind::Url)
+        .unwrap()
+        .as_str()
+        .to_string()
+}
+
+#[tokio::test]
+async fn merge_tags_are_rendered_for_each_recipient() {
+    let app = spawn_app().await;
+    app.create_custom_field(&json!({ "key": "company", "label": "Company", "type": "string" }))
+        .await
+        .unwrap();
+    app.create_custom_field(&json!({ "key": "seats", "label": "Seats", "type": "number" }))
+        .await
+        .unwrap();
+    create_confirmed_subscriber(&app).await;
+    sqlx::query!(
+        r#"UPDATE subscriptions SET name = 'Ursula <K.>', custom_fields = '{"seats": 3}'"#
+    )
+    .execute(&app.pool)
+    .await
+    .unwrap();
+    mount_email_server(&app).await;
+
+    let newsletter_issue_id = create_issue(
+        &app,
+        r#"<p>Hi {{ subscriber.name }} ({{subscriber.email}}), {{ subscriber.seats }} seats at [{{ subscriber.company }}].</p><a href="{{ unsubscribe_url }}">Unsubscribe</a>"#,
+        "Hi {{ subscriber.name }}, {{ subscriber.seats }} seats. Unsubscribe: {{ unsubscribe_url }}",
+    )
+    .await;
+    let response = app.publish_newsletter(&newsletter_issue_id).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_emails().await;
+
+    let email = last_email(&app).await;
+    let html = email["HtmlBody"].as_str().unwrap();
+    let text = email["TextBody"].as_str().unwrap();
+    assert!(html.contains("<p>Hi Ursula &lt;K.&gt; (ursula_le_guin@gmail.com), 3 seats at [].</p>"));
+    assert!(html.contains(r#"<a href="http://127.0.0.1:"#));
+    assert!(text.starts_with("Hi Ursula <K.>, 3 seats. Unsubscribe: http://127.0.0.1:"));
+    assert!(!html.contains("{{") && !text.contains("{{"));
+}
+
+#[tokio::test]
+async fn publishing_an_issue_with_unknown_merge_tags_returns_400() {
+    let app = spawn_app().await;
+
+    let newsletter_issue_id = create_issue(
+        &app,
+        "<p>Hi {{ subscriber.first_name }}</p>",
+        "Hi {{ subscriber.first_name }}",
+    )
+    .await;
+
+    let response = app.publish_newsletter(&newsletter_issue_id).await.unwrap();
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("subscriber.first_name"));
+
+    let scheduled_for = chrono::Utc::now() + chrono::Duration::hours(1);
+    let response = app
+        .schedule_newsletter(
+            &newsletter_issue_id,
+            &json!({ "scheduled_for": scheduled_for }),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 400);
+
+    let saved = sqlx::query!("SELECT status FROM newsletter_issues")
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "draft");
+}
+
+#[tokio::test]
+async fn scheduled_issues_cannot_be_edited_to_use_unknown_merge_tags() {
+    let app = spawn_app().await;
+    let newsletter_issue_id = create_issue(&app, "<p>Hi</p>", "Hi").await;
+    let scheduled_for = chrono::Utc::now() + chrono::Duration::hours(1);
+    let response = app
+        .schedule_newsletter(
+            &newsletter_issue_id,
+            &json!({ "scheduled_for": scheduled_for }),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app
+        .update_newsletter(
+            &newsletter_issue_id,
+            &json!({
+                "title": "Newsletter title",
+                "content": {
+                    "html": "<p>Hi {{ subscriber.first_name }}</p>",
+                    "text": "Hi {{ subscriber.first_name }}"
+                }
+            }),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 400);
+
+    let saved = sqlx::query!("SELECT status, text_content FROM newsletter_issues")
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "scheduled");
+    assert_eq!(saved.text_content, "Hi");
+}
+
+#[tokio::test]
+async fn unsubscribe_links_stop_further_deliveries() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    mount_email_server(&app).await;
+
+    let newsletter_issue_id = create_issue(
+        &app,
+        r#"<a href="{{ unsubscribe_url }}">Unsubscribe</a>"#,
+        "Unsubscribe: {{ unsubscribe_url }}",
+    )
+    .await;
+    app.publish_newsletter(&newsletter_issue_id).await.unwrap();
+    app.dispatch_all_pending_emails().await;
+    let email = last_email(&app).await;
+    let unsubscribe_url = find_link(email["TextBody"].as_str().unwrap());
+
+    let response = reqwest::get(&unsubscribe_url).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("<form method=\"post\""));
+
+    let response = reqwest::Client::new()
+        .post(&unsubscribe_url)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let membership = sqlx::query!("SELECT status FROM list_memberships")
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(membership.status, "unsubscribed");
+
+    let newsletter_issue_id = app.create_draft_newsletter().await;
+    app.publish_newsletter(&newsletter_issue_id).await.unwrap();
+    let queued = sqlx::query!("SELECT subscriber_email FROM issue_delivery_queue")
+        .fetch_all(&app.pool)
+        .await
+        .unwrap();
+    assert!(queued.is_empty());
+}
+
+#[tokio::test]
+async fn unsubscribing_cancels_issues_held_for_a_digest() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    mount_email_server(&app).await;
+    let subscriber =
+        sqlx::query!("UPDATE subscriptions SET digest_frequency = 'weekly' RETURNING id")
+            .fetch_one(&app.pool)
+            .await
+            .unwrap();
+    let newsletter_issue_id = app.create_draft_newsletter().await;
+    app.publish_newsletter(&newsletter_issue_id).await.unwrap();
+
+    let response = client()
+        .post(app.signed_links.unsubscribe_url(subscriber.id))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    // A week later.
+    sqlx::query!("UPDATE issue_delivery_queue SET execute_after = now()")
+        .execute(&app.pool)
+        .await
+        .unwrap();
+    let sent_before = app.email_server.received_requests().await.unwrap().len();
+    app.dispatch_all_pending_emails().await;
+    let sent_after = app.email_server.received_requests().await.unwrap().len();
+    assert_eq!(sent_after, sent_before);
+}
+
+#[tokio::test]
+async fn tampered_unsubscribe_links_are_rejected() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.pool)
+        .await
+        .unwrap()
+        .id;
+    let token = app.signed_links.unsubscribe_url(subscriber_id);
+    let (_, signature) = token.rsplit_once('.').unwrap();
+    let forged = format!(
+        "subscriptions/unsubscribe?token={}.0.{}",
+        uuid::Uuid::new_v4(),
+        signature
+    );
+
+    let response = get(&app.address, &forged).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 400);
+    let response = post(&app.address, &forged).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 400);
+
+    let membership = sqlx::query!("SELECT status FROM list_memberships")
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(membership.status, "confirmed");
+}
+
+#[tokio::test]
+async fn test_sends_render_merge_tags_for_the_recipient() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    mount_email_server(&app).await;
+
+    let newsletter_issue_id = create_issue(
+        &app,
+        r#"<p>Hi {{ subscriber.name }}</p><a href="{{ unsubscribe_url }}">Unsubscribe</a>"#,
+        "Hi {{ subscriber.name }} ({{ subscriber.email }})",
+    )
+    .await;
+    for (recipient, greeting) in [
+        ("ursula_le_guin@gmail.com", "Hi le guin"),
+        ("editor@example.com", "Hi Test Subscriber"),
+    ] {
+        let response = post(
+            &app.address,
+            &format!("admin/newsletters/{}/test", newsletter_issue_id),
+        )
+        .json(&json!({ "recipients": [recipient] }))
+        .send()
+        .await
+        .unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+
+        let email = last_email(&app).await;
+        let html = email["HtmlBody"].as_str().unwrap();
+        let text = email["TextBody"].as_str().unwrap();
+        assert!(html.contains(&format!("<p>{greeting}</p>")), "{}", html);
+        assert!(html.contains(r#"<a href="http://127.0.0.1:"#));
+        assert_eq!(text, format!("{greeting} ({recipient})"));
+    }
+}
@@ -1,7 +1,9 @@
 use wiremock::matchers::{any, method, path};
 use wiremock::{Mock, ResponseTemplate};
 
-use crate::helpers::{spawn_app, TestApp};
+use crate::helpers::{
+    create_confirmed_subscriber, create_confirmed_subscriber_on_list, get, post, spawn_app, TestApp,
+};
 
 #[tokio::test]
 async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
@@ -15,22 +17,381 @@ async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
         .mount(&app.email_server)
         .await;
 
+    let newsletter_issue_id = app.create_draft_newsletter().await;
+
+    let response = app
+        .publish_newsletter(&newsletter_issue_id)
+        .await
+        .expect("Failed to send request");
+
+    assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn newsletters_are_delivered_to_confirmed_subscribers_once_published() {
+    let app = spawn_app().await;
+
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_issue_id = app.create_draft_newsletter().await;
+    let response = app
+        .publish_newsletter(&newsletter_issue_id)
+        .await
+        .expect("Failed to send request");
+
+    assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn drafts_are_not_delivered_to_confirmed_subscribers() {
+    let app = spawn_app().await;
+
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    app.create_draft_newsletter().await;
+    app.dispatch_all_pending_emails().await;
+
+    let saved = sqlx::query!("SELECT status, published_at FROM newsletter_issues")
+        .fetch_one(&app.pool)
+        .await
+        .expect("Failed to fetch saved newsletter issue.");
+
+    assert_eq!(saved.status, "draft");
+    assert!(saved.published_at.is_none());
+}
+
+#[tokio::test]
+async fn published_issues_are_only_delivered_to_the_targeted_lists() {
+    let app = spawn_app().await;
+    app.create_list("engineering-digest", "Engineering digest")
+        .await
+        .unwrap();
+    app.create_list("product-updates", "Product updates")
+        .await
+        .unwrap();
+
+    create_confirmed_subscriber_on_list(&app, "grace@example.com", "engineering-digest").await;
+    create_confirmed_subscriber_on_list(&app, "grace@example.com", "product-updates").await;
+    create_confirmed_subscriber_on_list(&app, "ada@example.com", "product-updates").await;
+    create_confirmed_subscriber_on_list(&app, "alan@example.com", "newsletter").await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_issue_id = app.create_draft_newsletter().await;
+    let response = app
+        .publish_newsletter_to_lists(
+            &newsletter_issue_id,
+            &["engineering-digest", "product-updates"],
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let mut recipients = sqlx::query!("SELECT subscriber_email FROM issue_delivery_queue")
+        .fetch_all(&app.pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|task| task.subscriber_email)
+        .collect::<Vec<_>>();
+    recipients.sort();
+    assert_eq!(recipients, ["ada@example.com", "grace@example.com"]);
+
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn publishing_to_an_unknown_list_returns_400_and_keeps_the_draft() {
+    let app = spawn_app().await;
+
+    let newsletter_issue_id = app.create_draft_newsletter().await;
+    for lists in [vec!["does-not-exist"], vec![]] {
+        let response = app
+            .publish_newsletter_to_lists(&newsletter_issue_id, &lists)
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 400);
+    }
+
+    let saved = sqlx::query!("SELECT status FROM newsletter_issues")
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "draft");
+}
+
+#[tokio::test]
+async fn publishing_an_issue_twice_returns_409() {
+    let app = spawn_app().await;
+
+    let newsletter_issue_id = app.create_draft_newsletter().await;
+    app.publish_newsletter(&newsletter_issue_id).await.unwrap();
+    let response = app.publish_newsletter(&newsletter_issue_id).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 409);
+}
+
+#[tokio::test]
+async fn publishing_an_unknown_issue_returns_404() {
+    let app = spawn_app().await;
+
+    let response = app
+        .publish_newsletter(&uuid::Uuid::new_v4().to_string())
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn drafts_can_be_edited_until_published() {
+    let app = spawn_app().await;
+
+    let newsletter_issue_id = app.create_draft_newsletter().await;
+    let edited = serde_json::json!({
+        "title": "Edited title",
+        "content": {
+            "markdown": "Edited body"
+        }
+    });
+
+    let response = app
+        .update_newsletter(&newsletter_issue_id, &edited)
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let saved = sqlx::query!("SELECT title, text_content FROM newsletter_issues")
+        .fetch_one(&app.pool)
+        .await
+        .expect("Failed to fetch saved newsletter issue.");
+    assert_eq!(saved.title, "Edited title");
+    assert_eq!(saved.text_content, "Edited body\n");
+
+    app.publish_newsletter(&newsletter_issue_id).await.unwrap();
+    let response = app
+        .update_newsletter(&newsletter_issue_id, &edited)
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 409);
+}
+
+#[tokio::test]
+async fn preview_returns_the_rendered_html() {
+    let app = spawn_app().await;
+
+    let newsletter_issue_id = app.create_draft_newsletter().await;
+
+    let response = get(
+        &app.address,
+        &format!("admin/newsletters/{}/preview", newsletter_issue_id),
+    )
+    .send()
+    .await
+    .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Type"].to_str().unwrap(),
+        "text/html; charset=utf-8"
+    );
+    let html = response.text().await.unwrap();
+    assert!(html.contains("<p>Newsletter body as HTML</p>"));
+}
+
+#[tokio::test]
+async fn test_send_only_emails_the_listed_recipients() {
+    let app = spawn_app().await;
+
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_issue_id = app.create_draft_newsletter().await;
+    let response = post(
+        &app.address,
+        &format!("admin/newsletters/{}/test", newsletter_issue_id),
+    )
+    .json(&serde_json::json!({
+        "recipients": ["editor@example.com", "reviewer@example.com"]
+    }))
+    .send()
+    .await
+    .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_emails().await;
+
+    let requests = app.email_server.received_requests().await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&requests.last().unwrap().body).unwrap();
+    assert_eq!(body["Subject"], "[TEST] Newsletter title");
+}
+
+#[tokio::test]
+async fn test_send_returns_400_for_invalid_recipients() {
+    let app = spawn_app().await;
+
+    let newsletter_issue_id = app.create_draft_newsletter().await;
+    let test_cases = vec![
+        (serde_json::json!({"recipients": []}), "no recipients"),
+        (
+            serde_json::json!({"recipients": ["not-an-email"]}),
+            "invalid recipient",
+        ),
+    ];
+
+    for (invalid_body, error_message) in test_cases {
+        let response = post(
+            &app.address,
+            &format!("admin/newsletters/{}/test", newsletter_issue_id),
+        )
+        .json(&invalid_body)
+        .send()
+        .await
+        .unwrap();
+
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 Bad Request when payload was {}.",
+            error_message
+        );
+    }
+}
+
+#[tokio::test]
+async fn newsletter_written_in_markdown_is_rendered_and_persisted() {
+    let app = spawn_app().await;
+
+    let markdown = "# Weekly update\n\nRead the [changelog](https://example.com/changelog).";
     let newsletter = serde_json::json!({
         "title": "Newsletter title",
         "content": {
-            "text": "Newsletter",
-            "html": "Newsletter"
+            "markdown": markdown
         }
     });
 
-    let response = reqwest::Client::new()
-        .post(&format!("{}/newsletter", &app.address))
-        .json(&newsletter)
-        .send()
+    let response = app
+        .create_newsletter(&newsletter)
+        .await
+        .expect("Failed to send request");
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let saved =
+        sqlx::query!("SELECT html_content, text_content, markdown_content FROM newsletter_issues")
+            .fetch_one(&app.pool)
+            .await
+            .expect("Failed to fetch saved newsletter issue.");
+
+    assert_eq!(saved.markdown_content.as_deref(), Some(markdown));
+    assert!(saved.html_content.contains("<h1>Weekly update</h1>"));
+    assert!(saved
+        .html_content
+        .contains("<title>Newsletter title</title>"));
+    assert_eq!(
+        saved.text_content,
+        "Weekly update\n\nRead the changelog (https://example.com/changelog).\n"
+    );
+}
+
+#[tokio::test]
+async fn newsletter_html_is_sanitized_before_storage_and_removals_are_reported() {
+    let app = spawn_app().await;
+
+    let newsletter = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "html": r#"<p onclick="steal()">Hi <a href="javascript:alert(1)">there</a></p><script>alert(1)</script>"#,
+            "text": "Hi there"
+        }
+    });
+
+    let response = app
+        .create_newsletter(&newsletter)
         .await
         .expect("Failed to send request");
 
     assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        body["sanitization"],
+        serde_json::json!({
+            "removed_elements": ["script"],
+            "removed_attributes": ["p[onclick]"],
+            "removed_urls": ["javascript:alert(1)"]
+        })
+    );
+
+    let saved = sqlx::query!("SELECT html_content FROM newsletter_issues")
+        .fetch_one(&app.pool)
+        .await
+        .expect("Failed to fetch saved newsletter issue.");
+
+    assert!(saved.html_content.contains("Hi <a"));
+    assert!(!saved.html_content.contains("<script>"));
+    assert!(!saved.html_content.contains("onclick"));
+    assert!(!saved.html_content.contains("javascript:"));
+}
+
+#[tokio::test]
+async fn newsletter_returns_400_for_invalid_content() {
+    let app = spawn_app().await;
+
+    let test_cases = vec![
+        (serde_json::json!({"title": "Title"}), "missing content"),
+        (
+            serde_json::json!({"title": "Title", "content": {"html": "<p>Hi</p>"}}),
+            "missing text",
+        ),
+        (
+            serde_json::json!({"title": "Title", "content": {"html": "<p>Hi</p>", "text": "Hi", "markdown": "Hi"}}),
+            "both markdown and html/text",
+        ),
+        (
+            serde_json::json!({"title": "Title", "content": {"markdown": ""}}),
+            "empty markdown",
+        ),
+    ];
+
+    for (invalid_body, error_message) in test_cases {
+        let response = app
+            .create_newsletter(&invalid_body)
+            .await
+            .expect("Failed to send request");
+
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 Bad Request when payload was {}.",
+            error_message
+        );
+    }
 }
 
 async fn create_unconfirmed_subscribers(app: &TestApp) {
@@ -0,0 +1,370 @@
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+use zero2prod::newsletter_scheduler::{try_publish_due_issue, SchedulingOutcome};
+
+use crate::helpers::{
+    create_confirmed_subscriber, create_confirmed_subscriber_on_list, delete, spawn_app, TestApp,
+};
+
+fn in_one_hour() -> serde_json::Value {
+    let scheduled_for = chrono::Utc::now() + chrono::Duration::hours(1);
+    serde_json::json!({ "scheduled_for": scheduled_for })
+}
+
+async fn make_scheduled_issues_due(app: &TestApp) {
+    sqlx::query!(
+        "UPDATE newsletter_issues SET scheduled_for = now() - interval '1 minute' WHERE status = 'scheduled'"
+    )
+    .execute(&app.pool)
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn scheduling_a_draft_stores_the_publication_time() {
+    let app = spawn_app().await;
+
+    let newsletter_issue_id = app.create_draft_newsletter().await;
+    let response = app
+        .schedule_newsletter(&newsletter_issue_id, &in_one_hour())
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status, scheduled_for FROM newsletter_issues")
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "scheduled");
+    assert!(saved.scheduled_for.is_some());
+}
+
+#[tokio::test]
+async fn scheduling_in_the_past_returns_400() {
+    let app = spawn_app().await;
+
+    let newsletter_issue_id = app.create_draft_newsletter().await;
+    let scheduled_for = chrono::Utc::now() - chrono::Duration::hours(1);
+    let response = app
+        .schedule_newsletter(
+            &newsletter_issue_id,
+            &serde_json::json!({ "scheduled_for": scheduled_for }),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn scheduling_a_published_issue_returns_409() {
+    let app = spawn_app().await;
+
+    let newsletter_issue_id = app.create_draft_newsletter().await;
+    app.publish_newsletter(&newsletter_issue_id).await.unwrap();
+    let response = app
+        .schedule_newsletter(&newsletter_issue_id, &in_one_hour())
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 409);
+}
+
+#[tokio::test]
+async fn cancelling_a_schedule_returns_the_issue_to_draft() {
+    let app = spawn_app().await;
+
+    let newsletter_issue_id = app.create_draft_newsletter().await;
+    app.schedule_newsletter(&newsletter_issue_id, &in_one_hour())
+        .await
+        .unwrap();
+
+    let response = delete(
+        &app.address,
+        &format!("admin/newsletters/{}/schedule", newsletter_issue_id),
+    )
+    .send()
+    .await
+    .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status, scheduled_for FROM newsletter_issues")
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "draft");
+    assert!(saved.scheduled_for.is_none());
+
+    make_scheduled_issues_due(&app).await;
+    assert!(matches!(
+        try_publish_due_issue(&app.pool).await.unwrap(),
+        SchedulingOutcome::NoDueIssue
+    ));
+}
+
+#[tokio::test]
+async fn scheduled_issues_are_not_published_before_they_are_due() {
+    let app = spawn_app().await;
+
+    let newsletter_issue_id = app.create_draft_newsletter().await;
+    app.schedule_newsletter(&newsletter_issue_id, &in_one_hour())
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        try_publish_due_issue(&app.pool).await.unwrap(),
+        SchedulingOutcome::NoDueIssue
+    ));
+}
+
+#[tokio::test]
+async fn due_issues_are_delivered_exactly_once() {
+    let app = spawn_app().await;
+
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_issue_id = app.create_draft_newsletter().await;
+    app.schedule_newsletter(&newsletter_issue_id, &in_one_hour())
+        .await
+        .unwrap();
+    make_scheduled_issues_due(&app).await;
+
+    let (first, second) = tokio::join!(
+        try_publish_due_issue(&app.pool),
+        try_publish_due_issue(&app.pool)
+    );
+    let published = [first.unwrap(), second.unwrap()]
+        .into_iter()
+        .filter(|outcome| matches!(outcome, SchedulingOutcome::IssuePublished(_)))
+        .count();
+    assert_eq!(published, 1);
+    assert!(matches!(
+        try_publish_due_issue(&app.pool).await.unwrap(),
+        SchedulingOutcome::NoDueIssue
+    ));
+
+    app.dispatch_all_pending_emails().await;
+
+    let saved = sqlx::query!("SELECT status, published_at FROM newsletter_issues")
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "published");
+    assert!(saved.published_at.is_some());
+}
+
+#[tokio::test]
+async fn an_issue_that_cannot_be_published_does_not_hold_up_later_ones() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let broken_issue_id = app.create_draft_newsletter().await;
+    app.schedule_newsletter(&broken_issue_id, &in_one_hour())
+        .await
+        .unwrap();
+    let good_issue_id = app.create_draft_newsletter().await;
+    app.schedule_newsletter(&good_issue_id, &in_one_hour())
+        .await
+        .unwrap();
+    // A segment that no longer parses, and is due ahead of the good issue.
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET segment = '{"no_such_condition": true}',
+            scheduled_for = now() - interval '2 minutes'
+        WHERE id = $1
+        "#,
+        broken_issue_id.parse::<uuid::Uuid>().unwrap()
+    )
+    .execute(&app.pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        "UPDATE newsletter_issues SET scheduled_for = now() - interval '1 minute' WHERE id = $1",
+        good_issue_id.parse::<uuid::Uuid>().unwrap()
+    )
+    .execute(&app.pool)
+    .await
+    .unwrap();
+
+    assert!(matches!(
+        try_publish_due_issue(&app.pool).await.unwrap(),
+        SchedulingOutcome::IssueFailed(_)
+    ));
+    assert!(matches!(
+        try_publish_due_issue(&app.pool).await.unwrap(),
+        SchedulingOutcome::IssuePublished(_)
+    ));
+    assert!(matches!(
+        try_publish_due_issue(&app.pool).await.unwrap(),
+        SchedulingOutcome::NoDueIssue
+    ));
+
+    let broken = sqlx::query!(
+        "SELECT status, failure_reason FROM newsletter_issues WHERE id = $1",
+        broken_issue_id.parse::<uuid::Uuid>().unwrap()
+    )
+    .fetch_one(&app.pool)
+    .await
+    .unwrap();
+    assert_eq!(broken.status, "failed");
+    assert!(broken.failure_reason.is_some());
+    let good = sqlx::query!(
+        "SELECT status FROM newsletter_issues WHERE id = $1",
+        good_issue_id.parse::<uuid::Uuid>().unwrap()
+    )
+    .fetch_one(&app.pool)
+    .await
+    .unwrap();
+    assert_eq!(good.status, "published");
+
+    // Once fixed, the failed issue can be scheduled again.
+    let response = app
+        .schedule_newsletter(
+            &broken_issue_id,
+            &serde_json::json!({
+                "scheduled_for": chrono::Utc::now() + chrono::Duration::hours(1),
+                "segment": null
+            }),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn local_delivery_waits_for_the_subscriber_timezone() {
+    let app = spawn_app().await;
+
+    create_confirmed_subscriber(&app).await;
+    sqlx::query!("UPDATE subscriptions SET timezone = 'Asia/Tokyo'")
+        .execute(&app.pool)
+        .await
+        .unwrap();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let deliver_at_local = (chrono::Utc::now() + chrono::Duration::days(2))
+        .naive_utc()
+        .date()
+        .and_hms_opt(9, 0, 0)
+        .unwrap();
+    let newsletter_issue_id = app.create_draft_newsletter().await;
+    let response = app
+        .schedule_newsletter(
+            &newsletter_issue_id,
+            &serde_json::json!({ "deliver_at_local": deliver_at_local }),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    make_scheduled_issues_due(&app).await;
+    assert!(matches!(
+        try_publish_due_issue(&app.pool).await.unwrap(),
+        SchedulingOutcome::IssuePublished(_)
+    ));
+
+    let task = sqlx::query!("SELECT execute_after FROM issue_delivery_queue")
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    let expected = deliver_at_local
+        .and_local_timezone(chrono_tz::Asia::Tokyo)
+        .unwrap()
+        .with_timezone(&chrono::Utc);
+    assert_eq!(task.execute_after, expected);
+
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn schedules_must_specify_exactly_one_delivery_time() {
+    let app = spawn_app().await;
+
+    let newsletter_issue_id = app.create_draft_newsletter().await;
+    let scheduled_for = chrono::Utc::now() + chrono::Duration::hours(1);
+    let deliver_at_local = scheduled_for.naive_utc() + chrono::Duration::days(1);
+    for body in [
+        serde_json::json!({}),
+        serde_json::json!({
+            "scheduled_for": scheduled_for,
+            "deliver_at_local": deliver_at_local
+        }),
+    ] {
+        let response = app
+            .schedule_newsletter(&newsletter_issue_id, &body)
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 400);
+    }
+}
+
+#[tokio::test]
+async fn digest_subscribers_get_issues_at_the_start_of_their_next_period() {
+    use chrono::{Datelike, Timelike};
+
+    let app = spawn_app().await;
+    for email in ["grace@example.com", "ada@example.com", "alan@example.com"] {
+        create_confirmed_subscriber_on_list(&app, email, "newsletter").await;
+    }
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions SET digest_frequency = CASE email
+            WHEN 'ada@example.com' THEN 'daily'
+            WHEN 'alan@example.com' THEN 'weekly'
+            ELSE digest_frequency
+        END
+        "#
+    )
+    .execute(&app.pool)
+    .await
+    .unwrap();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_issue_id = app.create_draft_newsletter().await;
+    let response = app.publish_newsletter(&newsletter_issue_id).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_emails().await;
+
+    // Only the immediate subscriber was mailed; the others wait for the
+    // next midnight and the next Monday, in UTC as they set no timezone.
+    let tasks = sqlx::query!(
+        "SELECT subscriber_email, execute_after FROM issue_delivery_queue ORDER BY subscriber_email"
+    )
+    .fetch_all(&app.pool)
+    .await
+    .unwrap();
+    let now = chrono::Utc::now();
+    assert_eq!(tasks.len(), 2);
+    let (ada, alan) = (&tasks[0], &tasks[1]);
+    assert_eq!(ada.subscriber_email, "ada@example.com");
+    assert_eq!(
+        ada.execute_after.date_naive(),
+        now.date_naive().succ_opt().unwrap()
+    );
+    assert_eq!(ada.execute_after.time().num_seconds_from_midnight(), 0);
+    assert_eq!(alan.subscriber_email, "alan@example.com");
+    assert!(alan.execute_after > now && alan.execute_after <= now + chrono::Duration::weeks(1));
+    assert_eq!(alan.execute_after.weekday(), chrono::Weekday::Mon);
+    assert_eq!(alan.execute_after.time().num_seconds_from_midnight(), 0);
+}
@@ -0,0 +1,265 @@
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use zero2prod::signed_links::LinkPurpose;
+
+use crate::helpers::{create_confirmed_subscriber, post, spawn_app, TestApp};
+
+async fn subscriber_id(app: &TestApp) -> uuid::Uuid {
+    sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.pool)
+        .await
+        .unwrap()
+        .id
+}
+
+async fn preferences_url(app: &TestApp) -> String {
+    let expires_at = chrono::Utc::now() + chrono::Duration::hours(1);
+    app.signed_links
+        .preferences_url(subscriber_id(app).await, expires_at)
+}
+
+async fn memberships(app: &TestApp) -> Vec<(String, String)> {
+    sqlx::query!(
+        r#"
+        SELECT l.slug, m.status
+        FROM list_memberships m
+        JOIN lists l ON l.id = m.list_id
+        ORDER BY l.slug
+        "#
+    )
+    .fetch_all(&app.pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|m| (m.slug, m.status))
+    .collect()
+}
+
+async fn queued_issues(app: &TestApp) -> Vec<uuid::Uuid> {
+    sqlx::query!("SELECT newsletter_issue_id FROM issue_delivery_queue")
+        .fetch_all(&app.pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|task| task.newsletter_issue_id)
+        .collect()
+}
+
+async fn post_preferences(url: &str, body: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(url)
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body(body.to_string())
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn preferences_links_are_only_emailed_to_subscribers() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    for email in ["ursula_le_guin%40gmail.com", "nobody%40example.com"] {
+        let response = post(&app.address, "subscriptions/preferences/link")
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(format!("email={}", email))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    let requests = app.email_server.received_requests().await.unwrap();
+    let link = app.get_confirmation_link(requests.last().unwrap());
+    assert!(link.contains("/subscriptions/preferences?token="));
+    let response = reqwest::get(&link).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains("le guin"));
+}
+
+#[tokio::test]
+async fn preferences_page_shows_the_current_lists() {
+    let app = spawn_app().await;
+    app.create_list("engineering-digest", "Engineering digest")
+        .await
+        .unwrap();
+    create_confirmed_subscriber(&app).await;
+
+    let response = reqwest::get(preferences_url(&app).await).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let page = response.text().await.unwrap();
+    assert!(page.contains(r#"value="newsletter" checked>"#));
+    assert!(page.contains(r#"value="engineering-digest">"#));
+    assert!(page.contains(r#"<option value="immediate" selected>"#));
+}
+
+#[tokio::test]
+async fn subscribers_can_update_their_preferences() {
+    let app = spawn_app().await;
+    app.create_list("engineering-digest", "Engineering digest")
+        .await
+        .unwrap();
+    create_confirmed_subscriber(&app).await;
+
+    let response = post_preferences(
+        &preferences_url(&app).await,
+        "name=Ursula%20K.%20Le%20Guin&digest_frequency=weekly&lists=engineering-digest",
+    )
+    .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains("saved"));
+    let saved = sqlx::query!("SELECT name, digest_frequency FROM subscriptions")
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.name, "Ursula K. Le Guin");
+    assert_eq!(saved.digest_frequency, "weekly");
+    assert_eq!(
+        memberships(&app).await,
+        [
+            ("engineering-digest".to_string(), "confirmed".to_string()),
+            ("newsletter".to_string(), "unsubscribed".to_string())
+        ]
+    );
+    let consent = sqlx::query!(
+        r#"
+        SELECT c.form_id, c.confirmed_at
+        FROM consent_records c
+        JOIN lists l ON l.id = c.list_id
+        WHERE l.slug = 'engineering-digest'
+        "#
+    )
+    .fetch_one(&app.pool)
+    .await
+    .unwrap();
+    assert_eq!(consent.form_id.as_deref(), Some("preferences"));
+    assert!(consent.confirmed_at.is_some());
+}
+
+#[tokio::test]
+async fn leaving_lists_cancels_the_issues_queued_for_them() {
+    let app = spawn_app().await;
+    app.create_list("engineering-digest", "Engineering digest")
+        .await
+        .unwrap();
+    create_confirmed_subscriber(&app).await;
+    let url = preferences_url(&app).await;
+    post_preferences(
+        &url,
+        "name=le%20guin&digest_frequency=weekly&lists=newsletter&lists=engineering-digest",
+    )
+    .await;
+    let newsletter_only = app.create_draft_newsletter().await;
+    app.publish_newsletter_to_lists(&newsletter_only, &["newsletter"])
+        .await
+        .unwrap();
+    let both_lists = app.create_draft_newsletter().await;
+    app.publish_newsletter_to_lists(&both_lists, &["newsletter", "engineering-digest"])
+        .await
+        .unwrap();
+
+    post_preferences(
+        &url,
+        "name=le%20guin&digest_frequency=weekly&lists=engineering-digest",
+    )
+    .await;
+    assert_eq!(queued_issues(&app).await, [both_lists.parse().unwrap()]);
+
+    post_preferences(&url, "unsubscribe_all=on").await;
+    assert!(queued_issues(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn subscribers_can_unsubscribe_from_everything() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let response = post_preferences(&preferences_url(&app).await, "unsubscribe_all=on").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        memberships(&app).await,
+        [("newsletter".to_string(), "unsubscribed".to_string())]
+    );
+}
+
+#[tokio::test]
+async fn invalid_preferences_are_rejected() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let url = preferences_url(&app).await;
+
+    let test_cases = [
+        ("name=&digest_frequency=weekly", "an empty name"),
+        (
+            "name=%3Cscript%3E&digest_frequency=weekly",
+            "a forbidden name",
+        ),
+        (
+            "name=Ursula&digest_frequency=hourly",
+            "an unknown digest frequency",
+        ),
+    ];
+    for (body, description) in test_cases {
+        let response = post_preferences(&url, body).await;
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not fail with 400 Bad Request when the form had {}.",
+            description
+        );
+        assert!(
+            response.headers()["Content-Type"]
+                .to_str()
+                .unwrap()
+                .starts_with("text/html"),
+            "The form was not shown again when it had {}.",
+            description
+        );
+        assert!(response.text().await.unwrap().contains("<form"));
+    }
+
+    let saved = sqlx::query!("SELECT name FROM subscriptions")
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.name, "le guin");
+}
+
+#[tokio::test]
+async fn expired_or_foreign_links_are_rejected() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let id = subscriber_id(&app).await;
+
+    let expired = app
+        .signed_links
+        .preferences_url(id, chrono::Utc::now() - chrono::Duration::minutes(1));
+    let unsubscribe_token = app.signed_links.sign(LinkPurpose::Unsubscribe, id, None);
+    let foreign = format!(
+        "{}/subscriptions/preferences?token={}",
+        app.address, unsubscribe_token
+    );
+
+    for url in [expired, foreign] {
+        let response = reqwest::get(&url).await.unwrap();
+        assert_eq!(response.status().as_u16(), 400);
+        let response = post_preferences(&url, "unsubscribe_all=on").await;
+        assert_eq!(response.status().as_u16(), 400);
+    }
+    assert_eq!(
+        memberships(&app).await,
+        [("newsletter".to_string(), "confirmed".to_string())]
+    );
+}
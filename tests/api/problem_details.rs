@@ -0,0 +1,76 @@
+use crate::helpers::{get, post, spawn_app};
+
+#[tokio::test]
+async fn client_errors_are_problem_details() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_subscriptions("name=le%20guin&email=not-an-email")
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "application/problem+json"
+    );
+    let correlation_id = response.headers()["X-Correlation-Id"]
+        .to_str()
+        .unwrap()
+        .to_string();
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["type"], "about:blank");
+    assert_eq!(problem["title"], "Bad Request");
+    assert_eq!(problem["status"], 400);
+    assert!(problem["detail"].is_string());
+    assert_eq!(problem["instance"], "/subscriptions");
+    assert_eq!(problem["correlation_id"], correlation_id);
+    assert_eq!(problem["errors"][0]["field"], "email");
+}
+
+#[tokio::test]
+async fn malformed_requests_and_unknown_routes_are_problem_details() {
+    let app = spawn_app().await;
+
+    let test_cases = [
+        (
+            post(&app.address, "subscriptions/confirm"),
+            "/subscriptions/confirm",
+            400,
+        ),
+        (
+            get(&app.address, "admin/subscribers/not-a-uuid"),
+            "/admin/subscribers/not-a-uuid",
+            404,
+        ),
+        (get(&app.address, "no/such/route"), "/no/such/route", 404),
+    ];
+    for (request, path, status) in test_cases {
+        let response = request.send().await.unwrap();
+
+        assert_eq!(response.status().as_u16(), status, "{}", path);
+        let problem: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(problem["status"], status);
+        assert_eq!(problem["instance"], path);
+    }
+}
+
+#[tokio::test]
+async fn server_errors_do_not_leak_internal_details() {
+    let app = spawn_app().await;
+    sqlx::query!("ALTER TABLE lists DROP COLUMN name")
+        .execute(&app.pool)
+        .await
+        .unwrap();
+
+    let response = get(&app.address, "admin/lists").send().await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 500);
+    assert!(response.headers().contains_key("X-Correlation-Id"));
+    let body = response.text().await.unwrap();
+    let problem: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(problem["title"], "Internal Server Error");
+    assert!(problem["correlation_id"].is_string());
+    assert!(!body.contains("column"));
+    assert!(!body.contains("query"));
+}
@@ -0,0 +1,213 @@
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+use zero2prod::configuration::{LimitSettings, RateLimitStoreKind, Settings};
+use zero2prod::rate_limit::{
+    sweep_expired_hits, Decision, PostgresStore, RateLimit, RateLimitStore,
+};
+
+use crate::helpers::{get, post, spawn_app_with, TestApp};
+
+fn limit(max_requests: u32) -> LimitSettings {
+    LimitSettings {
+        max_requests,
+        window_secs: 60,
+    }
+}
+
+async fn subscribe(app: &TestApp, email: &str, forwarded_for: Option<&str>) -> reqwest::Response {
+    let mut request =
+        post(&app.address, "subscriptions").form(&[("name", "le guin"), ("email", email)]);
+    if let Some(forwarded_for) = forwarded_for {
+        request = request.header("X-Forwarded-For", forwarded_for);
+    }
+    request.send().await.unwrap()
+}
+
+async fn mount_email_server(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+}
+
+async fn assert_too_many_requests(response: reqwest::Response) {
+    assert_eq!(429, response.status().as_u16());
+    let retry_after = response
+        .headers()
+        .get("Retry-After")
+        .expect("No Retry-After header.")
+        .to_str()
+        .unwrap()
+        .parse::<u64>()
+        .unwrap();
+    assert!((1..=60).contains(&retry_after), "{}", retry_after);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], 429);
+}
+
+#[tokio::test]
+async fn requests_over_the_per_ip_limit_are_rejected() {
+    let app = spawn_app_with(|config| config.rate_limit.per_ip = limit(2)).await;
+
+    for _ in 0..2 {
+        let response = get(&app.address, "subscriptions/form-token")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(200, response.status().as_u16());
+    }
+    let response = get(&app.address, "subscriptions/form-token")
+        .send()
+        .await
+        .unwrap();
+    assert_too_many_requests(response).await;
+
+    // Admin endpoints are not limited.
+    let response = get(&app.address, "admin/lists").send().await.unwrap();
+    assert_eq!(200, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn the_same_email_is_limited_across_client_ips() {
+    let app = spawn_app_with(|config| {
+        config.rate_limit.trusted_proxies = vec!["127.0.0.1".parse().unwrap()];
+        config.rate_limit.per_email = limit(2);
+    })
+    .await;
+    mount_email_server(&app).await;
+
+    for (ip, email) in [
+        ("203.0.113.1", "ursula_le_guin@gmail.com"),
+        ("203.0.113.2", "Ursula_Le_Guin@GMAIL.com"),
+    ] {
+        let response = subscribe(&app, email, Some(ip)).await;
+        assert_eq!(200, response.status().as_u16());
+    }
+    let response = subscribe(&app, "ursula_le_guin@gmail.com", Some("203.0.113.3")).await;
+    assert_too_many_requests(response).await;
+
+    let response = subscribe(&app, "octavia_butler@gmail.com", Some("203.0.113.3")).await;
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(3, app.email_server.received_requests().await.unwrap().len());
+}
+
+fn limit_by_ip(trusted_proxies: &'static [&'static str]) -> impl FnOnce(&mut Settings) {
+    move |config| {
+        config.rate_limit.trusted_proxies = trusted_proxies
+            .iter()
+            .map(|ip| ip.parse().unwrap())
+            .collect();
+        config.rate_limit.per_ip = limit(1);
+    }
+}
+
+#[tokio::test]
+async fn forwarded_ips_are_trusted_only_from_trusted_proxies() {
+    let form_token = |app: &TestApp, forwarded_for: &str| {
+        get(&app.address, "subscriptions/form-token")
+            .header("X-Forwarded-For", forwarded_for)
+            .send()
+    };
+
+    // Behind a trusted proxy, every forwarded client has its own limit.
+    let app = spawn_app_with(limit_by_ip(&["127.0.0.1"])).await;
+    for ip in ["203.0.113.1", "203.0.113.2"] {
+        let response = form_token(&app, ip).await.unwrap();
+        assert_eq!(200, response.status().as_u16());
+    }
+
+    // Otherwise the header is the client's word, and ignored.
+    let app = spawn_app_with(limit_by_ip(&[])).await;
+    let response = form_token(&app, "203.0.113.1").await.unwrap();
+    assert_eq!(200, response.status().as_u16());
+    let response = form_token(&app, "203.0.113.2").await.unwrap();
+    assert_too_many_requests(response).await;
+}
+
+#[tokio::test]
+async fn the_postgres_store_limits_requests() {
+    let app = spawn_app_with(|config| {
+        config.rate_limit.store = RateLimitStoreKind::Postgres;
+        config.rate_limit.per_email = limit(1);
+    })
+    .await;
+    mount_email_server(&app).await;
+
+    let response = subscribe(&app, "ursula_le_guin@gmail.com", None).await;
+    assert_eq!(200, response.status().as_u16());
+    let response = subscribe(&app, "ursula_le_guin@gmail.com", None).await;
+    assert_too_many_requests(response).await;
+
+    let hits = sqlx::query!("SELECT key FROM rate_limit_hits ORDER BY key")
+        .fetch_all(&app.pool)
+        .await
+        .unwrap();
+    let keys = hits.into_iter().map(|hit| hit.key).collect::<Vec<_>>();
+    // Addresses are only stored hashed.
+    assert_eq!(
+        keys,
+        [
+            format!(
+                "email:{}",
+                app.email_hasher.hash("ursula_le_guin@gmail.com")
+            ),
+            "ip:127.0.0.1".to_string(),
+            "ip:127.0.0.1".to_string(),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn expired_hits_of_other_keys_are_left_to_the_sweeper() {
+    let app = spawn_app_with(|config| config.rate_limit.store = RateLimitStoreKind::Postgres).await;
+    let store = PostgresStore::new(app.pool.clone());
+    for key in ["a", "b"] {
+        sqlx::query!(
+            "INSERT INTO rate_limit_hits (key, expires_at) VALUES ($1, now() - interval '1 second')",
+            key
+        )
+        .execute(&app.pool)
+        .await
+        .unwrap();
+    }
+    let limit = RateLimit {
+        max_requests: 1,
+        window: std::time::Duration::from_secs(60),
+    };
+
+    assert_eq!(store.hit("a", &limit).await.unwrap(), Decision::Allowed);
+    let expired = sqlx::query_scalar!(
+        r#"SELECT key AS "key!" FROM rate_limit_hits WHERE expires_at <= now()"#
+    )
+    .fetch_all(&app.pool)
+    .await
+    .unwrap();
+    assert_eq!(expired, ["b"]);
+
+    sweep_expired_hits(&app.pool).await.unwrap();
+    let keys = sqlx::query_scalar!("SELECT key FROM rate_limit_hits")
+        .fetch_all(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(keys, ["a"]);
+}
+
+#[tokio::test]
+async fn rate_limiting_can_be_disabled() {
+    let app = spawn_app_with(|config| {
+        config.rate_limit.enabled = false;
+        config.rate_limit.per_ip = limit(1);
+    })
+    .await;
+
+    for _ in 0..3 {
+        let response = get(&app.address, "subscriptions/form-token")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(200, response.status().as_u16());
+    }
+}
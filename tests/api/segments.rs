@@ -0,0 +1,232 @@
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{create_confirmed_subscriber_on_list, spawn_app, TestApp};
+
+async fn subscriber_id(app: &TestApp, email: &str) -> String {
+    sqlx::query!("SELECT id FROM subscriptions WHERE email = $1", email)
+        .fetch_one(&app.pool)
+        .await
+        .unwrap()
+        .id
+        .to_string()
+}
+
+async fn queued_recipients(app: &TestApp) -> Vec<String> {
+    let mut recipients = sqlx::query!("SELECT subscriber_email FROM issue_delivery_queue")
+        .fetch_all(&app.pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|task| task.subscriber_email)
+        .collect::<Vec<_>>();
+    recipients.sort();
+    recipients
+}
+
+#[tokio::test]
+async fn subscriber_tags_are_normalized_and_replaced() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber_on_list(&app, "grace@example.com", "newsletter").await;
+    let id = subscriber_id(&app, "grace@example.com").await;
+
+    app.set_subscriber_tags(&id, &["alpha"]).await.unwrap();
+    let response = app
+        .set_subscriber_tags(&id, &["Beta", "beta", "early-adopter"])
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let tags = sqlx::query!("SELECT tag FROM subscriber_tags ORDER BY tag")
+        .fetch_all(&app.pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|row| row.tag)
+        .collect::<Vec<_>>();
+    assert_eq!(tags, ["beta", "early-adopter"]);
+}
+
+#[tokio::test]
+async fn tagging_returns_404_for_unknown_subscribers_and_400_for_invalid_tags() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber_on_list(&app, "grace@example.com", "newsletter").await;
+    let id = subscriber_id(&app, "grace@example.com").await;
+
+    let response = app
+        .set_subscriber_tags(&uuid::Uuid::new_v4().to_string(), &["beta"])
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+
+    let response = app.set_subscriber_tags(&id, &["not a tag"]).await.unwrap();
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn publishing_to_a_segment_only_enqueues_matching_subscribers() {
+    let app = spawn_app().await;
+    for email in ["grace@example.com", "ada@example.com", "alan@example.com"] {
+        create_confirmed_subscriber_on_list(&app, email, "newsletter").await;
+    }
+    for email in ["grace@example.com", "ada@example.com"] {
+        let id = subscriber_id(&app, email).await;
+        app.set_subscriber_tags(&id, &["beta"]).await.unwrap();
+    }
+    // Ada confirmed long ago; Grace signed up long ago but only just
+    // confirmed.
+    sqlx::query!(
+        r#"
+        UPDATE list_memberships SET confirmed_at = now() - interval '200 days'
+        WHERE subscriber_id = (SELECT id FROM subscriptions WHERE email = 'ada@example.com')
+        "#
+    )
+    .execute(&app.pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        "UPDATE subscriptions SET subscribed_at = now() - interval '200 days' WHERE email = 'grace@example.com'"
+    )
+    .execute(&app.pool)
+    .await
+    .unwrap();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_issue_id = app.create_draft_newsletter().await;
+    let response = app
+        .publish_newsletter_to(
+            &newsletter_issue_id,
+            &serde_json::json!({
+                "segment": {
+                    "all": [
+                        { "tag": "beta" },
+                        { "status": "confirmed" },
+                        { "subscribed_within_days": 90 }
+                    ]
+                }
+            }),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(queued_recipients(&app).await, ["grace@example.com"]);
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn status_segments_match_the_membership_of_the_delivered_list() {
+    let app = spawn_app().await;
+    for email in ["grace@example.com", "ada@example.com"] {
+        create_confirmed_subscriber_on_list(&app, email, "newsletter").await;
+    }
+    // Ada left the list, but her subscriber record is still confirmed.
+    sqlx::query!(
+        r#"
+        UPDATE list_memberships SET status = 'unsubscribed'
+        WHERE subscriber_id = (SELECT id FROM subscriptions WHERE email = 'ada@example.com')
+        "#
+    )
+    .execute(&app.pool)
+    .await
+    .unwrap();
+
+    let newsletter_issue_id = app.create_draft_newsletter().await;
+    let response = app
+        .publish_newsletter_to(
+            &newsletter_issue_id,
+            &serde_json::json!({ "segment": { "status": "confirmed" } }),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(queued_recipients(&app).await, ["grace@example.com"]);
+}
+
+#[tokio::test]
+async fn segments_can_filter_on_custom_fields() {
+    let app = spawn_app().await;
+    for (email, seats) in [("grace@example.com", 50), ("ada@example.com", 5)] {
+        create_confirmed_subscriber_on_list(&app, email, "newsletter").await;
+        sqlx::query!(
+            "UPDATE subscriptions SET custom_fields = jsonb_build_object('seats', $2::int) WHERE email = $1",
+            email,
+            seats
+        )
+        .execute(&app.pool)
+        .await
+        .unwrap();
+    }
+    create_confirmed_subscriber_on_list(&app, "alan@example.com", "newsletter").await;
+
+    let newsletter_issue_id = app.create_draft_newsletter().await;
+    let response = app
+        .publish_newsletter_to(
+            &newsletter_issue_id,
+            &serde_json::json!({
+                "segment": { "field": { "name": "seats", "op": "gte", "value": 10 } }
+            }),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(queued_recipients(&app).await, ["grace@example.com"]);
+}
+
+#[tokio::test]
+async fn scheduled_issues_keep_their_segment() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber_on_list(&app, "grace@example.com", "newsletter").await;
+    create_confirmed_subscriber_on_list(&app, "ada@example.com", "newsletter").await;
+    let id = subscriber_id(&app, "grace@example.com").await;
+    app.set_subscriber_tags(&id, &["beta"]).await.unwrap();
+
+    let newsletter_issue_id = app.create_draft_newsletter().await;
+    let scheduled_for = chrono::Utc::now() + chrono::Duration::hours(1);
+    let response = app
+        .schedule_newsletter(
+            &newsletter_issue_id,
+            &serde_json::json!({
+                "scheduled_for": scheduled_for,
+                "segment": { "tag": "beta" }
+            }),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.publish_newsletter(&newsletter_issue_id).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(queued_recipients(&app).await, ["grace@example.com"]);
+}
+
+#[tokio::test]
+async fn publishing_with_an_invalid_segment_returns_400() {
+    let app = spawn_app().await;
+
+    let newsletter_issue_id = app.create_draft_newsletter().await;
+    let segments = [
+        serde_json::json!({ "all": [] }),
+        serde_json::json!({ "tag": "beta; DROP TABLE subscriptions" }),
+        serde_json::json!({ "field": { "name": "seats", "op": "gte", "value": true } }),
+    ];
+    for segment in segments {
+        let response = app
+            .publish_newsletter_to(
+                &newsletter_issue_id,
+                &serde_json::json!({ "segment": segment }),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 400);
+    }
+}
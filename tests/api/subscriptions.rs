@@ -3,7 +3,7 @@ use wiremock::{
     Mock, ResponseTemplate,
 };
 
-use crate::helpers::spawn_app;
+use crate::helpers::{create_confirmed_subscriber, get, post, spawn_app, spawn_app_with};
 
 #[tokio::test]
 async fn subscribe_returns_a_200_for_valid_data() {
@@ -32,6 +32,28 @@ async fn subscribe_returns_a_200_for_valid_data() {
     assert_eq!(saved.status, "pending_verification");
 }
 
+#[tokio::test]
+async fn subscribe_stores_the_normalized_name() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    let body = "name=%20%20Ursula%20%09K.%0ALe%20Guin%20&email=ursula_le_guin%40gmail.com";
+    let response = test_app.post_subscriptions(body).await.unwrap();
+    assert_eq!(200, response.status().as_u16());
+
+    let saved = sqlx::query!("SELECT name FROM subscriptions")
+        .fetch_one(&test_app.pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.name, "Ursula K. Le Guin");
+}
+
 #[tokio::test]
 async fn subscribe_returns_400_when_data_is_missing() {
     let test_app = spawn_app().await;
@@ -132,3 +154,402 @@ async fn subscribe_inserts_a_subscription_token_to_db() {
 
     assert_eq!(1, token.len());
 }
+
+#[tokio::test]
+async fn subscribe_persists_the_subscriber_timezone() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&timezone=Asia%2FTokyo";
+
+    let response = test_app.post_subscriptions(body).await.unwrap();
+
+    assert_eq!(200, response.status().as_u16());
+
+    let saved = sqlx::query!("SELECT timezone FROM subscriptions",)
+        .fetch_one(&test_app.pool.clone())
+        .await
+        .expect("Failed to fetch saved subscription.");
+
+    assert_eq!(saved.timezone.as_deref(), Some("Asia/Tokyo"));
+}
+
+#[tokio::test]
+async fn subscribe_returns_400_for_an_unknown_timezone() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&timezone=Mars%2FOlympus_Mons";
+
+    let response = test_app.post_subscriptions(body).await.unwrap();
+
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn subscribe_returns_400_for_an_unknown_list() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&list=does-not-exist";
+
+    let response = test_app.post_subscriptions(body).await.unwrap();
+
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn subscribing_to_several_lists_keeps_a_single_subscriber() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app
+        .create_list("engineering-digest", "Engineering digest")
+        .await
+        .unwrap();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&test_app.email_server)
+        .await;
+
+    for list in ["newsletter", "engineering-digest"] {
+        let body = format!(
+            "name=le%20guin&email=ursula_le_guin%40gmail.com&list={}",
+            list
+        );
+        let response = test_app.post_subscriptions(&body).await.unwrap();
+        assert_eq!(200, response.status().as_u16());
+    }
+
+    let subscribers = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_all(&test_app.pool)
+        .await
+        .unwrap();
+    assert_eq!(subscribers.len(), 1);
+    let memberships = sqlx::query!(
+        r#"
+        SELECT l.slug, m.status
+        FROM list_memberships m
+        JOIN lists l ON l.id = m.list_id
+        ORDER BY l.slug
+        "#
+    )
+    .fetch_all(&test_app.pool)
+    .await
+    .unwrap();
+    let memberships = memberships
+        .iter()
+        .map(|m| (m.slug.as_str(), m.status.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        memberships,
+        [
+            ("engineering-digest", "pending_verification"),
+            ("newsletter", "pending_verification")
+        ]
+    );
+}
+
+#[tokio::test]
+async fn subscribing_with_a_differently_cased_email_keeps_a_single_subscriber() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    for email in ["Ursula%40Example.COM", "%20ursula%40example.com"] {
+        let body = format!("name=le%20guin&email={}", email);
+        let response = test_app.post_subscriptions(&body).await.unwrap();
+        assert_eq!(200, response.status().as_u16());
+    }
+
+    let subscribers = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_all(&test_app.pool)
+        .await
+        .unwrap();
+    assert_eq!(subscribers.len(), 1);
+    assert_eq!(subscribers[0].email, "Ursula@example.com");
+}
+
+#[tokio::test]
+async fn subscribing_again_to_a_confirmed_list_sends_no_email() {
+    // Arrange
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    let response = test_app.post_subscriptions(body).await.unwrap();
+
+    assert_eq!(200, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn subscribe_and_confirm_record_proof_of_consent() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    let body =
+        "name=le%20guin&email=ursula_le_guin%40gmail.com&form=footer&consent_version=2024-07";
+
+    let response = post(&test_app.address, "subscriptions")
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("User-Agent", "Mozilla/5.0 (Earthsea)")
+        // Not from a trusted proxy, so ignored.
+        .header("X-Forwarded-For", "203.0.113.9")
+        .body(body)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(200, response.status().as_u16());
+
+    let email_requests = test_app.email_server.received_requests().await.unwrap();
+    let confirmation_link = test_app.get_confirmation_link(&email_requests[0]);
+    let response = reqwest::Client::new()
+        .post(confirmation_link)
+        .header("X-Forwarded-For", "203.0.113.9")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(200, response.status().as_u16());
+
+    let subscriber = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&test_app.pool)
+        .await
+        .unwrap();
+    let profile: serde_json::Value = get(
+        &test_app.address,
+        &format!("admin/subscribers/{}", subscriber.id),
+    )
+    .send()
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+
+    let consent = &profile["consents"][0];
+    assert_eq!(consent["list"], "newsletter");
+    assert_eq!(consent["source_ip"], "127.0.0.1");
+    assert_eq!(consent["user_agent"], "Mozilla/5.0 (Earthsea)");
+    assert_eq!(consent["form_id"], "footer");
+    assert_eq!(consent["consent_text_version"], "2024-07");
+    assert!(consent["consented_at"].is_string());
+    assert_eq!(consent["confirmation_ip"], "127.0.0.1");
+    assert!(consent["confirmed_at"].is_string());
+}
+
+#[tokio::test]
+async fn consent_records_the_client_ip_forwarded_by_a_trusted_proxy() {
+    let test_app = spawn_app_with(|config| {
+        config.rate_limit.trusted_proxies = vec!["127.0.0.1".parse().unwrap()];
+    })
+    .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    let response = post(&test_app.address, "subscriptions")
+        .header("X-Forwarded-For", "192.0.2.1, 203.0.113.9")
+        .form(&[("name", "le guin"), ("email", "ursula_le_guin@gmail.com")])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(200, response.status().as_u16());
+    let email_requests = test_app.email_server.received_requests().await.unwrap();
+    let confirmation_link = test_app.get_confirmation_link(&email_requests[0]);
+    let response = reqwest::Client::new()
+        .post(confirmation_link)
+        .header("X-Forwarded-For", "203.0.113.10")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(200, response.status().as_u16());
+
+    let consent = sqlx::query!("SELECT source_ip, confirmation_ip FROM consent_records")
+        .fetch_one(&test_app.pool)
+        .await
+        .unwrap();
+    assert_eq!(consent.source_ip.as_deref(), Some("203.0.113.9"));
+    assert_eq!(consent.confirmation_ip.as_deref(), Some("203.0.113.10"));
+}
+
+#[tokio::test]
+async fn subscribe_returns_400_for_an_oversized_consent_version() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    let body = format!(
+        "name=le%20guin&email=ursula_le_guin%40gmail.com&consent_version={}",
+        "v".repeat(257)
+    );
+
+    let response = test_app.post_subscriptions(&body).await.unwrap();
+
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn subscribe_accepts_json_and_answers_in_json() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app
+        .create_custom_field(
+            &serde_json::json!({ "key": "seats", "label": "Seats", "type": "number" }),
+        )
+        .await
+        .unwrap();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    let response = post(&test_app.address, "subscriptions")
+        .json(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com",
+            "timezone": "Asia/Tokyo",
+            "fields": { "seats": 3 }
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(200, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert!(body["message"].is_string());
+
+    let saved = sqlx::query!("SELECT email, timezone, custom_fields FROM subscriptions")
+        .fetch_one(&test_app.pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.email, "ursula_le_guin@gmail.com");
+    assert_eq!(saved.timezone.as_deref(), Some("Asia/Tokyo"));
+    assert_eq!(saved.custom_fields, serde_json::json!({ "seats": 3 }));
+}
+
+#[tokio::test]
+async fn subscribe_reports_the_invalid_field_in_json() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    let test_cases = [
+        (
+            serde_json::json!({ "name": "le guin", "email": "not-an-email" }),
+            "email",
+        ),
+        (
+            serde_json::json!({ "name": "", "email": "ursula_le_guin@gmail.com" }),
+            "name",
+        ),
+        (
+            serde_json::json!({ "name": "le guin", "email": "ursula_le_guin@gmail.com", "list": "nope" }),
+            "list",
+        ),
+        (
+            serde_json::json!({ "name": "le guin", "email": "ursula_le_guin@gmail.com", "fields": [] }),
+            "fields",
+        ),
+    ];
+
+    for (body, field) in test_cases {
+        let response = post(&test_app.address, "subscriptions")
+            .json(&body)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(400, response.status().as_u16());
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["errors"][0]["field"], field);
+        assert!(body["errors"][0]["message"].is_string());
+    }
+}
+
+#[tokio::test]
+async fn form_submissions_get_json_errors_when_they_accept_json() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    let response = post(&test_app.address, "subscriptions")
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("Accept", "application/json")
+        .body("name=le%20guin&email=definitely-not-an-email")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(400, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["errors"][0]["field"], "email");
+}
+
+#[tokio::test]
+async fn subscribe_reports_every_invalid_field_at_once() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    let response = post(&test_app.address, "subscriptions")
+        .json(&serde_json::json!({
+            "name": "<le guin>",
+            "email": "not-an-email",
+            "timezone": "Mars/Olympus_Mons",
+            "consent_version": "v".repeat(257)
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(400, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    let errors = body["errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|error| {
+            assert!(error["message"].is_string());
+            (
+                error["field"].as_str().unwrap(),
+                error["code"].as_str().unwrap(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        errors,
+        [
+            ("name", "forbidden_characters"),
+            ("email", "missing_at_symbol"),
+            ("timezone", "unknown_timezone"),
+            ("consent_version", "too_long")
+        ]
+    );
+}